@@ -0,0 +1,15 @@
+//! Build script: embeds build metadata exposed by `/api/version`
+
+use vergen::EmitBuilder;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Without a git checkout (e.g. Docker builds) vergen emits placeholder values
+    EmitBuilder::builder()
+        .build_timestamp()
+        .git_sha(true)
+        .git_commit_timestamp()
+        .rustc_semver()
+        .emit()?;
+
+    Ok(())
+}
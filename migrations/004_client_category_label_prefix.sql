@@ -0,0 +1,2 @@
+-- Label prefix used to emulate categories on clients without native support (Transmission)
+ALTER TABLE clients ADD COLUMN category_label_prefix TEXT;
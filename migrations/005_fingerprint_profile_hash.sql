@@ -0,0 +1,2 @@
+-- Identifies the fingerprint options (e.g. ignored extensions) a fingerprint was computed with
+ALTER TABLE content_fingerprints ADD COLUMN profile_hash TEXT;
@@ -0,0 +1,17 @@
+-- One summary row per reseed run, complementing the per-torrent history
+CREATE TABLE IF NOT EXISTS reseed_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT,
+    source_clients TEXT NOT NULL,  -- JSON array of client IDs
+    target_client TEXT NOT NULL,
+    target_sites TEXT NOT NULL,  -- JSON array of site IDs
+    total INTEGER NOT NULL DEFAULT 0,
+    success INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    duration_ms INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (task_id) REFERENCES reseed_tasks(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_runs_date ON reseed_runs(created_at DESC);
@@ -0,0 +1,2 @@
+-- Broad content class (video/audio/other) derived from file extensions
+ALTER TABLE content_fingerprints ADD COLUMN media_type TEXT;
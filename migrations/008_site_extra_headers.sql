@@ -0,0 +1,2 @@
+-- Extra HTTP headers sent with torrent downloads (JSON object of name -> value)
+ALTER TABLE sites ADD COLUMN extra_headers TEXT;
@@ -0,0 +1,2 @@
+-- Announce URL pattern for torrents built from a source torrent ({passkey} is substituted)
+ALTER TABLE sites ADD COLUMN announce_url TEXT;
@@ -0,0 +1,9 @@
+-- Matches completed by a task's current run, so an interrupted run can resume
+CREATE TABLE IF NOT EXISTS reseed_progress (
+    task_id TEXT NOT NULL,
+    source_hash TEXT NOT NULL,
+    target_site TEXT NOT NULL,
+    processed_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (task_id, source_hash, target_site),
+    FOREIGN KEY (task_id) REFERENCES reseed_tasks(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Per-site category and save path for torrents added from that site
+ALTER TABLE sites ADD COLUMN default_category TEXT;
+ALTER TABLE sites ADD COLUMN default_save_path TEXT;
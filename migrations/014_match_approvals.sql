@@ -0,0 +1,28 @@
+-- Low-confidence matches from scheduled runs, held for manual approval
+CREATE TABLE IF NOT EXISTS match_approvals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT,
+    source_client TEXT NOT NULL,
+    target_client TEXT NOT NULL,
+    source_hash TEXT NOT NULL,
+    source_name TEXT NOT NULL,
+    source_site TEXT,
+    target_site TEXT NOT NULL,
+    target_torrent_id TEXT,
+    target_hash TEXT NOT NULL,
+    save_path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    confidence REAL NOT NULL,
+    reason TEXT NOT NULL,
+    add_paused INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    resolved_at TEXT,
+    FOREIGN KEY (task_id) REFERENCES reseed_tasks(id) ON DELETE SET NULL,
+    FOREIGN KEY (source_client) REFERENCES clients(id) ON DELETE CASCADE,
+    FOREIGN KEY (target_client) REFERENCES clients(id) ON DELETE CASCADE
+);
+
+-- A match is queued once while it waits, and not again once rejected
+CREATE UNIQUE INDEX IF NOT EXISTS idx_approvals_match
+    ON match_approvals(source_hash, target_site, target_client) WHERE status != 'approved';
@@ -0,0 +1,2 @@
+-- Proxy for requests to a site (torrent downloads and auth checks)
+ALTER TABLE sites ADD COLUMN proxy TEXT;
@@ -0,0 +1,5 @@
+-- Category of failed reseed attempts, so failures can be grouped by cause
+ALTER TABLE reseed_history ADD COLUMN failure_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_history_failure_reason ON reseed_history(failure_reason)
+    WHERE failure_reason IS NOT NULL;
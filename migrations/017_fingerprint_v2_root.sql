@@ -0,0 +1,2 @@
+-- BitTorrent v2 file tree root, for fingerprints computed from v2 torrent files
+ALTER TABLE content_fingerprints ADD COLUMN v2_root TEXT;
@@ -0,0 +1,7 @@
+-- Fingerprints built from the total size alone, when a client did not return
+-- the file list. Earlier versions stored these as one file of the total size.
+ALTER TABLE content_fingerprints ADD COLUMN size_only INTEGER NOT NULL DEFAULT 0;
+
+UPDATE content_fingerprints SET size_only = 1
+WHERE files_hash IS NULL AND media_type IS NULL
+  AND file_count = 1 AND largest_file_size = total_size;
@@ -0,0 +1,3 @@
+-- Login step timeout and retries, separate from the per-request timeout
+ALTER TABLE clients ADD COLUMN login_timeout_secs INTEGER;
+ALTER TABLE clients ADD COLUMN login_retries INTEGER;
@@ -0,0 +1,2 @@
+-- Unit3D RSS key for download URLs, separate from the API passkey
+ALTER TABLE sites ADD COLUMN rsskey TEXT;
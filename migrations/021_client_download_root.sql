@@ -0,0 +1,2 @@
+-- Root directory of a client's downloads, for translating save paths
+ALTER TABLE clients ADD COLUMN download_root TEXT;
@@ -0,0 +1,13 @@
+-- Source torrents successfully reseeded to a target site, so previews can
+-- leave out work that is already done
+CREATE TABLE IF NOT EXISTS cross_seeded (
+    source_hash TEXT NOT NULL,
+    target_site TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (source_hash, target_site)
+);
+
+INSERT OR IGNORE INTO cross_seeded (source_hash, target_site, created_at)
+SELECT lower(info_hash), target_site, MIN(created_at) FROM reseed_history
+WHERE status IN ('success', 'verified_100')
+GROUP BY lower(info_hash), target_site;
@@ -0,0 +1,3 @@
+-- Fingerprints computed with BitTorrent v1 padding files left out. Entries
+-- indexed earlier still count their padding files until re-imported.
+ALTER TABLE content_fingerprints ADD COLUMN padding_excluded INTEGER NOT NULL DEFAULT 0;
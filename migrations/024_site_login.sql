@@ -0,0 +1,3 @@
+-- Site account for logging in when the session cookie expires
+ALTER TABLE sites ADD COLUMN site_username TEXT;
+ALTER TABLE sites ADD COLUMN site_password TEXT;
@@ -0,0 +1,5 @@
+-- When a site's entries were cleared; cleared entries can be restored until
+-- they are purged
+ALTER TABLE torrent_index ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_torrent_deleted ON torrent_index(deleted_at);
@@ -0,0 +1,9 @@
+-- Outcome of the latest requests to each client and site, for the status view
+CREATE TABLE IF NOT EXISTS health_status (
+    kind TEXT NOT NULL,  -- 'client' or 'site'
+    id TEXT NOT NULL,
+    last_ok_at TEXT,
+    last_error TEXT,
+    last_error_at TEXT,
+    PRIMARY KEY (kind, id)
+);
@@ -0,0 +1,10 @@
+-- File lists of index entries, kept with fingerprint.subset_matching to find
+-- packs containing an indexed torrent
+CREATE TABLE IF NOT EXISTS index_files (
+    index_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES torrent_index(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_index_files_entry ON index_files(index_id);
@@ -0,0 +1,26 @@
+-- History status for torrents downloaded from a site outside of a reseed run
+-- SQLite cannot alter a CHECK constraint, so the table is rebuilt
+CREATE TABLE reseed_history_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT,
+    info_hash TEXT NOT NULL,
+    source_site TEXT,
+    target_site TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('success', 'failed', 'skipped', 'site_disabled', 'data_not_found', 'daily_limit_reached', 'verified_100', 'verify_failed', 'downloaded')),
+    message TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    failure_reason TEXT,
+    FOREIGN KEY (task_id) REFERENCES reseed_tasks(id) ON DELETE SET NULL
+);
+
+INSERT INTO reseed_history_new (id, task_id, info_hash, source_site, target_site, status, message, created_at, failure_reason)
+SELECT id, task_id, info_hash, source_site, target_site, status, message, created_at, failure_reason FROM reseed_history;
+
+DROP TABLE reseed_history;
+ALTER TABLE reseed_history_new RENAME TO reseed_history;
+
+CREATE INDEX IF NOT EXISTS idx_history_hash ON reseed_history(info_hash);
+CREATE INDEX IF NOT EXISTS idx_history_date ON reseed_history(created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_history_status ON reseed_history(status);
+CREATE INDEX IF NOT EXISTS idx_history_failure_reason ON reseed_history(failure_reason)
+    WHERE failure_reason IS NOT NULL;
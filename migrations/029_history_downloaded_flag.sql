@@ -0,0 +1,10 @@
+-- Whether the torrent was fetched before the attempt ended, so failures after
+-- the download still count against the site's daily limit
+ALTER TABLE reseed_history ADD COLUMN downloaded INTEGER NOT NULL DEFAULT 0;
+
+UPDATE reseed_history SET downloaded = 1
+WHERE status IN ('success', 'verified_100', 'verify_failed', 'downloaded')
+   OR failure_reason IN ('mismatch', 'add_failed');
+
+CREATE INDEX IF NOT EXISTS idx_history_site_downloaded ON reseed_history(target_site, created_at)
+    WHERE downloaded = 1;
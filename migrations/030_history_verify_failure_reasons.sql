@@ -0,0 +1,9 @@
+-- Verify timeouts and client errors were recorded as mismatches; tell them
+-- apart by the messages they were stored with
+UPDATE reseed_history SET failure_reason = 'verify_timeout'
+WHERE status = 'verify_failed'
+  AND (message LIKE 'Still checking after %' OR message LIKE 'Recheck not started after %');
+
+UPDATE reseed_history SET failure_reason = 'verify_error'
+WHERE status = 'verify_failed'
+  AND (message LIKE 'Recheck failed: %' OR message LIKE 'Could not query torrent: %');
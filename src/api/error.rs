@@ -5,48 +5,115 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::client::ClientError;
+use crate::service::ReseedInProgress;
+use crate::site::templates::TemplateError;
+
+/// Machine-readable error code sent alongside the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadRequest,
+    NotFound,
+    Forbidden,
+    Internal,
+    DatabaseError,
+    ReadOnly,
+    InvalidHeader,
+    InvalidProxy,
+    InvalidIndexFile,
+    InvalidExportFile,
+    InvalidTorrentFile,
+    InvalidPath,
+    ClientNotFound,
+    ClientDisabled,
+    ClientConnectionFailed,
+    ClientAuthFailed,
+    ClientRequestFailed,
+    ClientInvalidResponse,
+    TorrentNotFound,
+    NotSupported,
+    SiteNotFound,
+    SiteDisabled,
+    SiteMissingPasskey,
+    SiteMissingCookie,
+    SiteAuthFailed,
+    SiteRequestFailed,
+    SiteInvalidResponse,
+    DownloadFailed,
+    SiteRateLimited,
+    DailyLimitReached,
+    ApprovalNotFound,
+    ApprovalResolved,
+    ReseedInProgress,
+}
 
 /// Application error type
 #[derive(Debug)]
 pub struct AppError {
     pub status: StatusCode,
+    pub code: ErrorCode,
     pub message: String,
 }
 
+/// JSON body of an error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+    pub code: ErrorCode,
+}
+
 impl AppError {
-    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+    pub fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
         Self {
             status,
+            code,
             message: message.into(),
         }
     }
 
     pub fn bad_request(message: impl Into<String>) -> Self {
-        Self::new(StatusCode::BAD_REQUEST, message)
+        Self::new(StatusCode::BAD_REQUEST, ErrorCode::BadRequest, message)
     }
 
     pub fn not_found(message: impl Into<String>) -> Self {
-        Self::new(StatusCode::NOT_FOUND, message)
+        Self::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, ErrorCode::Forbidden, message)
     }
 
     pub fn internal(message: impl Into<String>) -> Self {
-        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, message)
+    }
+
+    /// Replace the generic code set by the constructor
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let body = Json(json!({
-            "error": self.message,
-        }));
+        let body = ErrorBody {
+            error: self.message,
+            code: self.code,
+        };
 
-        (self.status, body).into_response()
+        (self.status, Json(body)).into_response()
     }
 }
 
 impl From<anyhow::Error> for AppError {
     fn from(err: anyhow::Error) -> Self {
+        if err.is::<ReseedInProgress>() {
+            return Self::new(StatusCode::CONFLICT, ErrorCode::ReseedInProgress, err.to_string());
+        }
         tracing::error!("Internal error: {:?}", err);
         Self::internal(err.to_string())
     }
@@ -55,12 +122,72 @@ impl From<anyhow::Error> for AppError {
 impl From<rusqlite::Error> for AppError {
     fn from(err: rusqlite::Error) -> Self {
         tracing::error!("Database error: {:?}", err);
-        Self::internal(format!("Database error: {}", err))
+        Self::internal(format!("Database error: {}", err)).with_code(ErrorCode::DatabaseError)
+    }
+}
+
+impl From<ClientError> for AppError {
+    fn from(err: ClientError) -> Self {
+        let code = match err {
+            ClientError::ConnectionFailed(_) => ErrorCode::ClientConnectionFailed,
+            ClientError::AuthenticationFailed => ErrorCode::ClientAuthFailed,
+            ClientError::RequestFailed(_) => ErrorCode::ClientRequestFailed,
+            ClientError::InvalidResponse(_) => ErrorCode::ClientInvalidResponse,
+            ClientError::TorrentNotFound(_) => ErrorCode::TorrentNotFound,
+            ClientError::NotSupported => ErrorCode::NotSupported,
+        };
+        Self::bad_request(err.to_string()).with_code(code)
+    }
+}
+
+impl From<TemplateError> for AppError {
+    fn from(err: TemplateError) -> Self {
+        let code = match err {
+            TemplateError::MissingPasskey => ErrorCode::SiteMissingPasskey,
+            TemplateError::MissingCookie => ErrorCode::SiteMissingCookie,
+            TemplateError::AuthenticationFailed => ErrorCode::SiteAuthFailed,
+            TemplateError::DownloadFailed(_) => ErrorCode::DownloadFailed,
+            // Passed on as a 429 so callers back off too
+            TemplateError::RateLimited { .. } => {
+                return Self::new(StatusCode::TOO_MANY_REQUESTS, ErrorCode::SiteRateLimited, err.to_string());
+            }
+            TemplateError::HttpError(_) => ErrorCode::SiteRequestFailed,
+            TemplateError::InvalidResponse(_) => ErrorCode::SiteInvalidResponse,
+            TemplateError::SiteError(_) => ErrorCode::DownloadFailed,
+            TemplateError::LoginNotSupported(_) => ErrorCode::NotSupported,
+        };
+        Self::bad_request(err.to_string()).with_code(code)
     }
 }
 
-impl From<crate::client::ClientError> for AppError {
-    fn from(err: crate::client::ClientError) -> Self {
-        Self::bad_request(err.to_string())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_body_includes_code() {
+        let response = AppError::not_found("Client not found")
+            .with_code(ErrorCode::ClientNotFound)
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "CLIENT_NOT_FOUND");
+        assert_eq!(body["error"], "Client not found");
+    }
+
+    #[test]
+    fn test_reseed_in_progress_is_conflict() {
+        let err = AppError::from(anyhow::Error::from(ReseedInProgress));
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert_eq!(err.code, ErrorCode::ReseedInProgress);
+    }
+
+    #[test]
+    fn test_client_error_codes() {
+        let err = AppError::from(ClientError::AuthenticationFailed);
+        assert_eq!(err.code, ErrorCode::ClientAuthFailed);
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
     }
 }
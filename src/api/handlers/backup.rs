@@ -0,0 +1,216 @@
+//! Database backup handler
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use flate2::{write::GzEncoder, Compression};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use tokio::sync::mpsc;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::{AppError, AppState};
+
+/// Size of the chunks the backup is streamed in
+const BACKUP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks buffered between the compressor and the response
+const BACKUP_CHANNEL_CAPACITY: usize = 16;
+
+/// Compression applied to a downloaded backup
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupCompression {
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct BackupQuery {
+    pub compress: Option<BackupCompression>,
+}
+
+/// Download a snapshot of the database
+///
+/// The snapshot is written to a temporary file only we can read, then
+/// streamed out, through a gzip or zstd compressor when `compress` is set.
+/// It holds every credential, so it is refused in read-only mode.
+#[utoipa::path(
+    get,
+    path = "/backup",
+    tag = "system",
+    params(BackupQuery),
+    responses((status = 200, description = "SQLite database, compressed as requested", content_type = "application/octet-stream", body = Vec<u8>))
+)]
+pub async fn backup(
+    State(state): State<AppState>,
+    Query(query): Query<BackupQuery>,
+) -> Result<Response, AppError> {
+    let path = std::env::temp_dir().join(format!("graft-backup-{}.db", uuid::Uuid::new_v4()));
+
+    let db = state.db.clone();
+    let snapshot = path.clone();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        create_private_file(&snapshot)?;
+        let backed_up = db.backup_to(&snapshot);
+        if backed_up.is_err() {
+            let _ = std::fs::remove_file(&snapshot);
+        }
+        backed_up
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    let (tx, rx) = mpsc::channel(BACKUP_CHANNEL_CAPACITY);
+    let compress = query.compress;
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = write_backup(&path, compress, ChannelWriter(tx.clone())) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                warn!("Failed to stream backup: {}", e);
+            }
+            let _ = tx.blocking_send(Err(e));
+        }
+        if let Err(e) = std::fs::remove_file(&path) {
+            warn!("Failed to remove backup snapshot {:?}: {}", path, e);
+        }
+    });
+
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+
+    let timestamp = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+    let (content_type, filename) = match compress {
+        None => ("application/vnd.sqlite3", format!("graft-backup-{}.db", timestamp)),
+        Some(BackupCompression::Gzip) => ("application/gzip", format!("graft-backup-{}.db.gz", timestamp)),
+        Some(BackupCompression::Zstd) => ("application/zstd", format!("graft-backup-{}.db.zst", timestamp)),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Create an empty file readable by the current user only
+///
+/// `VACUUM INTO` accepts an empty file and keeps its permissions.
+fn create_private_file(path: &Path) -> io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path).map(drop)
+}
+
+/// Copy the snapshot at `path` to `out`, compressing it as requested
+fn write_backup(path: &Path, compress: Option<BackupCompression>, out: ChannelWriter) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut out = BufWriter::with_capacity(BACKUP_CHUNK_SIZE, out);
+
+    match compress {
+        None => {
+            io::copy(&mut file, &mut out)?;
+        }
+        Some(BackupCompression::Gzip) => {
+            let mut encoder = GzEncoder::new(&mut out, Compression::default());
+            io::copy(&mut file, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Some(BackupCompression::Zstd) => {
+            let mut encoder = zstd::Encoder::new(&mut out, 0)?;
+            io::copy(&mut file, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+
+    out.flush()
+}
+
+/// Sends everything written to it as response body chunks
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Backup download was closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_write_backup_compressed() {
+        let path = std::env::temp_dir().join(format!("graft-test-snapshot-{}.db", uuid::Uuid::new_v4()));
+        let data: Vec<u8> = (0..200_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        for compress in [None, Some(BackupCompression::Gzip), Some(BackupCompression::Zstd)] {
+            let (tx, mut rx) = mpsc::channel(1024);
+            write_backup(&path, compress, ChannelWriter(tx)).unwrap();
+
+            let mut written = Vec::new();
+            while let Ok(chunk) = rx.try_recv() {
+                written.extend_from_slice(&chunk.unwrap());
+            }
+
+            let restored = match compress {
+                None => written,
+                Some(BackupCompression::Gzip) => {
+                    let mut out = Vec::new();
+                    flate2::read::GzDecoder::new(written.as_slice()).read_to_end(&mut out).unwrap();
+                    out
+                }
+                Some(BackupCompression::Zstd) => zstd::decode_all(written.as_slice()).unwrap(),
+            };
+            assert_eq!(restored, data, "compress {:?}", compress);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_is_private() {
+        let path = std::env::temp_dir().join(format!("graft-test-snapshot-{}.db", uuid::Uuid::new_v4()));
+        create_private_file(&path).unwrap();
+        // Never reuse an existing file
+        assert!(create_private_file(&path).is_err());
+
+        let db = crate::db::Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        db.backup_to(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
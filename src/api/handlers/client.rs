@@ -1,15 +1,17 @@
 //! Client management handlers
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::api::{AppError, AppState};
-use crate::client::{ClientConfig, ClientType};
+use crate::api::{AppError, AppState, ErrorCode};
+use crate::client::{ClientConfig, ClientType, TorrentFilter};
+use crate::db::repository::{self, HealthKind};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ClientResponse {
     pub id: String,
     pub name: String,
@@ -19,9 +21,13 @@ pub struct ClientResponse {
     pub username: Option<String>,
     pub use_https: bool,
     pub enabled: bool,
+    pub category_label_prefix: Option<String>,
+    pub login_timeout_secs: Option<u64>,
+    pub login_retries: Option<u32>,
+    pub download_root: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateClientRequest {
     pub name: String,
     pub client_type: ClientType,
@@ -31,99 +37,127 @@ pub struct CreateClientRequest {
     pub password: Option<String>,
     #[serde(default)]
     pub use_https: bool,
+    pub category_label_prefix: Option<String>,
+    pub login_timeout_secs: Option<u64>,
+    pub login_retries: Option<u32>,
+    pub download_root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetEnabledRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DetectQuery {
+    /// Store the detected type when it differs from the configured one
+    #[serde(default)]
+    pub update: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DetectResponse {
+    pub configured: ClientType,
+    pub detected: ClientType,
+    pub updated: bool,
+}
+
+impl From<ClientConfig> for ClientResponse {
+    fn from(config: ClientConfig) -> Self {
+        Self {
+            id: config.id,
+            name: config.name,
+            client_type: config.client_type,
+            host: config.host,
+            port: config.port,
+            username: config.username,
+            use_https: config.use_https,
+            enabled: config.enabled,
+            category_label_prefix: config.category_label_prefix,
+            login_timeout_secs: config.login_timeout_secs,
+            login_retries: config.login_retries,
+            download_root: config.download_root,
+        }
+    }
+}
+
+impl CreateClientRequest {
+    fn into_config(self, id: String, enabled: bool) -> ClientConfig {
+        ClientConfig {
+            id,
+            name: self.name,
+            client_type: self.client_type,
+            host: self.host,
+            port: self.port,
+            username: self.username,
+            password: self.password,
+            use_https: self.use_https,
+            category_label_prefix: self.category_label_prefix,
+            enabled,
+            login_timeout_secs: self.login_timeout_secs,
+            login_retries: self.login_retries,
+            download_root: self.download_root.filter(|root| !root.trim().is_empty()),
+        }
+    }
 }
 
 /// List all clients
+#[utoipa::path(
+    get,
+    path = "/clients",
+    tag = "clients",
+    responses((status = 200, body = Vec<ClientResponse>))
+)]
 pub async fn list(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<ClientResponse>>, AppError> {
-    let conn = state.db.conn();
-    let mut stmt = conn.prepare(
-        "SELECT id, name, client_type, host, port, username, use_https, enabled FROM clients ORDER BY name"
-    )?;
-
-    let clients = stmt
-        .query_map([], |row| {
-            let client_type_str: String = row.get(2)?;
-            Ok(ClientResponse {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                client_type: client_type_str.parse().unwrap_or(ClientType::QBittorrent),
-                host: row.get(3)?,
-                port: row.get(4)?,
-                username: row.get(5)?,
-                use_https: row.get::<_, i32>(6)? != 0,
-                enabled: row.get::<_, i32>(7)? != 0,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-
-    Ok(Json(clients))
+    let clients = repository::list_clients(&state.db.conn())?;
+    Ok(Json(clients.into_iter().map(ClientResponse::from).collect()))
 }
 
 /// Get a single client
+#[utoipa::path(
+    get,
+    path = "/clients/{id}",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id")),
+    responses((status = 200, body = ClientResponse))
+)]
 pub async fn get_one(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<ClientResponse>, AppError> {
-    let conn = state.db.conn();
-    let client = conn.query_row(
-        "SELECT id, name, client_type, host, port, username, use_https, enabled FROM clients WHERE id = ?1",
-        [&id],
-        |row| {
-            let client_type_str: String = row.get(2)?;
-            Ok(ClientResponse {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                client_type: client_type_str.parse().unwrap_or(ClientType::QBittorrent),
-                host: row.get(3)?,
-                port: row.get(4)?,
-                username: row.get(5)?,
-                use_https: row.get::<_, i32>(6)? != 0,
-                enabled: row.get::<_, i32>(7)? != 0,
-            })
-        },
-    ).map_err(|_| AppError::not_found("Client not found"))?;
-
-    Ok(Json(client))
+    let client = state.client_config(&id)?;
+    Ok(Json(client.into()))
 }
 
 /// Create a new client
+#[utoipa::path(
+    post,
+    path = "/clients",
+    tag = "clients",
+    request_body = CreateClientRequest,
+    responses((status = 200, body = ClientResponse))
+)]
 pub async fn create(
     State(state): State<AppState>,
     Json(req): Json<CreateClientRequest>,
 ) -> Result<Json<ClientResponse>, AppError> {
-    let id = uuid::Uuid::new_v4().to_string();
+    let config = req.into_config(uuid::Uuid::new_v4().to_string(), true);
+    repository::insert_client(&state.db.conn(), &config)?;
 
-    let conn = state.db.conn();
-    conn.execute(
-        "INSERT INTO clients (id, name, client_type, host, port, username, password_encrypted, use_https, enabled)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 1)",
-        rusqlite::params![
-            id,
-            req.name,
-            req.client_type.to_string(),
-            req.host,
-            req.port,
-            req.username,
-            req.password, // TODO: encrypt
-            req.use_https as i32,
-        ],
-    )?;
-
-    Ok(Json(ClientResponse {
-        id,
-        name: req.name,
-        client_type: req.client_type,
-        host: req.host,
-        port: req.port,
-        username: req.username,
-        use_https: req.use_https,
-        enabled: true,
-    }))
+    Ok(Json(config.into()))
 }
 
 /// Update a client
+#[utoipa::path(
+    put,
+    path = "/clients/{id}",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id")),
+    request_body = CreateClientRequest,
+    responses((status = 200, body = ClientResponse))
+)]
 pub async fn update(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -131,61 +165,84 @@ pub async fn update(
 ) -> Result<Json<ClientResponse>, AppError> {
     let conn = state.db.conn();
 
-    let rows = conn.execute(
-        "UPDATE clients SET name = ?1, client_type = ?2, host = ?3, port = ?4, username = ?5, password_encrypted = ?6, use_https = ?7, updated_at = datetime('now')
-         WHERE id = ?8",
-        rusqlite::params![
-            req.name,
-            req.client_type.to_string(),
-            req.host,
-            req.port,
-            req.username,
-            req.password,
-            req.use_https as i32,
-            id,
-        ],
-    )?;
+    let mut config = req.into_config(id, true);
+    if repository::update_client(&conn, &config)? == 0 {
+        return Err(client_not_found());
+    }
 
-    if rows == 0 {
-        return Err(AppError::not_found("Client not found"));
+    // The update leaves the enabled flag alone
+    if let Some(stored) = repository::get_client(&conn, &config.id)? {
+        config.enabled = stored.enabled;
     }
 
-    Ok(Json(ClientResponse {
-        id,
-        name: req.name,
-        client_type: req.client_type,
-        host: req.host,
-        port: req.port,
-        username: req.username,
-        use_https: req.use_https,
-        enabled: true,
-    }))
+    Ok(Json(config.into()))
+}
+
+/// Enable or disable a client without deleting it
+#[utoipa::path(
+    patch,
+    path = "/clients/{id}/enabled",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id")),
+    request_body = SetEnabledRequest,
+    responses((status = 200, body = Object))
+)]
+pub async fn set_enabled(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<SetEnabledRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if repository::set_client_enabled(&state.db.conn(), &id, req.enabled)? == 0 {
+        return Err(client_not_found());
+    }
+
+    Ok(Json(serde_json::json!({"id": id, "enabled": req.enabled})))
 }
 
 /// Delete a client
+#[utoipa::path(
+    delete,
+    path = "/clients/{id}",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id")),
+    responses((status = 200, body = Object))
+)]
 pub async fn remove(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let conn = state.db.conn();
-    let rows = conn.execute("DELETE FROM clients WHERE id = ?1", [&id])?;
-
-    if rows == 0 {
-        return Err(AppError::not_found("Client not found"));
+    if repository::delete_client(&state.db.conn(), &id)? == 0 {
+        return Err(client_not_found());
     }
 
     Ok(Json(serde_json::json!({"deleted": true})))
 }
 
 /// Test client connection
+#[utoipa::path(
+    post,
+    path = "/clients/{id}/test",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id")),
+    responses((status = 200, body = Object))
+)]
 pub async fn test(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let config = get_client_config(&state, &id)?;
+    let config = state.client_config(&id)?;
     let client = config.create_client();
 
-    match client.test_connection().await {
+    state.reseed_service.throttle().await;
+    let tested = client.test_connection().await;
+    let outcome = match &tested {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Connection failed".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    state.store.record_outcome(HealthKind::Client, &id, &outcome);
+
+    match tested {
         Ok(true) => Ok(Json(serde_json::json!({
             "success": true,
             "message": "Connection successful"
@@ -201,36 +258,84 @@ pub async fn test(
     }
 }
 
-/// Get torrents from a client
+/// Detect the client type by probing the configured address
+#[utoipa::path(
+    post,
+    path = "/clients/{id}/detect",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id"), DetectQuery),
+    responses((status = 200, body = DetectResponse))
+)]
+pub async fn detect(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DetectQuery>,
+) -> Result<Json<DetectResponse>, AppError> {
+    let config = state.client_config(&id)?;
+    let detected = config.detect_type().await?;
+
+    let updated = query.update && detected != config.client_type;
+    if updated {
+        repository::set_client_type(&state.db.conn(), &id, detected)?;
+    }
+
+    Ok(Json(DetectResponse {
+        configured: config.client_type,
+        detected,
+        updated,
+    }))
+}
+
+/// Get torrents from a client, optionally filtered by `category` and `tag`
+#[utoipa::path(
+    get,
+    path = "/clients/{id}/torrents",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id"), TorrentFilter),
+    responses((status = 200, body = Vec<crate::client::TorrentInfo>))
+)]
 pub async fn torrents(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    Query(filter): Query<TorrentFilter>,
 ) -> Result<Json<Vec<crate::client::TorrentInfo>>, AppError> {
-    let config = get_client_config(&state, &id)?;
+    let config = state.client_config(&id)?;
     let client = config.create_client();
 
-    let torrents = client.get_torrents().await?;
+    let torrents = client.get_torrents_filtered(&filter).await?;
     Ok(Json(torrents))
 }
 
-/// Helper to get client config from database
-fn get_client_config(state: &AppState, id: &str) -> Result<ClientConfig, AppError> {
-    let conn = state.db.conn();
-    conn.query_row(
-        "SELECT id, name, client_type, host, port, username, password_encrypted, use_https FROM clients WHERE id = ?1",
-        [id],
-        |row| {
-            let client_type_str: String = row.get(2)?;
-            Ok(ClientConfig {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                client_type: client_type_str.parse().unwrap_or(ClientType::QBittorrent),
-                host: row.get(3)?,
-                port: row.get(4)?,
-                username: row.get(5)?,
-                password: row.get(6)?,
-                use_https: row.get::<_, i32>(7)? != 0,
-            })
-        },
-    ).map_err(|_| AppError::not_found("Client not found"))
+/// Get a single torrent with its files and trackers
+#[utoipa::path(
+    get,
+    path = "/clients/{id}/torrents/{hash}",
+    tag = "clients",
+    params(("id" = String, Path, description = "Client id"), ("hash" = String, Path, description = "Info hash")),
+    responses((status = 200, body = crate::client::TorrentInfo))
+)]
+pub async fn torrent_detail(
+    State(state): State<AppState>,
+    Path((id, hash)): Path<(String, String)>,
+) -> Result<Json<crate::client::TorrentInfo>, AppError> {
+    let config = state.client_config(&id)?;
+    let client = config.create_client();
+
+    let mut torrent = client
+        .get_torrent(&hash)
+        .await?
+        .ok_or_else(|| AppError::not_found("Torrent not found").with_code(ErrorCode::TorrentNotFound))?;
+
+    if torrent.files.is_empty() {
+        torrent.files = client.get_torrent_files(&torrent.hash).await?;
+    }
+    if torrent.trackers.is_empty() {
+        torrent.trackers = client.get_torrent_trackers(&torrent.hash).await?;
+    }
+
+    Ok(Json(torrent))
+}
+
+fn client_not_found() -> AppError {
+    AppError::not_found("Client not found").with_code(ErrorCode::ClientNotFound)
 }
@@ -0,0 +1,49 @@
+//! Fingerprint diagnostics handlers
+
+use axum::{
+    extract::{Multipart, State},
+    Json,
+};
+
+use crate::api::{AppError, AppState, ErrorCode};
+use crate::service::FingerprintAnalysis;
+use crate::utils::torrent;
+
+/// Fingerprint an uploaded `.torrent` file and match it against the index
+///
+/// Expects a multipart body with the torrent in a `file` field. Nothing is
+/// stored or added to a client.
+#[utoipa::path(
+    post,
+    path = "/fingerprint/analyze",
+    tag = "fingerprint",
+    request_body(content = String, content_type = "multipart/form-data", description = "Form with the torrent file in a `file` field"),
+    responses((status = 200, body = FingerprintAnalysis))
+)]
+pub async fn analyze(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<FingerprintAnalysis>, AppError> {
+    let mut bytes = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::bad_request(format!("Invalid upload: {}", e)))?
+    {
+        if field.name() == Some("file") {
+            bytes = Some(
+                field
+                    .bytes()
+                    .await
+                    .map_err(|e| AppError::bad_request(format!("Invalid upload: {}", e)))?,
+            );
+            break;
+        }
+    }
+    let bytes = bytes.ok_or_else(|| AppError::bad_request("Missing 'file' field"))?;
+
+    let contents = torrent::read_contents(&bytes)
+        .map_err(|e| AppError::bad_request(format!("{:#}", e)).with_code(ErrorCode::InvalidTorrentFile))?;
+
+    Ok(Json(state.index_service.analyze(&contents)?))
+}
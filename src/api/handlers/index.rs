@@ -1,15 +1,113 @@
 //! Index management handlers
 
 use axum::{
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::api::{AppError, AppState};
-use crate::client::{ClientConfig, ClientType};
-use crate::service::{ImportResult, IndexStats};
+use crate::api::{AppError, AppState, ErrorCode};
+use crate::client::{parse_torrent_export, BitTorrentClient, TorrentFilter};
+use crate::db::repository::{self, HealthKind};
+use crate::service::{DuplicateSet, ImportFilter, ImportResult, IndexExportEntry, IndexStats, PackSuggestion};
+
+/// Number of index rows fetched per chunk when streaming an export
+const EXPORT_BATCH_SIZE: usize = 500;
+
+/// Portable index file format
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexFileFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Outcome of importing one client in `import_all`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClientImportOutcome {
+    pub result: Option<ImportResult>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct IndexFileQuery {
+    #[serde(default)]
+    pub format: IndexFileFormat,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: IndexFileFormat,
+    /// Leave out names, save paths and source clients, for sharing
+    #[serde(default)]
+    pub scrub: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ImportQuery {
+    /// Only import torrents added after this RFC 3339 timestamp
+    pub added_after: Option<DateTime<Utc>>,
+    /// With `added_after`, also leave out torrents without an added date
+    #[serde(default)]
+    pub exclude_undated: bool,
+    /// Only import torrents in this category
+    pub category: Option<String>,
+    /// Only import torrents with this tag
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TorrentListQuery {
+    /// Client the list was exported from, recorded as the entries' source
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportDirRequest {
+    /// Directory on the server holding `.torrent` files
+    #[schema(value_type = String)]
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PruneQuery {
+    /// Prune entries indexed more than this many days ago
+    pub older_than: Option<u32>,
+    /// Prune entries whose torrent is gone from the client it was imported from
+    #[serde(default)]
+    pub check_clients: bool,
+}
+
+/// Entries pruned (or, for a dry run, that would be pruned)
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct PruneReport {
+    /// Indexed before the `older_than` cutoff
+    pub older: usize,
+    /// No longer in their source client. In a dry run, entries can be
+    /// counted both here and in `older`.
+    pub missing: usize,
+    pub dry_run: bool,
+    /// Clients that could not be checked, with the error
+    pub failed_clients: HashMap<String, String>,
+}
 
 /// Get index statistics
+#[utoipa::path(
+    get,
+    path = "/index/stats",
+    tag = "index",
+    responses((status = 200, body = IndexStats))
+)]
 pub async fn stats(
     State(state): State<AppState>,
 ) -> Result<Json<IndexStats>, AppError> {
@@ -17,22 +115,186 @@ pub async fn stats(
     Ok(Json(stats))
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DuplicatesQuery {
+    /// Only look for duplicates on this site; otherwise every site is
+    /// searched, each on its own
+    pub site_id: Option<String>,
+}
+
+/// Find content indexed more than once on a site under different info hashes
+#[utoipa::path(
+    get,
+    path = "/index/duplicates",
+    tag = "index",
+    params(DuplicatesQuery),
+    responses((status = 200, body = Vec<DuplicateSet>))
+)]
+pub async fn duplicates(
+    State(state): State<AppState>,
+    Query(query): Query<DuplicatesQuery>,
+) -> Result<Json<Vec<DuplicateSet>>, AppError> {
+    let duplicates = state.index_service.find_duplicates(query.site_id.as_deref())?;
+    Ok(Json(duplicates))
+}
+
+/// Suggest indexed packs containing indexed torrents (subset matching)
+#[utoipa::path(
+    get,
+    path = "/index/suggestions",
+    tag = "index",
+    params(DuplicatesQuery),
+    responses((status = 200, body = Vec<PackSuggestion>))
+)]
+pub async fn suggestions(
+    State(state): State<AppState>,
+    Query(query): Query<DuplicatesQuery>,
+) -> Result<Json<Vec<PackSuggestion>>, AppError> {
+    if !state.index_service.subset_matching() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::NotSupported,
+            "Subset matching is disabled (fingerprint.subset_matching)",
+        ));
+    }
+
+    let suggestions = state.index_service.pack_suggestions(query.site_id.as_deref())?;
+    Ok(Json(suggestions))
+}
+
 /// Import torrents from a client
+#[utoipa::path(
+    post,
+    path = "/index/import/{client_id}",
+    tag = "index",
+    params(("client_id" = String, Path, description = "Client id"), ImportQuery),
+    responses((status = 200, body = ImportResult))
+)]
 pub async fn import(
     State(state): State<AppState>,
     Path(client_id): Path<String>,
+    Query(query): Query<ImportQuery>,
 ) -> Result<Json<ImportResult>, AppError> {
     // Get client config
-    let config = get_client_config(&state, &client_id)?;
+    let config = state.enabled_client_config(&client_id)?;
     let client = config.create_client();
+    let fallbacks = state.detail_fallback_clients(&client_id)?;
+    let fallback_refs: Vec<&dyn BitTorrentClient> = fallbacks.iter().map(|c| c.as_ref()).collect();
+
+    let filter = ImportFilter {
+        added_after: query.added_after,
+        exclude_undated: query.exclude_undated,
+        torrents: TorrentFilter {
+            category: query.category,
+            tag: query.tag,
+        },
+    };
 
     // Run import
-    let result = state.index_service.import_from_client(client.as_ref(), &client_id).await?;
+    let result = state.index_service
+        .import_from_client(client.as_ref(), &fallback_refs, &client_id, &filter)
+        .await;
+    state.store.record_outcome(HealthKind::Client, &client_id, &result);
+    let result = result?;
 
     Ok(Json(result))
 }
 
+/// Index the `.torrent` files in a server-side directory
+#[utoipa::path(
+    post,
+    path = "/index/import-dir",
+    tag = "index",
+    request_body = ImportDirRequest,
+    responses((status = 200, body = ImportResult))
+)]
+pub async fn import_dir(
+    State(state): State<AppState>,
+    Json(req): Json<ImportDirRequest>,
+) -> Result<Json<ImportResult>, AppError> {
+    if !tokio::fs::metadata(&req.path).await.is_ok_and(|m| m.is_dir()) {
+        return Err(AppError::bad_request(format!("Not a directory: {}", req.path.display()))
+            .with_code(ErrorCode::InvalidPath));
+    }
+
+    let result = state.index_service.import_from_dir(&req.path).await?;
+    Ok(Json(result))
+}
+
+/// Index a saved qBittorrent torrent list (`/api/v2/torrents/info` output)
+#[utoipa::path(
+    post,
+    path = "/index/import-qb-export",
+    tag = "index",
+    params(TorrentListQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses((status = 200, body = ImportResult))
+)]
+pub async fn import_qb_export(
+    State(state): State<AppState>,
+    Query(query): Query<TorrentListQuery>,
+    body: Bytes,
+) -> Result<Json<ImportResult>, AppError> {
+    let torrents = parse_torrent_export(&body).map_err(|e| {
+        AppError::bad_request(format!("Invalid qBittorrent export: {}", e)).with_code(ErrorCode::InvalidExportFile)
+    })?;
+
+    let client_id = query.client_id.filter(|id| !id.is_empty());
+    let result = state.index_service.import_torrent_list(&torrents, client_id.as_deref())?;
+    Ok(Json(result))
+}
+
+/// Import torrents from every enabled client
+///
+/// A client that fails to import is reported in its entry and does not
+/// stop the others.
+#[utoipa::path(
+    post,
+    path = "/index/import-all",
+    tag = "index",
+    responses((status = 200, body = HashMap<String, ClientImportOutcome>))
+)]
+pub async fn import_all(
+    State(state): State<AppState>,
+) -> Result<Json<HashMap<String, ClientImportOutcome>>, AppError> {
+    let configs = repository::list_enabled_clients(&state.db.conn())?;
+    let mut outcomes = HashMap::new();
+
+    for config in configs {
+        let client = config.create_client();
+        let fallbacks = state.detail_fallback_clients(&config.id)?;
+        let fallback_refs: Vec<&dyn BitTorrentClient> = fallbacks.iter().map(|c| c.as_ref()).collect();
+        let imported = state
+            .index_service
+            .import_from_client(client.as_ref(), &fallback_refs, &config.id, &ImportFilter::default())
+            .await;
+        state.store.record_outcome(HealthKind::Client, &config.id, &imported);
+        let outcome = match imported {
+            Ok(result) => ClientImportOutcome {
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => {
+                warn!("Import from client {} failed: {:#}", config.name, e);
+                ClientImportOutcome {
+                    result: None,
+                    error: Some(format!("{:#}", e)),
+                }
+            }
+        };
+        outcomes.insert(config.id, outcome);
+    }
+
+    Ok(Json(outcomes))
+}
+
 /// Clear all index entries
+#[utoipa::path(
+    delete,
+    path = "/index",
+    tag = "index",
+    responses((status = 200, body = Object))
+)]
 pub async fn clear_all(
     State(state): State<AppState>,
 ) -> Result<Json<serde_json::Value>, AppError> {
@@ -41,32 +303,282 @@ pub async fn clear_all(
 }
 
 /// Clear index entries for a specific site
+#[utoipa::path(
+    delete,
+    path = "/index/{site_id}",
+    tag = "index",
+    params(("site_id" = String, Path, description = "Site id")),
+    responses((status = 200, body = Object))
+)]
 pub async fn clear_site(
     State(state): State<AppState>,
     Path(site_id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    state.index_service.clear_by_site(&site_id)?;
-    Ok(Json(serde_json::json!({"cleared": true, "site_id": site_id})))
-}
-
-/// Helper to get client config from database
-fn get_client_config(state: &AppState, id: &str) -> Result<ClientConfig, AppError> {
-    let conn = state.db.conn();
-    conn.query_row(
-        "SELECT id, name, client_type, host, port, username, password_encrypted, use_https FROM clients WHERE id = ?1",
-        [id],
-        |row| {
-            let client_type_str: String = row.get(2)?;
-            Ok(ClientConfig {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                client_type: client_type_str.parse().unwrap_or(ClientType::QBittorrent),
-                host: row.get(3)?,
-                port: row.get(4)?,
-                username: row.get(5)?,
-                password: row.get(6)?,
-                use_https: row.get::<_, i32>(7)? != 0,
-            })
-        },
-    ).map_err(|_| AppError::not_found("Client not found"))
+    let entries = state.index_service.clear_by_site(&site_id)?;
+    Ok(Json(serde_json::json!({"cleared": true, "site_id": site_id, "entries": entries})))
+}
+
+/// Restore a site's cleared index entries
+#[utoipa::path(
+    post,
+    path = "/index/{site_id}/restore",
+    tag = "index",
+    params(("site_id" = String, Path, description = "Site id")),
+    responses((status = 200, body = Object))
+)]
+pub async fn restore_site(
+    State(state): State<AppState>,
+    Path(site_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let restored = state.index_service.restore_site(&site_id)?;
+    Ok(Json(serde_json::json!({"site_id": site_id, "restored": restored})))
+}
+
+/// Report how many entries a prune would remove, without removing them
+#[utoipa::path(
+    get,
+    path = "/index/prune",
+    tag = "index",
+    params(PruneQuery),
+    responses((status = 200, body = PruneReport))
+)]
+pub async fn prune_preview(
+    State(state): State<AppState>,
+    Query(query): Query<PruneQuery>,
+) -> Result<Json<PruneReport>, AppError> {
+    Ok(Json(prune_index(&state, &query, true).await?))
+}
+
+/// Remove stale index entries
+#[utoipa::path(
+    post,
+    path = "/index/prune",
+    tag = "index",
+    params(PruneQuery),
+    responses((status = 200, body = PruneReport))
+)]
+pub async fn prune(
+    State(state): State<AppState>,
+    Query(query): Query<PruneQuery>,
+) -> Result<Json<PruneReport>, AppError> {
+    Ok(Json(prune_index(&state, &query, false).await?))
+}
+
+async fn prune_index(state: &AppState, query: &PruneQuery, dry_run: bool) -> Result<PruneReport, AppError> {
+    if query.older_than.is_none() && !query.check_clients {
+        return Err(AppError::bad_request("Set older_than, check_clients or both"));
+    }
+
+    let mut report = PruneReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    if let Some(days) = query.older_than {
+        let before = Utc::now() - Duration::days(days.into());
+        report.older = if dry_run {
+            state.index_service.count_prunable(before)?
+        } else {
+            state.index_service.prune(before)?
+        };
+    }
+
+    if query.check_clients {
+        let configs = repository::list_enabled_clients(&state.db.conn())?;
+        for config in configs {
+            let client = config.create_client();
+            let missing = match state.index_service.missing_from_client(client.as_ref(), &config.id).await {
+                Ok(missing) => missing,
+                Err(e) => {
+                    // Without the client's list we cannot tell what is gone
+                    warn!("Skipping prune check for client {}: {:#}", config.name, e);
+                    report.failed_clients.insert(config.id, format!("{:#}", e));
+                    continue;
+                }
+            };
+
+            report.missing += if dry_run {
+                missing.len()
+            } else {
+                state.index_service.prune_missing(&config.id, &missing)?
+            };
+        }
+    }
+
+    Ok(report)
+}
+
+/// Export the full index as a portable JSON or CSV file
+///
+/// Rows are read in batches and streamed to the client, so the whole index
+/// is never buffered in memory.
+#[utoipa::path(
+    get,
+    path = "/index/export",
+    tag = "index",
+    params(ExportQuery),
+    responses((status = 200, description = "Index entries as JSON or CSV", content((Vec<IndexExportEntry> = "application/json"), (String = "text/csv"))))
+)]
+pub async fn export(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let format = query.format;
+    let scrub = query.scrub;
+    let index_service = state.index_service.clone();
+
+    // (after_id, first chunk, finished)
+    let stream = futures::stream::unfold((0i64, true, false), move |(after_id, first, finished)| {
+        let index_service = index_service.clone();
+        async move {
+            if finished {
+                return None;
+            }
+
+            let (entries, last_id) = match index_service.export_batch(after_id, EXPORT_BATCH_SIZE, scrub) {
+                Ok(batch) => batch,
+                Err(e) => return Some((Err(e), (after_id, first, true))),
+            };
+            let last = entries.len() < EXPORT_BATCH_SIZE;
+
+            let chunk = match format {
+                IndexFileFormat::Json => encode_json_chunk(&entries, first, last),
+                IndexFileFormat::Csv => encode_csv_chunk(&entries, first),
+            };
+
+            Some((chunk.map(Bytes::from), (last_id, false, last)))
+        }
+    });
+
+    let (content_type, filename) = match format {
+        IndexFileFormat::Json => ("application/json", "graft-index.json"),
+        IndexFileFormat::Csv => ("text/csv", "graft-index.csv"),
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+/// Import an index file previously produced by `export`
+#[utoipa::path(
+    post,
+    path = "/index/import-file",
+    tag = "index",
+    params(IndexFileQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses((status = 200, body = ImportResult))
+)]
+pub async fn import_file(
+    State(state): State<AppState>,
+    Query(query): Query<IndexFileQuery>,
+    body: Bytes,
+) -> Result<Json<ImportResult>, AppError> {
+    let entries: Vec<IndexExportEntry> = match query.format {
+        IndexFileFormat::Json => serde_json::from_slice(&body)
+            .map_err(|e| AppError::bad_request(format!("Invalid JSON index file: {}", e)).with_code(ErrorCode::InvalidIndexFile))?,
+        IndexFileFormat::Csv => csv::Reader::from_reader(body.as_ref())
+            .deserialize()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| AppError::bad_request(format!("Invalid CSV index file: {}", e)).with_code(ErrorCode::InvalidIndexFile))?,
+    };
+
+    let result = state.index_service.import_entries(&entries)?;
+    Ok(Json(result))
+}
+
+/// Encode a batch of entries as part of a streamed JSON array
+fn encode_json_chunk(entries: &[IndexExportEntry], first: bool, last: bool) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    if first {
+        buf.push(b'[');
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if !first || i > 0 {
+            buf.push(b',');
+        }
+        serde_json::to_writer(&mut buf, entry)?;
+    }
+
+    if last {
+        buf.push(b']');
+    }
+
+    Ok(buf)
+}
+
+/// Encode a batch of entries as CSV rows, with a header on the first batch
+fn encode_csv_chunk(entries: &[IndexExportEntry], first: bool) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(first)
+        .from_writer(Vec::new());
+
+    for entry in entries {
+        writer.serialize(entry)?;
+    }
+
+    // The header is only emitted alongside the first record
+    if first && entries.is_empty() {
+        writer.write_record([
+            "info_hash", "site_id", "torrent_id", "name", "save_path", "source_client",
+            "total_size", "file_count", "largest_file_size", "files_hash", "profile_hash",
+            "media_type", "v2_root", "size_only", "padding_excluded",
+        ])?;
+    }
+
+    writer.into_inner().map_err(|e| anyhow::anyhow!(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ContentFingerprint;
+
+    fn entry(hash: &str) -> IndexExportEntry {
+        IndexExportEntry {
+            torrent_id: Some("123".to_string()),
+            name: Some("Movie, The (2020)".to_string()),
+            ..IndexExportEntry::from_fingerprint(hash, "hdsky", &ContentFingerprint::from_size(1000, 2, 900))
+        }
+    }
+
+    #[test]
+    fn test_json_chunks_form_valid_array() {
+        let mut buf = encode_json_chunk(&[entry("a"), entry("b")], true, false).unwrap();
+        buf.extend(encode_json_chunk(&[entry("c")], false, true).unwrap());
+
+        let parsed: Vec<IndexExportEntry> = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[2].info_hash, "c");
+    }
+
+    #[test]
+    fn test_json_empty_export() {
+        let buf = encode_json_chunk(&[], true, true).unwrap();
+        assert_eq!(buf, b"[]");
+    }
+
+    #[test]
+    fn test_csv_chunks_round_trip() {
+        let mut buf = encode_csv_chunk(&[entry("a")], true).unwrap();
+        buf.extend(encode_csv_chunk(&[entry("b")], false).unwrap());
+
+        let parsed: Vec<IndexExportEntry> = csv::Reader::from_reader(buf.as_slice())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name.as_deref(), Some("Movie, The (2020)"));
+        assert_eq!(parsed[1].save_path, None);
+    }
 }
@@ -1,22 +1,36 @@
 //! API request handlers
 
+pub mod backup;
 pub mod client;
+pub mod fingerprint;
 pub mod index;
 pub mod reseed;
 pub mod site;
+pub mod ws;
 
 use axum::{
     body::Body,
-    http::{header, StatusCode, Uri},
+    extract::State,
+    http::{header, HeaderMap, StatusCode, Uri},
     response::{IntoResponse, Response},
     Json,
 };
 use rust_embed::Embed;
+use serde::Serialize;
 use serde_json::json;
+use utoipa::ToSchema;
 
-use super::WebAssets;
+use super::{AppState, WebAssets};
+use crate::config::ServerSettings;
+use crate::db::repository::{self, Health, HealthKind};
 
 /// Health check endpoint
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "system",
+    responses((status = 200, body = Object))
+)]
 pub async fn health() -> Json<serde_json::Value> {
     Json(json!({
         "status": "ok",
@@ -24,40 +38,60 @@ pub async fn health() -> Json<serde_json::Value> {
     }))
 }
 
+/// Build metadata endpoint
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "system",
+    responses((status = 200, body = Object))
+)]
+pub async fn version() -> Json<serde_json::Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("VERGEN_GIT_SHA"),
+        "git_commit_date": env!("VERGEN_GIT_COMMIT_TIMESTAMP"),
+        "build_date": env!("VERGEN_BUILD_TIMESTAMP"),
+        "rustc_version": env!("VERGEN_RUSTC_SEMVER"),
+    }))
+}
+
+/// OpenAPI description of this API
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "system",
+    responses((status = 200, body = Object))
+)]
+pub async fn openapi(State(state): State<AppState>) -> Json<utoipa::openapi::OpenApi> {
+    let api_url = format!("{}/api", state.settings.server.normalized_base_path());
+    Json(super::openapi::document(&api_url))
+}
+
 /// Dashboard stats
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "system",
+    responses((status = 200, body = Object))
+)]
 pub async fn stats(
     axum::extract::State(state): axum::extract::State<super::AppState>,
 ) -> Result<Json<serde_json::Value>, super::AppError> {
-    let index_stats = state.index_service.get_stats()?;
+    Ok(Json(dashboard_stats(&state)?))
+}
 
-    // Get client count
-    let client_count: i64 = state.db.conn().query_row(
-        "SELECT COUNT(*) FROM clients",
-        [],
-        |row| row.get(0),
-    )?;
+/// Collect the stats shown on the dashboard
+pub(crate) fn dashboard_stats(state: &super::AppState) -> Result<serde_json::Value, super::AppError> {
+    let index_stats = state.index_service.get_stats()?;
 
-    // Get site count
-    let site_count: i64 = state.db.conn().query_row(
-        "SELECT COUNT(*) FROM sites WHERE enabled = 1",
-        [],
-        |row| row.get(0),
-    )?;
+    let client_count = repository::count_clients(&state.db.conn())?;
+    let site_count = repository::count_enabled_sites(&state.db.conn())?;
 
     // Get recent history stats
-    let today_success: i64 = state.db.conn().query_row(
-        "SELECT COUNT(*) FROM reseed_history WHERE status = 'success' AND date(created_at) = date('now')",
-        [],
-        |row| row.get(0),
-    )?;
-
-    let today_failed: i64 = state.db.conn().query_row(
-        "SELECT COUNT(*) FROM reseed_history WHERE status = 'failed' AND date(created_at) = date('now')",
-        [],
-        |row| row.get(0),
-    )?;
-
-    Ok(Json(json!({
+    let today_success = state.store.count_history_today(&["success", "verified_100"])?;
+    let today_failed = state.store.count_history_today(&["failed", "verify_failed"])?;
+
+    Ok(json!({
         "index": index_stats,
         "clients": client_count,
         "sites": site_count,
@@ -65,11 +99,82 @@ pub async fn stats(
             "success": today_success,
             "failed": today_failed,
         }
-    })))
+    }))
+}
+
+/// Latest request outcomes of a client or site
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ComponentStatus {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    /// Nothing failed since the last success (or nothing was tried yet)
+    pub ok: bool,
+    #[serde(flatten)]
+    pub health: Health,
+}
+
+impl ComponentStatus {
+    fn new(id: String, name: String, enabled: bool, health: Option<Health>) -> Self {
+        let health = health.unwrap_or_default();
+        Self {
+            id,
+            name,
+            enabled,
+            ok: health.is_ok(),
+            health,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StatusResponse {
+    /// Every enabled client and site is ok
+    pub ok: bool,
+    pub clients: Vec<ComponentStatus>,
+    pub sites: Vec<ComponentStatus>,
+}
+
+/// Last success and failure of each client and enabled site
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "system",
+    responses((status = 200, body = StatusResponse))
+)]
+pub async fn status(State(state): State<AppState>) -> Result<Json<StatusResponse>, super::AppError> {
+    let conn = state.db.conn();
+    let mut client_health = repository::list_health(&conn, HealthKind::Client)?;
+    let mut site_health = repository::list_health(&conn, HealthKind::Site)?;
+
+    let clients: Vec<ComponentStatus> = repository::list_clients(&conn)?
+        .into_iter()
+        .map(|c| {
+            let health = client_health.remove(&c.id);
+            ComponentStatus::new(c.id, c.name, c.enabled, health)
+        })
+        .collect();
+    let sites: Vec<ComponentStatus> = repository::list_enabled_sites(&conn)?
+        .into_iter()
+        .map(|s| {
+            let health = site_health.remove(&s.id);
+            ComponentStatus::new(s.id, s.name, s.enabled, health)
+        })
+        .collect();
+
+    Ok(Json(StatusResponse {
+        ok: clients.iter().chain(&sites).all(|c| c.ok || !c.enabled),
+        clients,
+        sites,
+    }))
 }
 
 /// Static file handler for SPA
-pub async fn static_handler(uri: Uri) -> impl IntoResponse {
+pub async fn static_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> impl IntoResponse {
     let path = uri.path().trim_start_matches('/');
 
     // Try to serve the exact file
@@ -84,14 +189,87 @@ pub async fn static_handler(uri: Uri) -> impl IntoResponse {
 
     // Fallback to index.html for SPA routing
     match <WebAssets as Embed>::get("index.html") {
-        Some(content) => Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, "text/html")
-            .body(Body::from(content.data.into_owned()))
-            .unwrap(),
+        Some(content) => {
+            let html = String::from_utf8_lossy(&content.data);
+            let base_href = public_base_url(&state.settings.server, &headers);
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "text/html")
+                .body(Body::from(inject_base(&html, &base_href, &state.settings.server.normalized_base_path())))
+                .unwrap()
+        }
         None => Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(Body::from("Not Found"))
             .unwrap(),
     }
 }
+
+/// URL the SPA is served from, ending in a slash
+///
+/// Relative to the host unless forwarded headers are trusted and present,
+/// in which case it is the absolute URL the client used to reach the proxy.
+fn public_base_url(server: &ServerSettings, headers: &HeaderMap) -> String {
+    let base_path = server.normalized_base_path();
+
+    if server.trust_forwarded_headers {
+        let forwarded = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                // Proxies chaining the header append values; the first is the client's
+                .and_then(|v| v.split(',').next())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        if let (Some(proto), Some(host)) = (forwarded("x-forwarded-proto"), forwarded("x-forwarded-host")) {
+            return format!("{}://{}{}/", proto, host, base_path);
+        }
+    }
+
+    format!("{}/", base_path)
+}
+
+/// Add a `<base>` tag and the base path for the SPA to `index.html`
+fn inject_base(html: &str, base_href: &str, base_path: &str) -> String {
+    let tags = format!(
+        "<head>\n    <base href=\"{}\" />\n    <script>window.__GRAFT_BASE__ = {};</script>",
+        html_escape(base_href),
+        serde_json::to_string(base_path).unwrap_or_default()
+    );
+    html.replacen("<head>", &tags, 1)
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_base_url() {
+        let mut server = ServerSettings {
+            base_path: "/graft/".to_string(),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        headers.insert("x-forwarded-host", "media.example.com, proxy.local".parse().unwrap());
+
+        // Forwarded headers are ignored unless trusted
+        assert_eq!(public_base_url(&server, &headers), "/graft/");
+
+        server.trust_forwarded_headers = true;
+        assert_eq!(public_base_url(&server, &headers), "https://media.example.com/graft/");
+        assert_eq!(public_base_url(&server, &HeaderMap::new()), "/graft/");
+    }
+
+    #[test]
+    fn test_inject_base() {
+        let html = inject_base("<html><head><title>Graft</title></head></html>", "/graft/", "/graft");
+        assert!(html.contains(r#"<base href="/graft/" />"#));
+        assert!(html.contains(r#"window.__GRAFT_BASE__ = "/graft";"#));
+    }
+}
@@ -1,34 +1,93 @@
 //! Reseed operation handlers
 
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
     Json,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::api::{AppError, AppState};
-use crate::client::{ClientConfig, ClientType};
+use crate::api::{AppError, AppState, ErrorCode, IDEMPOTENCY_KEY_HEADER};
+use crate::client::BitTorrentClient;
+use crate::db::repository::{self, Approval, FailureStat, HistoryEntry, RunEntry};
 use crate::service::{PreviewResult, ReseedRequest, ReseedResult};
 use crate::site::SiteConfig;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct PreviewRequest {
-    pub source_client_id: String,
+    /// Single source client (kept for compatibility with `source_client_ids`)
+    pub source_client_id: Option<String>,
+    #[serde(default)]
+    pub source_client_ids: Vec<String>,
     pub target_site_ids: Vec<String>,
+    /// Only match these source torrents (info hashes) instead of every
+    /// torrent in the source clients
+    pub source_hashes: Option<Vec<String>>,
+    /// Keep only the highest-priority target site per source torrent
+    #[serde(default)]
+    pub best_target_only: bool,
+    /// Also match against target sites without a passkey or cookie
+    #[serde(default)]
+    pub include_unconfigured: bool,
+    /// Keep only this many matches per source torrent, most confident
+    /// first; 0 or unset means unlimited
+    pub max_matches_per_source: Option<usize>,
+    /// Leave out matches to sites the torrent was already reseeded to
+    #[serde(default)]
+    pub hide_completed: bool,
+    /// Tag each match with what executing it into `target_client_id` would do
+    #[serde(default)]
+    pub annotate: bool,
+    /// Client checked for torrents it already has; required with `annotate`
+    pub target_client_id: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct ExecuteRequest {
-    pub source_client_id: String,
+    /// Single source client (kept for compatibility with `source_client_ids`)
+    pub source_client_id: Option<String>,
+    #[serde(default)]
+    pub source_client_ids: Vec<String>,
     pub target_client_id: String,
     pub target_site_ids: Vec<String>,
+    /// Only reseed these source torrents (info hashes)
+    pub source_hashes: Option<Vec<String>>,
     #[serde(default)]
     pub add_paused: bool,
     #[serde(default)]
     pub skip_checking: bool,
+    #[serde(default)]
+    pub add_top_of_queue: bool,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub use_category_save_path: bool,
+    pub min_confidence: Option<f64>,
+    pub save_path_override: Option<String>,
+    /// Rebase save paths from the source client's download root onto the target's
+    #[serde(default)]
+    pub translate_save_path: bool,
+    /// Bytes per second; 0 or unset means unlimited
+    pub upload_limit: Option<u64>,
+    /// Bytes per second; 0 or unset means unlimited
+    pub download_limit: Option<u64>,
+    #[serde(default)]
+    pub best_target_only: bool,
+    /// Keep announcing to the source trackers from the added torrent
+    #[serde(default)]
+    pub merge_source_trackers: bool,
+    /// Wait for each added torrent to be checked and record the result
+    #[serde(default)]
+    pub verify_after_add: bool,
+    /// `(from, to)` tracker host pairs to rewrite in downloaded torrents
+    #[serde(default)]
+    pub announce_rewrite: Vec<(String, String)>,
+    /// Also reseed to target sites without a passkey or cookie
+    #[serde(default)]
+    pub include_unconfigured: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct HistoryQuery {
     #[serde(default = "default_limit")]
     pub limit: i64,
@@ -41,181 +100,344 @@ fn default_limit() -> i64 {
     50
 }
 
-#[derive(Debug, Serialize)]
-pub struct HistoryEntry {
-    pub id: i64,
-    pub info_hash: String,
-    pub source_site: Option<String>,
-    pub target_site: String,
-    pub status: String,
-    pub message: Option<String>,
-    pub created_at: String,
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct FailureStatsQuery {
+    /// Only count failures from the last this many days
+    pub days: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RunsQuery {
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
 }
 
 /// Preview reseed matches
+#[utoipa::path(
+    post,
+    path = "/reseed/preview",
+    tag = "reseed",
+    request_body = PreviewRequest,
+    responses((status = 200, body = PreviewResult))
+)]
 pub async fn preview(
     State(state): State<AppState>,
     Json(req): Json<PreviewRequest>,
 ) -> Result<Json<PreviewResult>, AppError> {
-    // Get source client
-    let source_config = get_client_config(&state, &req.source_client_id)?;
-    let source_client = source_config.create_client();
+    // Get source clients
+    let source_client_ids = merge_source_client_ids(req.source_client_id, req.source_client_ids)?;
+    let source_clients = create_clients(&state, &source_client_ids)?;
+    let source_refs: Vec<&dyn BitTorrentClient> = source_clients.iter().map(|c| c.as_ref()).collect();
+
+    let target_client = match (req.annotate, req.target_client_id.as_deref()) {
+        (false, _) => None,
+        (true, Some(id)) => Some(state.enabled_client_config(id)?.create_client()),
+        (true, None) => return Err(AppError::bad_request("annotate requires target_client_id")),
+    };
 
     // Get target sites
-    let sites = get_site_configs(&state, &req.target_site_ids)?;
+    let (sites, unconfigured_sites) =
+        get_site_configs(&state, &req.target_site_ids, req.include_unconfigured)?;
 
     // Run preview
-    let result = state.reseed_service
-        .preview(source_client.as_ref(), &sites)
+    let mut result = state.reseed_service
+        .preview(
+            &source_refs,
+            &sites,
+            req.source_hashes.as_deref(),
+            req.best_target_only,
+            req.max_matches_per_source.filter(|&max| max > 0),
+            req.hide_completed,
+        )
         .await?;
+    result.unconfigured_sites = unconfigured_sites;
+
+    if let Some(target_client) = target_client {
+        state.reseed_service.annotate(&mut result, target_client.as_ref(), &sites).await?;
+    }
 
     Ok(Json(result))
 }
 
 /// Execute reseed operation
+///
+/// With an `Idempotency-Key` header, a repeat of the request within the
+/// key's lifetime returns the first run's result instead of running again.
+#[utoipa::path(
+    post,
+    path = "/reseed/execute",
+    tag = "reseed",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the stored result of an earlier request with the same key")),
+    request_body = ExecuteRequest,
+    responses((status = 200, body = ReseedResult))
+)]
 pub async fn execute(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(req): Json<ExecuteRequest>,
 ) -> Result<Json<ReseedResult>, AppError> {
-    // Get source client
-    let source_config = get_client_config(&state, &req.source_client_id)?;
-    let source_client = source_config.create_client();
+    let key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty());
+
+    let result = match key {
+        Some(key) => state.execute_results.run(key, || run_execute(&state, req)).await?,
+        None => run_execute(&state, req).await?,
+    };
+
+    Ok(Json(result))
+}
+
+async fn run_execute(state: &AppState, req: ExecuteRequest) -> Result<ReseedResult, AppError> {
+    // Get source clients
+    let source_client_ids = merge_source_client_ids(req.source_client_id, req.source_client_ids)?;
+    let source_clients = create_clients(state, &source_client_ids)?;
+    let source_refs: Vec<&dyn BitTorrentClient> = source_clients.iter().map(|c| c.as_ref()).collect();
 
     // Get target client
-    let target_config = get_client_config(&state, &req.target_client_id)?;
+    let target_config = state.enabled_client_config(&req.target_client_id)?;
     let target_client = target_config.create_client();
 
     // Get target sites
-    let sites = get_site_configs(&state, &req.target_site_ids)?;
+    let (sites, unconfigured_sites) =
+        get_site_configs(state, &req.target_site_ids, req.include_unconfigured)?;
 
     // Build request
     let reseed_req = ReseedRequest {
         task_id: None,
-        source_client_id: req.source_client_id,
+        source_client_ids,
         target_client_id: req.target_client_id,
         target_site_ids: req.target_site_ids,
+        source_hashes: req.source_hashes,
         add_paused: req.add_paused,
         skip_checking: req.skip_checking,
+        add_top_of_queue: req.add_top_of_queue,
+        category: req.category,
+        use_category_save_path: req.use_category_save_path,
+        min_confidence: req.min_confidence,
+        save_path_override: req.save_path_override.filter(|p| !p.trim().is_empty()),
+        translate_save_path: req.translate_save_path,
+        upload_limit: req.upload_limit.filter(|&l| l > 0),
+        download_limit: req.download_limit.filter(|&l| l > 0),
+        best_target_only: req.best_target_only,
+        merge_source_trackers: req.merge_source_trackers,
+        verify_after_add: req.verify_after_add,
+        queue_below_confidence: None,
+        announce_rewrite: req.announce_rewrite,
     };
 
     // Execute
-    let result = state.reseed_service
-        .execute(reseed_req, source_client.as_ref(), target_client.as_ref(), &sites)
+    let mut result = state.reseed_service
+        .execute(reseed_req, &source_refs, target_client.as_ref(), &sites)
         .await?;
+    result.unconfigured_sites = unconfigured_sites;
 
-    Ok(Json(result))
+    Ok(result)
 }
 
 /// Get reseed history
+#[utoipa::path(
+    get,
+    path = "/reseed/history",
+    tag = "reseed",
+    params(HistoryQuery),
+    responses((status = 200, body = Vec<HistoryEntry>))
+)]
 pub async fn history(
     State(state): State<AppState>,
     Query(query): Query<HistoryQuery>,
 ) -> Result<Json<Vec<HistoryEntry>>, AppError> {
-    let conn = state.db.conn();
-
-    let entries = if let Some(ref status) = query.status {
-        let sql = "SELECT id, info_hash, source_site, target_site, status, message, created_at
-             FROM reseed_history
-             WHERE status = ?1
-             ORDER BY created_at DESC
-             LIMIT ?2 OFFSET ?3";
-        let mut stmt = conn.prepare(sql)?;
-        let rows = stmt.query_map(rusqlite::params![status, query.limit, query.offset], |row| {
-            Ok(HistoryEntry {
-                id: row.get(0)?,
-                info_hash: row.get(1)?,
-                source_site: row.get(2)?,
-                target_site: row.get(3)?,
-                status: row.get(4)?,
-                message: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })?;
-        rows.collect::<Result<Vec<_>, _>>()?
-    } else {
-        let sql = "SELECT id, info_hash, source_site, target_site, status, message, created_at
-         FROM reseed_history
-         ORDER BY created_at DESC
-         LIMIT ?1 OFFSET ?2";
-        let mut stmt = conn.prepare(sql)?;
-        let rows = stmt.query_map(rusqlite::params![query.limit, query.offset], |row| {
-            Ok(HistoryEntry {
-                id: row.get(0)?,
-                info_hash: row.get(1)?,
-                source_site: row.get(2)?,
-                target_site: row.get(3)?,
-                status: row.get(4)?,
-                message: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })?;
-        rows.collect::<Result<Vec<_>, _>>()?
-    };
+    let entries = state.store.list_history(query.status.as_deref(), query.limit, query.offset)?;
 
     Ok(Json(entries))
 }
 
-/// Helper to get client config from database
-fn get_client_config(state: &AppState, id: &str) -> Result<ClientConfig, AppError> {
-    let conn = state.db.conn();
-    conn.query_row(
-        "SELECT id, name, client_type, host, port, username, password_encrypted, use_https FROM clients WHERE id = ?1",
-        [id],
-        |row| {
-            let client_type_str: String = row.get(2)?;
-            Ok(ClientConfig {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                client_type: client_type_str.parse().unwrap_or(ClientType::QBittorrent),
-                host: row.get(3)?,
-                port: row.get(4)?,
-                username: row.get(5)?,
-                password: row.get(6)?,
-                use_https: row.get::<_, i32>(7)? != 0,
-            })
-        },
-    ).map_err(|_| AppError::not_found("Client not found"))
-}
-
-/// Helper to get site configs from database
-fn get_site_configs(state: &AppState, site_ids: &[String]) -> Result<Vec<SiteConfig>, AppError> {
+/// Failure counts per target site and failure reason, most frequent first
+#[utoipa::path(
+    get,
+    path = "/reseed/failure-stats",
+    tag = "reseed",
+    params(FailureStatsQuery),
+    responses((status = 200, body = Vec<FailureStat>))
+)]
+pub async fn failure_stats(
+    State(state): State<AppState>,
+    Query(query): Query<FailureStatsQuery>,
+) -> Result<Json<Vec<FailureStat>>, AppError> {
+    let stats = state.store.failure_stats(query.days)?;
+
+    Ok(Json(stats))
+}
+
+/// List reseed run summaries, newest first
+#[utoipa::path(
+    get,
+    path = "/reseed/runs",
+    tag = "reseed",
+    params(RunsQuery),
+    responses((status = 200, body = Vec<RunEntry>))
+)]
+pub async fn runs(
+    State(state): State<AppState>,
+    Query(query): Query<RunsQuery>,
+) -> Result<Json<Vec<RunEntry>>, AppError> {
+    let runs = state.store.list_runs(query.limit, query.offset)?;
+
+    Ok(Json(runs))
+}
+
+/// List matches from scheduled runs waiting for approval, oldest first
+#[utoipa::path(
+    get,
+    path = "/reseed/pending",
+    tag = "reseed",
+    responses((status = 200, body = Vec<Approval>))
+)]
+pub async fn pending(State(state): State<AppState>) -> Result<Json<Vec<Approval>>, AppError> {
+    let approvals = repository::list_pending_approvals(&state.db.conn())?;
+
+    Ok(Json(approvals))
+}
+
+/// Approve a queued match and add it to its target client
+#[utoipa::path(
+    post,
+    path = "/reseed/pending/{id}/approve",
+    tag = "reseed",
+    params(("id" = i64, Path, description = "Pending match id")),
+    responses((status = 200, body = ReseedResult))
+)]
+pub async fn approve(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<ReseedResult>, AppError> {
+    let approval = pending_approval(&state, id)?;
+    let m = &approval.reseed_match;
+
+    let source_client = state.enabled_client_config(&m.source_client_id)?.create_client();
+    let target_client = state.enabled_client_config(&approval.target_client)?.create_client();
+    let site = repository::get_enabled_site(&state.db.conn(), &m.target_site)?.ok_or_else(|| {
+        AppError::bad_request(format!("Site is not available: {}", m.target_site)).with_code(ErrorCode::SiteNotFound)
+    })?;
+
+    // Claim the approval first so a second request cannot add it again
+    if !repository::resolve_approval(&state.db.conn(), id, "approved")? {
+        return Err(approval_resolved());
+    }
+
+    let result = state
+        .reseed_service
+        .execute_approved(&approval, source_client.as_ref(), target_client.as_ref(), &site)
+        .await?;
+
+    Ok(Json(result))
+}
+
+/// Reject a queued match without adding it
+#[utoipa::path(
+    post,
+    path = "/reseed/pending/{id}/reject",
+    tag = "reseed",
+    params(("id" = i64, Path, description = "Pending match id")),
+    responses((status = 200, body = Object))
+)]
+pub async fn reject(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    pending_approval(&state, id)?;
+
+    if !repository::resolve_approval(&state.db.conn(), id, "rejected")? {
+        return Err(approval_resolved());
+    }
+
+    Ok(Json(serde_json::json!({"rejected": true})))
+}
+
+/// Load an approval that is still waiting for a decision
+fn pending_approval(state: &AppState, id: i64) -> Result<Approval, AppError> {
+    let approval = repository::get_approval(&state.db.conn(), id)?
+        .ok_or_else(|| AppError::not_found("Approval not found").with_code(ErrorCode::ApprovalNotFound))?;
+
+    if approval.status != "pending" {
+        return Err(approval_resolved());
+    }
+
+    Ok(approval)
+}
+
+fn approval_resolved() -> AppError {
+    AppError::new(StatusCode::CONFLICT, ErrorCode::ApprovalResolved, "Approval was already resolved")
+}
+
+/// Combine the legacy single source client with the list, preserving order
+fn merge_source_client_ids(single: Option<String>, many: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut ids = Vec::with_capacity(many.len() + 1);
+    for id in single.into_iter().chain(many) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    if ids.is_empty() {
+        return Err(AppError::bad_request("At least one source client is required"));
+    }
+
+    Ok(ids)
+}
+
+/// Helper to create client instances for a list of client IDs
+fn create_clients(state: &AppState, ids: &[String]) -> Result<Vec<Box<dyn BitTorrentClient>>, AppError> {
+    ids.iter()
+        .map(|id| state.enabled_client_config(id).map(|c| c.create_client()))
+        .collect()
+}
+
+/// Helper to get enabled site configs from database, skipping unknown or
+/// disabled sites
+///
+/// Unless `include_unconfigured` is set, sites without a passkey or cookie
+/// are left out too, since every download from them would fail; their ids
+/// are returned separately so the caller can report them.
+fn get_site_configs(
+    state: &AppState,
+    site_ids: &[String],
+    include_unconfigured: bool,
+) -> Result<(Vec<SiteConfig>, Vec<String>), AppError> {
     let conn = state.db.conn();
     let mut sites = Vec::new();
+    let mut unconfigured = Vec::new();
 
     for site_id in site_ids {
-        let site = conn.query_row(
-            "SELECT id, name, base_url, template_type, passkey, cookie_encrypted, enabled, rate_limit_rpm
-             FROM sites WHERE id = ?1 AND enabled = 1",
-            [site_id],
-            |row| {
-                let template_str: String = row.get(3)?;
-                Ok(SiteConfig {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    base_url: row.get(2)?,
-                    template_type: template_str.parse().unwrap_or(crate::site::TemplateType::NexusPHP),
-                    tracker_domains: Vec::new(), // Not needed for download
-                    download_pattern: get_download_pattern(&template_str),
-                    passkey: row.get(4)?,
-                    cookie: row.get(5)?,
-                    enabled: row.get::<_, i32>(6)? != 0,
-                    rate_limit_rpm: row.get(7)?,
-                })
-            },
-        );
-
-        if let Ok(site) = site {
-            sites.push(site);
+        if let Some(site) = repository::get_enabled_site(&conn, site_id)? {
+            if include_unconfigured || site.has_credentials() {
+                sites.push(site);
+            } else {
+                unconfigured.push(site.id);
+            }
         }
     }
 
-    Ok(sites)
+    Ok((sites, unconfigured))
 }
 
-fn get_download_pattern(template_type: &str) -> String {
-    match template_type {
-        "unit3d" => "/torrent/download/{id}.{passkey}".to_string(),
-        "gazelle" => "/torrents.php?action=download&id={id}&authkey={authkey}&torrent_pass={passkey}".to_string(),
-        _ => "/download.php?id={id}&passkey={passkey}".to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_source_client_ids() {
+        let ids = merge_source_client_ids(
+            Some("a".to_string()),
+            vec!["b".to_string(), "a".to_string()],
+        )
+        .unwrap();
+        assert_eq!(ids, vec!["a".to_string(), "b".to_string()]);
+
+        assert!(merge_source_client_ids(None, Vec::new()).is_err());
     }
 }
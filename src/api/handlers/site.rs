@@ -2,25 +2,52 @@
 
 use axum::{
     extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
 
-use crate::api::{AppError, AppState};
-use crate::site::{builtin_sites, SiteConfig, TemplateType};
+use crate::api::{AppError, AppState, ErrorCode};
+use crate::db::repository::{self, HealthKind, SiteUpdate};
+use crate::site::{builtin_sites, redact_proxy, validate_torrent, AuthCheck, SiteConfig, TemplateType};
 
-#[derive(Debug, Serialize)]
+/// Timeout for each site's credential check
+const SITE_TEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Sites checked at once by test-all, on top of the global rate limit
+const SITE_TEST_CONCURRENCY: usize = 4;
+
+/// Timeout for a torrent download through `download_torrent`
+const SITE_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SiteResponse {
     pub id: String,
     pub name: String,
     pub base_url: String,
     pub template_type: TemplateType,
     pub has_passkey: bool,
+    pub has_rsskey: bool,
     pub has_cookie: bool,
+    /// Whether a username and password are stored for logging in
+    pub has_login: bool,
     pub enabled: bool,
+    pub extra_headers: HashMap<String, String>,
+    pub daily_download_limit: Option<u32>,
+    pub announce_url: Option<String>,
+    pub default_category: Option<String>,
+    pub default_save_path: Option<String>,
+    /// Proxy URL with the password masked
+    pub proxy: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateSiteRequest {
     pub id: String,
     pub name: String,
@@ -29,90 +56,273 @@ pub struct CreateSiteRequest {
     #[serde(default)]
     pub template_type: Option<TemplateType>,
     pub passkey: Option<String>,
+    /// Unit3D RSS key for downloads, when it differs from the passkey
+    pub rsskey: Option<String>,
     pub cookie: Option<String>,
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    pub daily_download_limit: Option<u32>,
+    pub announce_url: Option<String>,
+    pub default_category: Option<String>,
+    pub default_save_path: Option<String>,
+    pub proxy: Option<String>,
+    /// Account for refreshing the cookie by logging in (NexusPHP only)
+    pub site_username: Option<String>,
+    pub site_password: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateSiteRequest {
     pub name: Option<String>,
     pub base_url: Option<String>,
     pub passkey: Option<String>,
+    /// Unit3D RSS key; an empty string falls back to the passkey
+    pub rsskey: Option<String>,
     pub cookie: Option<String>,
     pub enabled: Option<bool>,
+    pub extra_headers: Option<HashMap<String, String>>,
+    /// Torrents added per day; 0 removes the limit
+    pub daily_download_limit: Option<u32>,
+    /// Announce URL pattern; an empty string removes it
+    pub announce_url: Option<String>,
+    /// Empty strings remove the site default
+    pub default_category: Option<String>,
+    pub default_save_path: Option<String>,
+    /// Proxy URL for this site; an empty string removes it
+    pub proxy: Option<String>,
+    /// Empty strings remove the stored login
+    pub site_username: Option<String>,
+    pub site_password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BulkEnableRequest {
+    pub site_ids: Vec<String>,
+    pub enabled: bool,
+}
+
+impl From<SiteConfig> for SiteResponse {
+    fn from(site: SiteConfig) -> Self {
+        let has_login = site.can_login();
+        Self {
+            id: site.id,
+            name: site.name,
+            base_url: site.base_url,
+            template_type: site.template_type,
+            has_passkey: site.passkey.is_some(),
+            has_rsskey: site.rsskey.is_some(),
+            has_cookie: site.cookie.is_some(),
+            has_login,
+            enabled: site.enabled,
+            extra_headers: site.extra_headers,
+            daily_download_limit: site.daily_download_limit,
+            announce_url: site.announce_url,
+            default_category: site.default_category,
+            default_save_path: site.default_save_path,
+            proxy: site.proxy.as_deref().map(redact_proxy),
+        }
+    }
 }
 
 /// List all configured sites
+#[utoipa::path(
+    get,
+    path = "/sites",
+    tag = "sites",
+    responses((status = 200, body = Vec<SiteResponse>))
+)]
 pub async fn list(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<SiteResponse>>, AppError> {
-    let conn = state.db.conn();
-    let mut stmt = conn.prepare(
-        "SELECT id, name, base_url, template_type, passkey, cookie_encrypted, enabled FROM sites ORDER BY name"
-    )?;
-
-    let sites = stmt
-        .query_map([], |row| {
-            let template_str: String = row.get(3)?;
-            let passkey: Option<String> = row.get(4)?;
-            let cookie: Option<String> = row.get(5)?;
-            Ok(SiteResponse {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                base_url: row.get(2)?,
-                template_type: template_str.parse().unwrap_or(TemplateType::NexusPHP),
-                has_passkey: passkey.is_some(),
-                has_cookie: cookie.is_some(),
-                enabled: row.get::<_, i32>(6)? != 0,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-
-    Ok(Json(sites))
+    let sites = repository::list_sites(&state.db.conn())?;
+    Ok(Json(sites.into_iter().map(SiteResponse::from).collect()))
+}
+
+/// Outcome of checking one site's credentials
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SiteTestResult {
+    pub success: bool,
+    /// Whether the site confirmed the credentials; false when only a passkey
+    /// is set, which cannot be checked without downloading a torrent
+    pub verified: bool,
+    pub message: String,
+}
+
+/// Check the credentials of every enabled site
+#[utoipa::path(
+    post,
+    path = "/sites/test-all",
+    tag = "sites",
+    responses((status = 200, body = HashMap<String, SiteTestResult>))
+)]
+pub async fn test_all(
+    State(state): State<AppState>,
+) -> Result<Json<HashMap<String, SiteTestResult>>, AppError> {
+    let sites = repository::list_enabled_sites(&state.db.conn())?;
+
+    let http_client = reqwest::Client::builder()
+        .timeout(SITE_TEST_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::internal(e.to_string()))?;
+
+    let checks = sites.into_iter().map(|site| {
+        let http_client = http_client.clone();
+        let state = state.clone();
+        async move {
+            // Sites behind their own proxy get a client of their own
+            let site_client = match site.proxy {
+                Some(_) => match site.http_client(SITE_TEST_TIMEOUT) {
+                    Ok(client) => Some(client),
+                    Err(e) => {
+                        let result = SiteTestResult {
+                            success: false,
+                            verified: false,
+                            message: format!("Invalid proxy: {}", e),
+                        };
+                        return (site.id.clone(), result);
+                    }
+                },
+                None => None,
+            };
+            let http_client = site_client.as_ref().unwrap_or(&http_client);
+
+            state.reseed_service.throttle().await;
+            let checked = site.create_template().check_auth(http_client).await;
+            // An unchecked passkey says nothing about the site's health, and
+            // read-only mode allows this route only because it writes nothing
+            if !state.settings.server.read_only && !matches!(checked, Ok(AuthCheck::Unverified)) {
+                state.store.record_outcome(HealthKind::Site, &site.id, &checked);
+            }
+            let result = match checked {
+                Ok(AuthCheck::Verified) => SiteTestResult {
+                    success: true,
+                    verified: true,
+                    message: "Authentication successful".to_string(),
+                },
+                Ok(AuthCheck::Unverified) => SiteTestResult {
+                    success: true,
+                    verified: false,
+                    message: "Passkey set but not verified; add a cookie to check the session".to_string(),
+                },
+                Err(e) => SiteTestResult {
+                    success: false,
+                    verified: false,
+                    message: e.to_string(),
+                },
+            };
+            (site.id.clone(), result)
+        }
+    });
+
+    Ok(Json(futures::stream::iter(checks).buffer_unordered(SITE_TEST_CONCURRENCY).collect().await))
+}
+
+/// Download a torrent file from a site with its stored credentials
+///
+/// Goes through the site template like a reseed download, so it doubles as
+/// a check that the credentials yield a real torrent. Like reseed downloads,
+/// it is throttled and counts against the site's daily download limit.
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/torrents/{torrent_id}/download",
+    tag = "sites",
+    params(("id" = String, Path, description = "Site id"), ("torrent_id" = String, Path, description = "Torrent id on the site")),
+    responses((status = 200, content_type = "application/x-bittorrent", body = Vec<u8>))
+)]
+pub async fn download_torrent(
+    State(state): State<AppState>,
+    Path((id, torrent_id)): Path<(String, String)>,
+) -> Result<Response, AppError> {
+    // The id is substituted into the download URL as is
+    if torrent_id.is_empty()
+        || !torrent_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(AppError::bad_request("Invalid torrent id"));
+    }
+
+    let site = repository::get_site(&state.db.conn(), &id)?.ok_or_else(site_not_found)?;
+    if !site.enabled {
+        return Err(AppError::bad_request("Site is disabled").with_code(ErrorCode::SiteDisabled));
+    }
+    if let Some(limit) = site.daily_download_limit {
+        if state.reseed_service.count_added_today(&site.id)? >= limit {
+            return Err(AppError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::DailyLimitReached,
+                format!("Daily download limit of {} reached", limit),
+            ));
+        }
+    }
+    let http_client = site.http_client(SITE_DOWNLOAD_TIMEOUT).map_err(|e| {
+        AppError::bad_request(format!("Invalid proxy: {}", e)).with_code(ErrorCode::InvalidProxy)
+    })?;
+
+    state.reseed_service.throttle().await;
+    let downloaded = site.create_template().download_torrent(&http_client, &torrent_id).await;
+    state.store.record_outcome(HealthKind::Site, &site.id, &downloaded);
+    let bytes = downloaded?;
+    let contents = validate_torrent(&bytes)?;
+    state.reseed_service.record_download(&site.id, &contents.info_hash)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-bittorrent".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}-{}.torrent\"", site.id, torrent_id),
+            ),
+        ],
+        bytes,
+    )
+        .into_response())
 }
 
 /// Get available site templates (built-in sites)
+#[utoipa::path(
+    get,
+    path = "/sites/available",
+    tag = "sites",
+    responses((status = 200, body = Vec<SiteConfig>))
+)]
 pub async fn available() -> Json<Vec<SiteConfig>> {
     Json(builtin_sites())
 }
 
 /// Get a single site
+#[utoipa::path(
+    get,
+    path = "/sites/{id}",
+    tag = "sites",
+    params(("id" = String, Path, description = "Site id")),
+    responses((status = 200, body = SiteResponse))
+)]
 pub async fn get_one(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<SiteResponse>, AppError> {
-    let conn = state.db.conn();
-    let site = conn.query_row(
-        "SELECT id, name, base_url, template_type, passkey, cookie_encrypted, enabled FROM sites WHERE id = ?1",
-        [&id],
-        |row| {
-            let template_str: String = row.get(3)?;
-            let passkey: Option<String> = row.get(4)?;
-            let cookie: Option<String> = row.get(5)?;
-            Ok(SiteResponse {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                base_url: row.get(2)?,
-                template_type: template_str.parse().unwrap_or(TemplateType::NexusPHP),
-                has_passkey: passkey.is_some(),
-                has_cookie: cookie.is_some(),
-                enabled: row.get::<_, i32>(6)? != 0,
-            })
-        },
-    ).map_err(|_| AppError::not_found("Site not found"))?;
-
-    Ok(Json(site))
+    let site = repository::get_site(&state.db.conn(), &id)?
+        .ok_or_else(site_not_found)?;
+
+    Ok(Json(site.into()))
 }
 
 /// Create or configure a site
+#[utoipa::path(
+    post,
+    path = "/sites",
+    tag = "sites",
+    request_body = CreateSiteRequest,
+    responses((status = 200, body = SiteResponse))
+)]
 pub async fn create(
     State(state): State<AppState>,
     Json(req): Json<CreateSiteRequest>,
 ) -> Result<Json<SiteResponse>, AppError> {
     // Check if site ID exists in built-in sites
     let builtin = builtin_sites();
-    let template = builtin.iter().find(|s| s.id == req.id);
+    let template = builtin.into_iter().find(|s| s.id == req.id);
 
-    let (base_url, template_type) = if let Some(t) = template {
+    let (base_url, template_type) = if let Some(ref t) = template {
         (
             req.base_url.clone().unwrap_or_else(|| t.base_url.clone()),
             t.template_type,
@@ -127,122 +337,214 @@ pub async fn create(
         )
     };
 
-    let conn = state.db.conn();
-
-    // Insert or update (upsert)
-    conn.execute(
-        "INSERT INTO sites (id, name, base_url, template_type, passkey, cookie_encrypted, enabled)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)
-         ON CONFLICT(id) DO UPDATE SET
-            name = excluded.name,
-            base_url = excluded.base_url,
-            passkey = COALESCE(excluded.passkey, passkey),
-            cookie_encrypted = COALESCE(excluded.cookie_encrypted, cookie_encrypted),
-            updated_at = datetime('now')",
-        rusqlite::params![
-            req.id,
-            req.name,
-            base_url,
-            template_type.to_string(),
-            req.passkey,
-            req.cookie,
-        ],
-    )?;
-
-    // Also register tracker domains if it's a built-in site
-    if let Some(t) = template {
-        for domain in &t.tracker_domains {
-            let _ = conn.execute(
-                "INSERT OR IGNORE INTO tracker_domains (domain, site_id) VALUES (?1, ?2)",
-                [domain, &req.id],
-            );
-        }
+    validate_headers(&req.extra_headers)?;
+    let proxy = req.proxy.filter(|p| !p.is_empty());
+    if let Some(ref proxy) = proxy {
+        validate_proxy(proxy)?;
     }
 
-    Ok(Json(SiteResponse {
+    let site = SiteConfig {
         id: req.id,
         name: req.name,
         base_url,
         template_type,
-        has_passkey: req.passkey.is_some(),
-        has_cookie: req.cookie.is_some(),
+        tracker_domains: Vec::new(),
+        download_pattern: template_type.default_download_pattern().to_string(),
+        passkey: req.passkey,
+        rsskey: req.rsskey.filter(|k| !k.is_empty()),
+        cookie: req.cookie,
         enabled: true,
-    }))
+        rate_limit_rpm: None,
+        extra_headers: req.extra_headers,
+        daily_download_limit: req.daily_download_limit,
+        announce_url: req.announce_url,
+        default_category: req.default_category,
+        default_save_path: req.default_save_path,
+        proxy,
+        site_username: req.site_username.filter(|u| !u.is_empty()),
+        site_password: req.site_password.filter(|p| !p.is_empty()),
+    };
+
+    let conn = state.db.conn();
+    repository::upsert_site(&conn, &site)?;
+
+    // Also register tracker domains if it's a built-in site
+    if let Some(t) = template {
+        if let Err(e) = repository::add_tracker_domains(&conn, &site.id, &t.tracker_domains) {
+            warn!("Failed to register tracker domains for {}: {}", site.id, e);
+        }
+    }
+
+    Ok(Json(site.into()))
 }
 
 /// Update a site
+#[utoipa::path(
+    put,
+    path = "/sites/{id}",
+    tag = "sites",
+    params(("id" = String, Path, description = "Site id")),
+    request_body = UpdateSiteRequest,
+    responses((status = 200, body = SiteResponse))
+)]
 pub async fn update(
     State(state): State<AppState>,
     Path(id): Path<String>,
     Json(req): Json<UpdateSiteRequest>,
 ) -> Result<Json<SiteResponse>, AppError> {
-    // Build and execute update in a scope to drop conn before calling get_one
-    {
-        let conn = state.db.conn();
-
-        // Build dynamic update query
-        let mut updates = Vec::new();
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-
-        if let Some(ref name) = req.name {
-            updates.push("name = ?");
-            params.push(Box::new(name.clone()));
-        }
-        if let Some(ref base_url) = req.base_url {
-            updates.push("base_url = ?");
-            params.push(Box::new(base_url.clone()));
-        }
-        if let Some(ref passkey) = req.passkey {
-            updates.push("passkey = ?");
-            params.push(Box::new(passkey.clone()));
-        }
-        if let Some(ref cookie) = req.cookie {
-            updates.push("cookie_encrypted = ?");
-            params.push(Box::new(cookie.clone()));
-        }
-        if let Some(enabled) = req.enabled {
-            updates.push("enabled = ?");
-            params.push(Box::new(enabled as i32));
-        }
+    let extra_headers = match req.extra_headers {
+        Some(ref headers) => Some(encode_headers(headers)?),
+        None => None,
+    };
 
-        if updates.is_empty() {
-            return Err(AppError::bad_request("No fields to update"));
+    if let Some(ref proxy) = req.proxy {
+        if !proxy.is_empty() {
+            validate_proxy(proxy)?;
         }
+    }
 
-        updates.push("updated_at = datetime('now')");
-        params.push(Box::new(id.clone()));
-
-        let sql = format!(
-            "UPDATE sites SET {} WHERE id = ?",
-            updates.join(", ")
-        );
+    // Empty values clear the optional settings
+    let update = SiteUpdate {
+        name: req.name,
+        base_url: req.base_url,
+        passkey: req.passkey,
+        rsskey: req.rsskey.map(|k| (!k.is_empty()).then_some(k)),
+        cookie: req.cookie,
+        enabled: req.enabled,
+        extra_headers,
+        daily_download_limit: req.daily_download_limit.map(|limit| (limit > 0).then_some(limit)),
+        announce_url: req.announce_url.map(|url| (!url.is_empty()).then_some(url)),
+        default_category: req.default_category.map(|c| (!c.is_empty()).then_some(c)),
+        default_save_path: req.default_save_path.map(|p| (!p.is_empty()).then_some(p)),
+        proxy: req.proxy.map(|p| (!p.is_empty()).then_some(p)),
+        site_username: req.site_username.map(|u| (!u.is_empty()).then_some(u)),
+        site_password: req.site_password.map(|p| (!p.is_empty()).then_some(p)),
+    };
 
-        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        let rows = conn.execute(&sql, params_refs.as_slice())?;
+    if update.is_empty() {
+        return Err(AppError::bad_request("No fields to update"));
+    }
 
-        if rows == 0 {
-            return Err(AppError::not_found("Site not found"));
-        }
-    } // conn is dropped here
+    let conn = state.db.conn();
+    if repository::update_site(&conn, &id, &update)? == 0 {
+        return Err(site_not_found());
+    }
 
-    // Fetch updated site
-    get_one(State(state), Path(id)).await
+    let site = repository::get_site(&conn, &id)?.ok_or_else(site_not_found)?;
+    Ok(Json(site.into()))
 }
 
 /// Delete a site
+#[utoipa::path(
+    delete,
+    path = "/sites/{id}",
+    tag = "sites",
+    params(("id" = String, Path, description = "Site id")),
+    responses((status = 200, body = Object))
+)]
 pub async fn remove(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    if repository::delete_site(&state.db.conn(), &id)? == 0 {
+        return Err(site_not_found());
+    }
+
+    // The site's index entries were removed by the cascade
+    state.index_service.mark_changed();
+
+    Ok(Json(serde_json::json!({"deleted": true})))
+}
+
+/// Enable or disable several sites at once
+///
+/// Sites already in the requested state are left untouched and not counted.
+#[utoipa::path(
+    post,
+    path = "/sites/bulk-enable",
+    tag = "sites",
+    request_body = BulkEnableRequest,
+    responses((status = 200, body = Object))
+)]
+pub async fn bulk_enable(
+    State(state): State<AppState>,
+    Json(req): Json<BulkEnableRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let changed = repository::set_sites_enabled(&state.db.conn(), &req.site_ids, req.enabled)?;
+
+    Ok(Json(serde_json::json!({"changed": changed, "enabled": req.enabled})))
+}
+
+/// Outcome of `sync_builtins`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SyncBuiltinsResult {
+    /// Configured builtin sites that were synced
+    pub synced: Vec<String>,
+    pub domains_added: usize,
+}
+
+/// Refresh configured builtin sites from the current builtin definitions
+///
+/// Sites set up with an older version miss tracker domains added since.
+/// Credentials and user settings are kept.
+#[utoipa::path(
+    post,
+    path = "/sites/sync-builtins",
+    tag = "sites",
+    responses((status = 200, body = SyncBuiltinsResult))
+)]
+pub async fn sync_builtins(
+    State(state): State<AppState>,
+) -> Result<Json<SyncBuiltinsResult>, AppError> {
     let conn = state.db.conn();
-    let rows = conn.execute("DELETE FROM sites WHERE id = ?1", [&id])?;
+    let tx = conn.unchecked_transaction()?;
+
+    let mut result = SyncBuiltinsResult {
+        synced: Vec::new(),
+        domains_added: 0,
+    };
+    for builtin in builtin_sites() {
+        if let Some(added) = repository::sync_builtin_site(&tx, &builtin)? {
+            result.synced.push(builtin.id);
+            result.domains_added += added;
+        }
+    }
+    tx.commit()?;
+
+    Ok(Json(result))
+}
+
+fn site_not_found() -> AppError {
+    AppError::not_found("Site not found").with_code(ErrorCode::SiteNotFound)
+}
 
-    if rows == 0 {
-        return Err(AppError::not_found("Site not found"));
+/// Reject header names or values that cannot be sent
+fn validate_headers(headers: &HashMap<String, String>) -> Result<(), AppError> {
+    for (name, value) in headers {
+        if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+            || reqwest::header::HeaderValue::from_str(value).is_err()
+        {
+            return Err(AppError::bad_request(format!("Invalid header: {}", name)).with_code(ErrorCode::InvalidHeader));
+        }
     }
+    Ok(())
+}
+
+/// Reject proxy URLs reqwest cannot use
+fn validate_proxy(proxy: &str) -> Result<(), AppError> {
+    reqwest::Proxy::all(proxy)
+        .map(|_| ())
+        .map_err(|e| AppError::bad_request(format!("Invalid proxy: {}", e)).with_code(ErrorCode::InvalidProxy))
+}
 
-    // Also remove tracker domain mappings
-    conn.execute("DELETE FROM tracker_domains WHERE site_id = ?1", [&id])?;
+/// Store headers as a JSON object, or NULL when there are none
+fn encode_headers(headers: &HashMap<String, String>) -> Result<Option<String>, AppError> {
+    validate_headers(headers)?;
+    if headers.is_empty() {
+        return Ok(None);
+    }
 
-    Ok(Json(serde_json::json!({"deleted": true})))
+    serde_json::to_string(headers)
+        .map(Some)
+        .map_err(|e| AppError::internal(e.to_string()))
 }
@@ -0,0 +1,85 @@
+//! WebSocket endpoint for live dashboard updates
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use crate::api::AppState;
+use crate::service::Event;
+
+/// Upgrade to a WebSocket that streams dashboard events
+///
+/// Sends a `snapshot` with the full dashboard stats on connect, then every
+/// service event as it happens, followed by fresh `stats` whenever the index
+/// changes or a reseed run finishes.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "system",
+    responses((status = 101, description = "Switching to the WebSocket protocol"))
+)]
+pub async fn ws(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState) {
+    // Subscribe before the snapshot so no event in between is missed
+    let mut events = state.events.subscribe();
+
+    if send_stats(&mut socket, &state, "snapshot").await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let refresh = matches!(event, Event::IndexChanged { .. } | Event::ReseedFinished { .. });
+                    if send_json(&mut socket, &json!(event)).await.is_err() {
+                        break;
+                    }
+                    if refresh && send_stats(&mut socket, &state, "stats").await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    // Too slow to keep up; resynchronize with a full snapshot
+                    warn!("WebSocket client lagged behind by {} events", skipped);
+                    if send_stats(&mut socket, &state, "snapshot").await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum; anything else from the client is ignored
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    debug!("WebSocket client disconnected");
+}
+
+async fn send_stats(socket: &mut WebSocket, state: &AppState, kind: &str) -> Result<(), axum::Error> {
+    let stats = match super::dashboard_stats(state) {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!("Failed to collect stats for WebSocket client: {:?}", e);
+            return Ok(());
+        }
+    };
+    send_json(socket, &json!({ "type": kind, "stats": stats })).await
+}
+
+async fn send_json(socket: &mut WebSocket, value: &serde_json::Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(value.to_string().into())).await
+}
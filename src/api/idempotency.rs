@@ -0,0 +1,102 @@
+//! Idempotency keys for requests that must not run twice
+//!
+//! A client sends the same `Idempotency-Key` header when it repeats a
+//! request, e.g. after a double-submit or a retry on a dropped connection.
+//! The first request with a key runs; repeats within the TTL get its result.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::OnceCell;
+
+/// Request header carrying the key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Results of keyed requests, kept for a TTL
+pub struct IdempotencyCache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry<T>>>,
+}
+
+struct Entry<T> {
+    created_at: Instant,
+    result: Arc<OnceCell<T>>,
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `run` unless a request with `key` already succeeded within the TTL
+    ///
+    /// A repeat that arrives while the first request is still running waits
+    /// for it. Failures are not kept, so a repeat after one runs again.
+    pub async fn run<F, Fut, E>(&self, key: &str, run: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let cell = {
+            let mut entries = self.entries.lock().unwrap();
+            // Requests still running are kept past the TTL; failed ones
+            // (never initialized and no longer held) are dropped
+            entries.retain(|_, entry| {
+                Arc::strong_count(&entry.result) > 1
+                    || (entry.result.initialized() && entry.created_at.elapsed() < self.ttl)
+            });
+            entries
+                .entry(key.to_string())
+                .or_insert_with(|| Entry {
+                    created_at: Instant::now(),
+                    result: Arc::new(OnceCell::new()),
+                })
+                .result
+                .clone()
+        };
+
+        cell.get_or_try_init(run).await.cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_repeated_key_runs_once() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let runs = AtomicUsize::new(0);
+        let run = || async {
+            let n = runs.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok::<_, ()>(n)
+        };
+
+        // Concurrent repeats wait for the first run
+        let (a, b) = tokio::join!(cache.run("key", run), cache.run("key", run));
+        assert_eq!((a, b), (Ok(0), Ok(0)));
+        assert_eq!(cache.run("key", run).await, Ok(0));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        assert_eq!(cache.run("other", run).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_failures_and_expired_keys_run_again() {
+        let cache = IdempotencyCache::new(Duration::from_millis(50));
+
+        assert_eq!(cache.run("key", || async { Err::<u32, _>("down") }).await, Err("down"));
+        assert_eq!(cache.run("key", || async { Ok::<_, &str>(1) }).await, Ok(1));
+        assert_eq!(cache.run("key", || async { Ok::<_, &str>(2) }).await, Ok(1));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(cache.run("key", || async { Ok::<_, &str>(3) }).await, Ok(3));
+    }
+}
@@ -0,0 +1,95 @@
+//! API middleware
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::{AppError, AppState, ErrorCode};
+
+/// Non-GET routes that are still allowed in read-only mode because they
+/// do not change any state
+const READ_ONLY_ALLOWED: &[(Method, &str)] = &[
+    (Method::POST, "/api/reseed/preview"),
+    (Method::POST, "/api/sites/test-all"),
+];
+
+/// Reject state-changing requests when `server.read_only` is set
+pub async fn read_only_guard(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.settings.server.read_only && !is_read_only_allowed(request.method(), request.uri().path()) {
+        return AppError::forbidden("Graft is running in read-only mode").with_code(ErrorCode::ReadOnly).into_response();
+    }
+
+    next.run(request).await
+}
+
+fn is_read_only_allowed(method: &Method, path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return !is_read_only_denied(path);
+    }
+
+    READ_ONLY_ALLOWED
+        .iter()
+        .any(|(m, p)| m == method && *p == path)
+        || is_client_test(method, path)
+}
+
+/// Reads refused in read-only mode because they hand out credentials or
+/// spend a site's download quota
+fn is_read_only_denied(path: &str) -> bool {
+    path == "/api/backup" || is_site_download(path)
+}
+
+/// `/api/sites/{id}/torrents/{torrent_id}/download`
+fn is_site_download(path: &str) -> bool {
+    path.strip_prefix("/api/sites/")
+        .and_then(|rest| rest.strip_suffix("/download"))
+        .is_some_and(|rest| matches!(rest.split('/').collect::<Vec<_>>()[..], [id, "torrents", torrent_id] if !id.is_empty() && !torrent_id.is_empty()))
+}
+
+/// Testing a client connection only reads from the client
+fn is_client_test(method: &Method, path: &str) -> bool {
+    *method == Method::POST
+        && path
+            .strip_prefix("/api/clients/")
+            .and_then(|rest| rest.strip_suffix("/test"))
+            .is_some_and(|id| !id.is_empty() && !id.contains('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_allows_reads_and_previews() {
+        assert!(is_read_only_allowed(&Method::GET, "/api/clients"));
+        assert!(is_read_only_allowed(&Method::GET, "/index.html"));
+        assert!(is_read_only_allowed(&Method::POST, "/api/reseed/preview"));
+        assert!(is_read_only_allowed(&Method::POST, "/api/clients/abc/test"));
+        assert!(is_read_only_allowed(&Method::POST, "/api/sites/test-all"));
+    }
+
+    #[test]
+    fn test_read_only_rejects_writes() {
+        assert!(!is_read_only_allowed(&Method::POST, "/api/reseed/execute"));
+        assert!(!is_read_only_allowed(&Method::DELETE, "/api/index"));
+        assert!(!is_read_only_allowed(&Method::DELETE, "/api/clients/abc"));
+        assert!(!is_read_only_allowed(&Method::PATCH, "/api/clients/abc/enabled"));
+        assert!(!is_read_only_allowed(&Method::POST, "/api/clients/a/b/test"));
+    }
+
+    #[test]
+    fn test_read_only_rejects_credential_reads() {
+        assert!(!is_read_only_allowed(&Method::GET, "/api/backup"));
+        assert!(!is_read_only_allowed(&Method::GET, "/api/backup/"));
+        assert!(!is_read_only_allowed(&Method::GET, "/api/sites/hdsky/torrents/42/download"));
+        assert!(is_read_only_allowed(&Method::GET, "/api/sites/hdsky/torrents"));
+    }
+}
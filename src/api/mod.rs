@@ -2,24 +2,45 @@
 
 mod error;
 pub mod handlers;
+mod idempotency;
+mod middleware;
+mod openapi;
 
 use axum::{
     Router,
-    routing::{get, post, put, delete},
+    extract::DefaultBodyLimit,
+    middleware::from_fn_with_state,
 };
 use rust_embed::RustEmbed;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
 use tower_http::{
     cors::CorsLayer,
     compression::CompressionLayer,
     trace::TraceLayer,
 };
 
-use crate::config::Settings;
-use crate::db::Database;
-use crate::service::{IndexService, ReseedService};
+use crate::client::{BitTorrentClient, ClientConfig};
+use crate::config::{ScoringSettings, Settings};
+use crate::db::{repository, Database, SqliteStore, Store};
+use crate::service::{
+    EventBus, FingerprintOptions, IndexService, RateLimiter, ReseedResult, ReseedService, ScoringWeights,
+};
+
+pub use error::{AppError, ErrorCode};
+pub use idempotency::{IdempotencyCache, IDEMPOTENCY_KEY_HEADER};
+
+/// Maximum accepted size of an uploaded index file
+const INDEX_FILE_BODY_LIMIT: usize = 256 * 1024 * 1024;
 
-pub use error::AppError;
+/// Maximum accepted size of an uploaded `.torrent` file
+const TORRENT_FILE_BODY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// How long an `Idempotency-Key` on reseed execute is remembered
+const EXECUTE_IDEMPOTENCY_TTL: Duration = Duration::from_secs(10 * 60);
 
 /// Embedded frontend assets
 #[derive(RustEmbed)]
@@ -30,65 +51,230 @@ struct WebAssets;
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
+    /// Index and history storage
+    pub store: Arc<dyn Store>,
     pub settings: Settings,
     pub index_service: Arc<IndexService>,
     pub reseed_service: Arc<ReseedService>,
+    /// Live events forwarded to WebSocket clients
+    pub events: EventBus,
+    /// Results of reseed executes by idempotency key
+    pub execute_results: Arc<IdempotencyCache<ReseedResult>>,
 }
 
 impl AppState {
     pub fn new(db: Database, settings: Settings) -> Self {
-        let index_service = Arc::new(IndexService::new(db.clone()));
-        let reseed_service = Arc::new(ReseedService::new(
-            db.clone(),
-            index_service.clone(),
-        ));
+        let events = EventBus::new();
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::new(db.clone()));
+        let rate_limiter = settings.reseed.global_rpm.map(|rpm| Arc::new(RateLimiter::per_minute(rpm)));
+        let index_service = Arc::new(
+            IndexService::new(store.clone())
+                .with_events(events.clone())
+                .with_rate_limiter(rate_limiter.clone())
+                .with_fingerprint_options(
+                    FingerprintOptions::new(&settings.fingerprint.ignore_extensions)
+                        .with_strip_root_dir(settings.fingerprint.strip_root_dir)
+                        .with_exclude_padding(settings.fingerprint.exclude_padding_files),
+                )
+                .with_require_same_media_type(settings.fingerprint.require_same_media_type)
+                .with_scoring(scoring_weights(&settings.fingerprint.scoring))
+                .with_max_candidates_per_bucket(
+                    Some(settings.fingerprint.max_candidates_per_bucket).filter(|&max| max > 0),
+                )
+                .with_subset_matching(settings.fingerprint.subset_matching),
+        );
+        let reseed_service = Arc::new(
+            ReseedService::new(db.clone(), store.clone(), index_service.clone())
+                .with_events(events.clone())
+                .with_rate_limiter(rate_limiter)
+                .with_site_auth_failure_limit(settings.reseed.site_auth_failure_limit)
+                .with_verify_save_path(settings.reseed.verify_save_path)
+                .with_min_confidence(settings.reseed.min_confidence)
+                .with_preview_cache_ttl(Duration::from_secs(settings.reseed.preview_cache_ttl_secs))
+                .with_record_runs(settings.reseed.record_runs)
+                .with_rewrite_announce(settings.reseed.rewrite_announce)
+                .with_verify_timeout(Duration::from_secs(settings.reseed.verify_timeout_secs))
+                .with_site_priority(settings.reseed.site_priority.clone())
+                .with_detail_fallback_clients(settings.reseed.detail_fallback_clients.clone())
+                .with_preview_concurrency(settings.reseed.preview_concurrency)
+                .with_user_agents(settings.reseed.user_agents.clone(), settings.reseed.user_agent_rotation),
+        );
 
         Self {
             db,
+            store,
             settings,
             index_service,
             reseed_service,
+            events,
+            execute_results: Arc::new(IdempotencyCache::new(EXECUTE_IDEMPOTENCY_TTL)),
         }
     }
+
+    /// Load a client's config, or a not-found error
+    pub fn client_config(&self, id: &str) -> Result<ClientConfig, AppError> {
+        repository::get_client(&self.db.conn(), id)?
+            .ok_or_else(|| AppError::not_found("Client not found").with_code(ErrorCode::ClientNotFound))
+    }
+
+    /// Load a client's config, refusing disabled clients
+    pub fn enabled_client_config(&self, id: &str) -> Result<ClientConfig, AppError> {
+        let config = self.client_config(id)?;
+
+        if !config.enabled {
+            return Err(AppError::bad_request(format!("Client is disabled: {}", config.name))
+                .with_code(ErrorCode::ClientDisabled));
+        }
+
+        Ok(config)
+    }
+
+    /// Clients from `reseed.detail_fallback_clients` to ask for torrent
+    /// details when `client_id` cannot return them
+    ///
+    /// Missing and disabled clients are skipped.
+    pub fn detail_fallback_clients(&self, client_id: &str) -> Result<Vec<Box<dyn BitTorrentClient>>, AppError> {
+        let conn = self.db.conn();
+        let mut clients = Vec::new();
+        for id in self.settings.reseed.detail_fallback_clients.iter().filter(|id| *id != client_id) {
+            match repository::get_client(&conn, id)? {
+                Some(config) if config.enabled => clients.push(config.create_client()),
+                _ => warn!("Skipping unavailable fallback client {}", id),
+            }
+        }
+        Ok(clients)
+    }
 }
 
-/// Create the application router
-pub fn create_router(state: AppState) -> Router {
-    let api_routes = Router::new()
+/// Matcher scoring weights from the settings, `None` when scoring is off
+fn scoring_weights(settings: &ScoringSettings) -> Option<ScoringWeights> {
+    settings.enabled.then_some(ScoringWeights {
+        total_size: settings.total_size,
+        largest_file: settings.largest_file,
+        file_count: settings.file_count,
+        name: settings.name,
+        min_score: settings.min_score,
+    })
+}
+
+/// API routes with their OpenAPI description, relative to `/api`
+fn api_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::with_openapi(openapi::ApiDoc::openapi())
         // Health check
-        .route("/health", get(handlers::health))
+        .routes(routes!(handlers::health))
+        .routes(routes!(handlers::version))
+        .routes(routes!(handlers::openapi))
 
         // Clients
-        .route("/clients", get(handlers::client::list).post(handlers::client::create))
-        .route("/clients/{id}", get(handlers::client::get_one).put(handlers::client::update).delete(handlers::client::remove))
-        .route("/clients/{id}/test", post(handlers::client::test))
-        .route("/clients/{id}/torrents", get(handlers::client::torrents))
+        .routes(routes!(handlers::client::list, handlers::client::create))
+        .routes(routes!(handlers::client::get_one, handlers::client::update, handlers::client::remove))
+        .routes(routes!(handlers::client::set_enabled))
+        .routes(routes!(handlers::client::test))
+        .routes(routes!(handlers::client::detect))
+        .routes(routes!(handlers::client::torrents))
+        .routes(routes!(handlers::client::torrent_detail))
 
         // Sites
-        .route("/sites", get(handlers::site::list).post(handlers::site::create))
-        .route("/sites/available", get(handlers::site::available))
-        .route("/sites/{id}", get(handlers::site::get_one).put(handlers::site::update).delete(handlers::site::remove))
+        .routes(routes!(handlers::site::list, handlers::site::create))
+        .routes(routes!(handlers::site::available))
+        .routes(routes!(handlers::site::test_all))
+        .routes(routes!(handlers::site::bulk_enable))
+        .routes(routes!(handlers::site::sync_builtins))
+        .routes(routes!(handlers::site::get_one, handlers::site::update, handlers::site::remove))
+        .routes(routes!(handlers::site::download_torrent))
 
         // Index
-        .route("/index/stats", get(handlers::index::stats))
-        .route("/index/import/{client_id}", post(handlers::index::import))
-        .route("/index", delete(handlers::index::clear_all))
-        .route("/index/{site_id}", delete(handlers::index::clear_site))
+        .routes(routes!(handlers::index::stats))
+        .routes(routes!(handlers::index::duplicates))
+        .routes(routes!(handlers::index::suggestions))
+        .routes(routes!(handlers::index::import))
+        .routes(routes!(handlers::index::import_all))
+        .routes(routes!(handlers::index::import_dir))
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(handlers::index::import_qb_export))
+                .routes(routes!(handlers::index::import_file))
+                .layer(DefaultBodyLimit::max(INDEX_FILE_BODY_LIMIT)),
+        )
+        .routes(routes!(handlers::index::export))
+        .routes(routes!(handlers::index::prune_preview, handlers::index::prune))
+        .routes(routes!(handlers::index::clear_all))
+        .routes(routes!(handlers::index::clear_site))
+        .routes(routes!(handlers::index::restore_site))
+
+        // Fingerprint diagnostics
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(handlers::fingerprint::analyze))
+                .layer(DefaultBodyLimit::max(TORRENT_FILE_BODY_LIMIT)),
+        )
 
         // Reseed
-        .route("/reseed/preview", post(handlers::reseed::preview))
-        .route("/reseed/execute", post(handlers::reseed::execute))
-        .route("/reseed/history", get(handlers::reseed::history))
+        .routes(routes!(handlers::reseed::preview))
+        .routes(routes!(handlers::reseed::execute))
+        .routes(routes!(handlers::reseed::history))
+        .routes(routes!(handlers::reseed::failure_stats))
+        .routes(routes!(handlers::reseed::runs))
+        .routes(routes!(handlers::reseed::pending))
+        .routes(routes!(handlers::reseed::approve))
+        .routes(routes!(handlers::reseed::reject))
 
         // Stats
-        .route("/stats", get(handlers::stats));
+        .routes(routes!(handlers::stats))
+        .routes(routes!(handlers::status))
 
-    Router::new()
+        // Backup
+        .routes(routes!(handlers::backup::backup))
+        .routes(routes!(handlers::ws::ws))
+}
+
+/// Create the application router
+pub fn create_router(state: AppState) -> Router {
+    let base_path = state.settings.server.normalized_base_path();
+    let (api_routes, _) = api_routes().split_for_parts();
+
+    let app = Router::new()
         .nest("/api", api_routes)
         // Serve static files
         .fallback(handlers::static_handler)
-        .with_state(state)
-        .layer(CorsLayer::permissive())
+        .layer(from_fn_with_state(state.clone(), middleware::read_only_guard))
+        .with_state(state);
+
+    // Behind a reverse proxy under a subpath, everything lives below it
+    let app = if base_path.is_empty() {
+        app
+    } else {
+        Router::new().nest(&base_path, app)
+    };
+
+    app.layer(CorsLayer::permissive())
         .layer(CompressionLayer::new())
         .layer(TraceLayer::new_for_http())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, http::StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_router_under_base_path() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let mut settings = Settings::default();
+        settings.server.base_path = "/graft".to_string();
+        let app = create_router(AppState::new(db, settings));
+
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(get("/graft/api/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(get("/graft/clients")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(get("/api/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
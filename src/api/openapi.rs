@@ -0,0 +1,122 @@
+//! OpenAPI description of the HTTP API
+//!
+//! Served at `GET /api/openapi.json` for third-party clients. The document
+//! is generated from the `#[utoipa::path]` annotations on the handlers as
+//! they are registered in [`super::api_routes`], so it cannot drift from
+//! the router.
+
+use utoipa::openapi::path::Operation;
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, Response, ResponseBuilder, Server};
+use utoipa::OpenApi;
+
+use super::error::ErrorBody;
+
+/// Document metadata; paths and their schemas are added by the router
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Graft API"),
+    components(schemas(ErrorBody)),
+    tags(
+        (name = "system", description = "Health, status and backups"),
+        (name = "clients", description = "Download clients"),
+        (name = "sites", description = "Tracker sites"),
+        (name = "index", description = "Torrent index"),
+        (name = "fingerprint", description = "Fingerprint diagnostics"),
+        (name = "reseed", description = "Reseed previews, runs and history"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document, with `api_url` (e.g. `/graft/api`) as the server
+pub fn document(api_url: &str) -> utoipa::openapi::OpenApi {
+    let mut doc = super::api_routes().into_openapi();
+    doc.servers = Some(vec![Server::new(api_url)]);
+
+    let error: RefOr<Response> = ResponseBuilder::new()
+        .description("Error with a message and, for known failures, a machine-readable code")
+        .content(
+            "application/json",
+            ContentBuilder::new()
+                .schema(Some(Ref::from_schema_name("ErrorBody")))
+                .build(),
+        )
+        .build()
+        .into();
+    for (path, item) in doc.paths.paths.iter_mut() {
+        let operations: [(&str, &mut Option<Operation>); 5] = [
+            ("get", &mut item.get),
+            ("put", &mut item.put),
+            ("post", &mut item.post),
+            ("delete", &mut item.delete),
+            ("patch", &mut item.patch),
+        ];
+        for (method, operation) in operations {
+            if let Some(operation) = operation {
+                // Handler names repeat across modules, so ids come from the route
+                operation.operation_id = Some(operation_id(method, path));
+                operation.responses.responses.insert("default".to_string(), error.clone());
+            }
+        }
+    }
+
+    doc
+}
+
+/// e.g. `post_reseed_pending_id_approve`
+fn operation_id(method: &str, path: &str) -> String {
+    let mut id = method.to_string();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        id.push('_');
+        id.extend(segment.chars().filter_map(|c| match c {
+            '{' | '}' => None,
+            '-' | '.' => Some('_'),
+            c => Some(c),
+        }));
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_document_describes_routes_and_schemas() {
+        let doc = serde_json::to_value(document("/graft/api")).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        assert_eq!(doc["servers"][0]["url"], "/graft/api");
+
+        let enable = &paths["/clients/{id}/enabled"]["patch"];
+        assert_eq!(enable["operationId"], "patch_clients_id_enabled");
+        assert_eq!(enable["parameters"][0]["name"], "id");
+        assert_eq!(
+            enable["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/SetEnabledRequest"
+        );
+        assert_eq!(
+            enable["responses"]["default"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorBody"
+        );
+
+        let execute = &paths["/reseed/execute"]["post"];
+        assert!(execute["parameters"].as_array().unwrap().iter().any(|p| p["in"] == "header"));
+
+        let history = &paths["/reseed/history"]["get"];
+        assert!(history["parameters"].as_array().unwrap().iter().any(|p| p["name"] == "status"));
+
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        for name in ["CreateClientRequest", "ExecuteRequest", "PreviewRequest", "ReseedResult", "ErrorCode"] {
+            assert!(schemas.contains_key(name), "{} missing from the schemas", name);
+        }
+
+        // Every operation has a distinct id
+        let ids: Vec<&Value> = paths
+            .values()
+            .flat_map(|item| item.as_object().unwrap().values())
+            .map(|operation| &operation["operationId"])
+            .collect();
+        let unique: std::collections::HashSet<_> = ids.iter().map(|id| id.as_str().unwrap()).collect();
+        assert_eq!(unique.len(), ids.len());
+    }
+}
@@ -6,12 +6,29 @@
 mod qbittorrent;
 mod transmission;
 
-pub use qbittorrent::QBittorrentClient;
+pub use qbittorrent::{parse_torrent_export, QBittorrentClient};
 pub use transmission::TransmissionClient;
 
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+/// Maximum number of characters of an unparseable body kept in errors
+const RESPONSE_SNIPPET_LEN: usize = 200;
+
+/// Timeout for the login step when a client does not set one
+pub const DEFAULT_LOGIN_TIMEOUT_SECS: u64 = 10;
+
+/// Extra login attempts after a timeout or connection error when a client
+/// does not set its own
+pub const DEFAULT_LOGIN_RETRIES: u32 = 2;
+
+/// Wait before the first login retry, doubled for each one after
+const LOGIN_RETRY_DELAY: Duration = Duration::from_millis(500);
 
 /// Unified error type for client operations
 #[derive(Debug, thiserror::Error)]
@@ -37,8 +54,63 @@ pub enum ClientError {
 
 pub type Result<T> = std::result::Result<T, ClientError>;
 
+impl ClientError {
+    /// Whether the request may succeed if sent again: the client was
+    /// unreachable or too slow to answer, e.g. a daemon waking up
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ClientError::ConnectionFailed(_) => true,
+            ClientError::RequestFailed(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+}
+
+/// Run a login step, retrying up to `retries` times on transient errors
+pub(crate) async fn retry_login<T, F, Fut>(retries: u32, mut login: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut delay = LOGIN_RETRY_DELAY;
+    for _ in 0..retries {
+        match login().await {
+            Err(e) if e.is_transient() => {
+                warn!("Client login failed, retrying in {:?}: {}", delay, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    login().await
+}
+
+/// Read a response body as JSON
+///
+/// Clients that are restarting often answer with an HTML page or a truncated
+/// body. Instead of an opaque decode error, this returns `InvalidResponse`
+/// with the parse error and the start of what was actually received.
+pub(crate) async fn read_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    let body = response.text().await?;
+    parse_json(&body)
+}
+
+fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T> {
+    serde_json::from_str(body).map_err(|e| {
+        let snippet: String = body
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(RESPONSE_SNIPPET_LEN)
+            .collect();
+        ClientError::InvalidResponse(format!("{} (received: {:?})", e, snippet))
+    })
+}
+
 /// BitTorrent client types
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ClientType {
     QBittorrent,
@@ -67,7 +139,7 @@ impl std::str::FromStr for ClientType {
 }
 
 /// Torrent state
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum TorrentState {
     Downloading,
@@ -77,11 +149,12 @@ pub enum TorrentState {
     Error,
     Queued,
     Stalled,
+    Moving,
     Unknown,
 }
 
 /// Information about a torrent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TorrentInfo {
     pub hash: String,
     pub name: String,
@@ -98,7 +171,7 @@ pub struct TorrentInfo {
 }
 
 /// Information about a file in a torrent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TorrentFile {
     pub name: String,
     pub size: u64,
@@ -113,23 +186,57 @@ pub struct AddTorrentOptions {
     pub tags: Vec<String>,
     pub paused: bool,
     pub skip_checking: bool,
+    /// Queue the torrent at the top instead of the bottom of the download queue
+    pub add_top_of_queue: bool,
+    /// Let the client place the torrent in the category's save path instead
+    /// of `save_path` (only applies when `category` is set)
+    pub use_category_save_path: bool,
+    /// Upload rate limit in bytes per second (unlimited when unset)
+    pub upload_limit: Option<u64>,
+    /// Download rate limit in bytes per second (unlimited when unset)
+    pub download_limit: Option<u64>,
+}
+
+/// Server-side torrent list filter; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+pub struct TorrentFilter {
+    pub category: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl TorrentFilter {
+    pub fn matches(&self, torrent: &TorrentInfo) -> bool {
+        self.category
+            .as_ref()
+            .is_none_or(|c| torrent.category.as_deref().unwrap_or_default() == c)
+            && self.tag.as_ref().is_none_or(|t| torrent.tags.contains(t))
+    }
 }
 
 /// Unified interface for BitTorrent clients
 #[async_trait]
 pub trait BitTorrentClient: Send + Sync {
-    /// Get the client type
-    fn client_type(&self) -> ClientType;
-
     /// Get the client ID
     fn client_id(&self) -> &str;
 
+    /// Directory the client's save paths are organized under, if configured
+    fn download_root(&self) -> Option<&str>;
+
     /// Test the connection to the client
     async fn test_connection(&self) -> Result<bool>;
 
     /// Get all torrents
     async fn get_torrents(&self) -> Result<Vec<TorrentInfo>>;
 
+    /// Get the torrents matching `filter`
+    ///
+    /// Clients that cannot filter on their side fetch everything and filter here.
+    async fn get_torrents_filtered(&self, filter: &TorrentFilter) -> Result<Vec<TorrentInfo>> {
+        let mut torrents = self.get_torrents().await?;
+        torrents.retain(|t| filter.matches(t));
+        Ok(torrents)
+    }
+
     /// Get a specific torrent by hash
     async fn get_torrent(&self, hash: &str) -> Result<Option<TorrentInfo>>;
 
@@ -142,17 +249,18 @@ pub trait BitTorrentClient: Send + Sync {
     /// Add a torrent from bytes
     async fn add_torrent(&self, torrent_bytes: &[u8], options: AddTorrentOptions) -> Result<String>;
 
-    /// Remove a torrent
-    async fn remove_torrent(&self, hash: &str, delete_files: bool) -> Result<()>;
-
-    /// Pause a torrent
-    async fn pause_torrent(&self, hash: &str) -> Result<()>;
-
-    /// Resume a torrent
-    async fn resume_torrent(&self, hash: &str) -> Result<()>;
-
     /// Force recheck a torrent
     async fn recheck_torrent(&self, hash: &str) -> Result<()>;
+
+    /// Export the .torrent file of a torrent
+    ///
+    /// Returns `NotSupported` for clients that cannot hand out torrent files.
+    async fn export_torrent(&self, hash: &str) -> Result<Vec<u8>>;
+
+    /// Add tracker URLs to an existing torrent
+    async fn add_trackers(&self, _hash: &str, _urls: &[String]) -> Result<()> {
+        Err(ClientError::NotSupported)
+    }
 }
 
 /// Client configuration
@@ -166,6 +274,20 @@ pub struct ClientConfig {
     pub username: Option<String>,
     pub password: Option<String>,
     pub use_https: bool,
+    /// Label prefix used to emulate categories on Transmission
+    /// (defaults to `cat:` when unset)
+    pub category_label_prefix: Option<String>,
+    /// Disabled clients keep their config but are refused for imports and reseeds
+    pub enabled: bool,
+    /// Timeout for the login step in seconds, separate from the request
+    /// timeout (`DEFAULT_LOGIN_TIMEOUT_SECS` when unset)
+    pub login_timeout_secs: Option<u64>,
+    /// Login retries after a timeout or connection error
+    /// (`DEFAULT_LOGIN_RETRIES` when unset)
+    pub login_retries: Option<u32>,
+    /// Root directory of the client's downloads, used to translate save
+    /// paths between clients whose roots differ
+    pub download_root: Option<String>,
 }
 
 impl ClientConfig {
@@ -177,9 +299,144 @@ impl ClientConfig {
         }
     }
 
+    pub fn login_timeout(&self) -> Duration {
+        Duration::from_secs(self.login_timeout_secs.unwrap_or(DEFAULT_LOGIN_TIMEOUT_SECS))
+    }
+
+    pub fn login_retries(&self) -> u32 {
+        self.login_retries.unwrap_or(DEFAULT_LOGIN_RETRIES)
+    }
+
     /// Get the base URL for the client
     pub fn base_url(&self) -> String {
         let scheme = if self.use_https { "https" } else { "http" };
         format!("{}://{}:{}", scheme, self.host, self.port)
     }
+
+    /// Find out which client is listening at the configured address
+    ///
+    /// Probes the qBittorrent Web API and the Transmission RPC endpoint
+    /// without logging in. Returns `ConnectionFailed` if neither answers
+    /// like the expected client.
+    pub async fn detect_type(&self) -> Result<ClientType> {
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()?;
+        let base_url = self.base_url();
+
+        let qbittorrent = http
+            .get(format!("{}/api/v2/app/version", base_url))
+            .send()
+            .await
+            .ok()
+            .map(|r| r.status());
+        let transmission = http
+            .post(format!("{}/transmission/rpc", base_url))
+            .send()
+            .await
+            .ok()
+            .map(|r| (r.status(), r.headers().contains_key("X-Transmission-Session-Id")));
+
+        detect_from_probes(qbittorrent, transmission).ok_or_else(|| {
+            ClientError::ConnectionFailed(format!(
+                "No qBittorrent or Transmission found at {}",
+                base_url
+            ))
+        })
+    }
+}
+
+/// Decide the client type from the probe responses
+///
+/// qBittorrent answers its version endpoint with 200, or 403 before login.
+/// Transmission answers RPC calls without a session ID with 409 and a
+/// session header, or 401 when authentication is required.
+fn detect_from_probes(
+    qbittorrent: Option<reqwest::StatusCode>,
+    transmission: Option<(reqwest::StatusCode, bool)>,
+) -> Option<ClientType> {
+    use reqwest::StatusCode;
+
+    if matches!(qbittorrent, Some(StatusCode::OK | StatusCode::FORBIDDEN)) {
+        return Some(ClientType::QBittorrent);
+    }
+
+    match transmission {
+        Some((StatusCode::CONFLICT | StatusCode::UNAUTHORIZED, _)) | Some((_, true)) => {
+            Some(ClientType::Transmission)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_torrent_filter_matches() {
+        let torrent = TorrentInfo {
+            hash: "abc".to_string(),
+            name: "Movie".to_string(),
+            size: 1000,
+            progress: 1.0,
+            state: TorrentState::Seeding,
+            save_path: "/data".to_string(),
+            category: Some("movies".to_string()),
+            tags: vec!["hdsky".to_string()],
+            tracker: None,
+            trackers: Vec::new(),
+            added_on: None,
+            files: Vec::new(),
+        };
+
+        assert!(TorrentFilter::default().matches(&torrent));
+        let filter = |category: Option<&str>, tag: Option<&str>| TorrentFilter {
+            category: category.map(String::from),
+            tag: tag.map(String::from),
+        };
+        assert!(filter(Some("movies"), Some("hdsky")).matches(&torrent));
+        assert!(!filter(Some("tv"), None).matches(&torrent));
+        assert!(!filter(None, Some("ourbits")).matches(&torrent));
+        // An empty category selects uncategorized torrents, as in qBittorrent
+        assert!(!filter(Some(""), None).matches(&torrent));
+    }
+
+    #[test]
+    fn test_detect_from_probes() {
+        assert_eq!(
+            detect_from_probes(Some(StatusCode::FORBIDDEN), Some((StatusCode::NOT_FOUND, false))),
+            Some(ClientType::QBittorrent)
+        );
+        assert_eq!(
+            detect_from_probes(Some(StatusCode::NOT_FOUND), Some((StatusCode::CONFLICT, true))),
+            Some(ClientType::Transmission)
+        );
+        assert_eq!(
+            detect_from_probes(None, Some((StatusCode::UNAUTHORIZED, false))),
+            Some(ClientType::Transmission)
+        );
+        assert_eq!(detect_from_probes(Some(StatusCode::NOT_FOUND), None), None);
+    }
+
+    #[test]
+    fn test_parse_json_reports_received_body() {
+        let body = "<html>\n  <body>qBittorrent is starting</body>\n</html>";
+        let err = parse_json::<Vec<String>>(body).unwrap_err();
+
+        match err {
+            ClientError::InvalidResponse(msg) => {
+                assert!(msg.contains("<html> <body>qBittorrent is starting</body> </html>"), "{}", msg);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_json_truncates_snippet() {
+        let body = format!("[\"{}", "x".repeat(1000));
+        let err = parse_json::<Vec<String>>(&body).unwrap_err();
+        assert!(err.to_string().len() < RESPONSE_SNIPPET_LEN + 200);
+    }
 }
@@ -4,19 +4,29 @@
 //! Reference: https://github.com/qbittorrent/qBittorrent/wiki/WebUI-API-(qBittorrent-4.1)
 
 use super::{
-    AddTorrentOptions, BitTorrentClient, ClientConfig, ClientError, ClientType, Result,
-    TorrentFile, TorrentInfo, TorrentState,
+    read_json, retry_login, AddTorrentOptions, BitTorrentClient, ClientConfig, ClientError,
+    Result, TorrentFile, TorrentFilter, TorrentInfo, TorrentState,
 };
+use crate::utils::torrent::info_hash;
 use async_trait::async_trait;
 use reqwest::{multipart, Client, StatusCode};
 use serde::Deserialize;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
 pub struct QBittorrentClient {
     config: ClientConfig,
     http: Client,
     cookie: Arc<RwLock<Option<String>>>,
+    /// Held for the duration of a login, so concurrent requests share one
+    /// login instead of tripping qBittorrent's failed-login ban
+    login_state: Mutex<LoginState>,
+}
+
+#[derive(Debug, Default)]
+struct LoginState {
+    attempts: u64,
+    last_failed: bool,
 }
 
 impl QBittorrentClient {
@@ -31,6 +41,7 @@ impl QBittorrentClient {
             config,
             http,
             cookie: Arc::new(RwLock::new(None)),
+            login_state: Mutex::new(LoginState::default()),
         }
     }
 
@@ -46,7 +57,13 @@ impl QBittorrentClient {
             ("password", self.config.password.as_deref().unwrap_or("")),
         ];
 
-        let response = self.http.post(&url).form(&params).send().await?;
+        let response = self
+            .http
+            .post(&url)
+            .form(&params)
+            .timeout(self.config.login_timeout())
+            .send()
+            .await?;
 
         if response.status() == StatusCode::FORBIDDEN {
             return Err(ClientError::AuthenticationFailed);
@@ -58,7 +75,7 @@ impl QBittorrentClient {
         }
 
         // Extract SID cookie
-        if let Some(cookie) = self.http.get(&self.api_url("/app/version")).send().await?.headers().get("set-cookie") {
+        if let Some(cookie) = self.http.get(self.api_url("/app/version")).send().await?.headers().get("set-cookie") {
             if let Ok(cookie_str) = cookie.to_str() {
                 let mut cookie_guard = self.cookie.write().await;
                 *cookie_guard = Some(cookie_str.to_string());
@@ -68,12 +85,38 @@ impl QBittorrentClient {
         Ok(())
     }
 
+    /// Log in, unless another caller has done so since attempt `seen`
+    ///
+    /// Callers that waited on someone else's login get its outcome rather
+    /// than sending their own.
+    async fn login_once(&self, seen: u64) -> Result<()> {
+        let mut state = self.login_state.lock().await;
+        if state.attempts != seen {
+            return if state.last_failed {
+                Err(ClientError::AuthenticationFailed)
+            } else {
+                Ok(())
+            };
+        }
+
+        let result = retry_login(self.config.login_retries(), || self.login()).await;
+        state.attempts += 1;
+        state.last_failed = result.is_err();
+        result
+    }
+
+    async fn login_attempts(&self) -> u64 {
+        self.login_state.lock().await.attempts
+    }
+
     async fn ensure_logged_in(&self) -> Result<()> {
+        let seen = self.login_attempts().await;
+
         // Try a simple request to check if we're logged in
-        let response = self.http.get(&self.api_url("/app/version")).send().await?;
+        let response = self.http.get(self.api_url("/app/version")).send().await?;
 
         if response.status() == StatusCode::FORBIDDEN {
-            self.login().await?;
+            self.login_once(seen).await?;
         }
 
         Ok(())
@@ -82,27 +125,41 @@ impl QBittorrentClient {
 
 #[async_trait]
 impl BitTorrentClient for QBittorrentClient {
-    fn client_type(&self) -> ClientType {
-        ClientType::QBittorrent
-    }
-
     fn client_id(&self) -> &str {
         &self.config.id
     }
 
+    fn download_root(&self) -> Option<&str> {
+        self.config.download_root.as_deref()
+    }
+
     async fn test_connection(&self) -> Result<bool> {
-        self.login().await?;
+        let seen = self.login_attempts().await;
+        self.login_once(seen).await?;
 
-        let response = self.http.get(&self.api_url("/app/version")).send().await?;
+        let response = self.http.get(self.api_url("/app/version")).send().await?;
 
         Ok(response.status().is_success())
     }
 
     async fn get_torrents(&self) -> Result<Vec<TorrentInfo>> {
+        self.get_torrents_filtered(&TorrentFilter::default()).await
+    }
+
+    async fn get_torrents_filtered(&self, filter: &TorrentFilter) -> Result<Vec<TorrentInfo>> {
         self.ensure_logged_in().await?;
 
+        // Let qBittorrent filter, so large instances don't send every torrent
+        let mut params = Vec::new();
+        if let Some(ref category) = filter.category {
+            params.push(("category", category.as_str()));
+        }
+        if let Some(ref tag) = filter.tag {
+            params.push(("tag", tag.as_str()));
+        }
+
         let url = self.api_url("/torrents/info");
-        let response = self.http.get(&url).send().await?;
+        let response = self.http.get(&url).query(&params).send().await?;
 
         if !response.status().is_success() {
             return Err(ClientError::InvalidResponse(format!(
@@ -111,7 +168,7 @@ impl BitTorrentClient for QBittorrentClient {
             )));
         }
 
-        let torrents: Vec<QBTorrent> = response.json().await?;
+        let torrents: Vec<QBTorrent> = read_json(response).await?;
 
         let mut result = Vec::with_capacity(torrents.len());
         for t in torrents {
@@ -131,7 +188,7 @@ impl BitTorrentClient for QBittorrentClient {
             return Ok(None);
         }
 
-        let torrents: Vec<QBTorrent> = response.json().await?;
+        let torrents: Vec<QBTorrent> = read_json(response).await?;
 
         Ok(torrents.into_iter().next().map(|t| t.into()))
     }
@@ -153,7 +210,7 @@ impl BitTorrentClient for QBittorrentClient {
             )));
         }
 
-        let files: Vec<QBTorrentFile> = response.json().await?;
+        let files: Vec<QBTorrentFile> = read_json(response).await?;
 
         Ok(files.into_iter().map(|f| f.into()).collect())
     }
@@ -175,12 +232,12 @@ impl BitTorrentClient for QBittorrentClient {
             )));
         }
 
-        let trackers: Vec<QBTracker> = response.json().await?;
+        let trackers: Vec<QBTracker> = read_json(response).await?;
 
         Ok(trackers
             .into_iter()
-            .filter(|t| !t.url.is_empty() && t.url != "** [DHT] **" && t.url != "** [PeX] **")
             .map(|t| t.url)
+            .filter(|url| is_tracker_url(url))
             .collect())
     }
 
@@ -196,7 +253,11 @@ impl BitTorrentClient for QBittorrentClient {
 
         let mut form = multipart::Form::new().part("torrents", file_part);
 
-        if let Some(ref path) = options.save_path {
+        // With automatic torrent management qBittorrent resolves the save path
+        // from the category; an explicit savepath would override it
+        if options.use_category_save_path && options.category.is_some() {
+            form = form.text("autoTMM", "true");
+        } else if let Some(ref path) = options.save_path {
             form = form.text("savepath", path.clone());
         }
 
@@ -216,30 +277,21 @@ impl BitTorrentClient for QBittorrentClient {
             form = form.text("skip_checking", "true");
         }
 
-        let response = self.http.post(&url).multipart(form).send().await?;
-
-        if !response.status().is_success() {
-            return Err(ClientError::InvalidResponse(format!(
-                "Status: {}",
-                response.status()
-            )));
+        if let Some(limit) = options.upload_limit {
+            form = form.text("upLimit", limit.to_string());
         }
 
-        // qBittorrent doesn't return the hash directly, we need to parse the torrent
-        // For now, return empty string - caller should use torrent parsing to get hash
-        Ok(String::new())
-    }
-
-    async fn remove_torrent(&self, hash: &str, delete_files: bool) -> Result<()> {
-        self.ensure_logged_in().await?;
+        if let Some(limit) = options.download_limit {
+            form = form.text("dlLimit", limit.to_string());
+        }
 
-        let url = self.api_url("/torrents/delete");
-        let params = [
-            ("hashes", hash),
-            ("deleteFiles", if delete_files { "true" } else { "false" }),
-        ];
+        // Always sent so the client's global queue preference doesn't apply
+        form = form.text(
+            "addToTopOfQueue",
+            if options.add_top_of_queue { "true" } else { "false" },
+        );
 
-        let response = self.http.post(&url).form(&params).send().await?;
+        let response = self.http.post(&url).multipart(form).send().await?;
 
         if !response.status().is_success() {
             return Err(ClientError::InvalidResponse(format!(
@@ -248,13 +300,14 @@ impl BitTorrentClient for QBittorrentClient {
             )));
         }
 
-        Ok(())
+        // qBittorrent doesn't return the hash, so take it from the torrent itself
+        Ok(info_hash(torrent_bytes).unwrap_or_default())
     }
 
-    async fn pause_torrent(&self, hash: &str) -> Result<()> {
+    async fn recheck_torrent(&self, hash: &str) -> Result<()> {
         self.ensure_logged_in().await?;
 
-        let url = self.api_url("/torrents/pause");
+        let url = self.api_url("/torrents/recheck");
         let params = [("hashes", hash)];
 
         let response = self.http.post(&url).form(&params).send().await?;
@@ -269,13 +322,16 @@ impl BitTorrentClient for QBittorrentClient {
         Ok(())
     }
 
-    async fn resume_torrent(&self, hash: &str) -> Result<()> {
+    async fn export_torrent(&self, hash: &str) -> Result<Vec<u8>> {
         self.ensure_logged_in().await?;
 
-        let url = self.api_url("/torrents/resume");
-        let params = [("hashes", hash)];
+        // Available since qBittorrent 4.5
+        let url = format!("{}?hash={}", self.api_url("/torrents/export"), hash);
+        let response = self.http.get(&url).send().await?;
 
-        let response = self.http.post(&url).form(&params).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(ClientError::TorrentNotFound(hash.to_string()));
+        }
 
         if !response.status().is_success() {
             return Err(ClientError::InvalidResponse(format!(
@@ -284,17 +340,22 @@ impl BitTorrentClient for QBittorrentClient {
             )));
         }
 
-        Ok(())
+        Ok(response.bytes().await?.to_vec())
     }
 
-    async fn recheck_torrent(&self, hash: &str) -> Result<()> {
+    async fn add_trackers(&self, hash: &str, urls: &[String]) -> Result<()> {
         self.ensure_logged_in().await?;
 
-        let url = self.api_url("/torrents/recheck");
-        let params = [("hashes", hash)];
+        let url = self.api_url("/torrents/addTrackers");
+        let joined = urls.join("\n");
+        let params = [("hash", hash), ("urls", joined.as_str())];
 
         let response = self.http.post(&url).form(&params).send().await?;
 
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(ClientError::TorrentNotFound(hash.to_string()));
+        }
+
         if !response.status().is_success() {
             return Err(ClientError::InvalidResponse(format!(
                 "Status: {}",
@@ -320,20 +381,48 @@ struct QBTorrent {
     tags: Option<String>,
     tracker: Option<String>,
     added_on: Option<i64>,
+    /// Only present in exports that embed the file list
+    #[serde(default)]
+    files: Vec<QBTorrentFile>,
+    /// Only present in exports that embed the tracker list
+    #[serde(default)]
+    trackers: Vec<QBTracker>,
+}
+
+/// Parse a saved `/api/v2/torrents/info` response (a JSON array of
+/// torrents), optionally with `files` and `trackers` embedded per torrent
+pub fn parse_torrent_export(json: &[u8]) -> serde_json::Result<Vec<TorrentInfo>> {
+    let torrents: Vec<QBTorrent> = serde_json::from_slice(json)?;
+    Ok(torrents.into_iter().map(TorrentInfo::from).collect())
+}
+
+/// Whether a tracker list entry is a real tracker rather than the DHT, PeX
+/// or LSD pseudo-entries
+fn is_tracker_url(url: &str) -> bool {
+    !url.is_empty() && !url.starts_with("** [")
+}
+
+/// Map a qBittorrent state string to a `TorrentState`
+///
+/// Covers the states documented for the WebUI API, including the `stopped*`
+/// names that replaced `paused*` in qBittorrent 5.0.
+fn map_state(state: &str) -> TorrentState {
+    match state {
+        "downloading" | "forcedDL" | "metaDL" | "forcedMetaDL" | "allocating" => TorrentState::Downloading,
+        "uploading" | "forcedUP" | "stalledUP" => TorrentState::Seeding,
+        "pausedDL" | "pausedUP" | "stoppedDL" | "stoppedUP" => TorrentState::Paused,
+        "checkingDL" | "checkingUP" | "checkingResumeData" => TorrentState::Checking,
+        "error" | "missingFiles" => TorrentState::Error,
+        "queuedDL" | "queuedUP" => TorrentState::Queued,
+        "stalledDL" => TorrentState::Stalled,
+        "moving" => TorrentState::Moving,
+        _ => TorrentState::Unknown,
+    }
 }
 
 impl From<QBTorrent> for TorrentInfo {
     fn from(t: QBTorrent) -> Self {
-        let state = match t.state.as_str() {
-            "downloading" | "forcedDL" | "metaDL" | "allocating" => TorrentState::Downloading,
-            "uploading" | "forcedUP" | "stalledUP" => TorrentState::Seeding,
-            "pausedDL" | "pausedUP" => TorrentState::Paused,
-            "checkingDL" | "checkingUP" | "checkingResumeData" => TorrentState::Checking,
-            "error" | "missingFiles" => TorrentState::Error,
-            "queuedDL" | "queuedUP" => TorrentState::Queued,
-            "stalledDL" => TorrentState::Stalled,
-            _ => TorrentState::Unknown,
-        };
+        let state = map_state(&t.state);
 
         let tags = t
             .tags
@@ -354,9 +443,15 @@ impl From<QBTorrent> for TorrentInfo {
             category: t.category,
             tags,
             tracker: t.tracker,
-            trackers: Vec::new(), // Will be fetched separately if needed
+            // Fetched separately from a live client; exports may embed them
+            trackers: t
+                .trackers
+                .into_iter()
+                .map(|t| t.url)
+                .filter(|url| is_tracker_url(url))
+                .collect(),
             added_on,
-            files: Vec::new(), // Will be fetched separately if needed
+            files: t.files.into_iter().map(TorrentFile::from).collect(),
         }
     }
 }
@@ -382,3 +477,151 @@ impl From<QBTorrentFile> for TorrentFile {
 struct QBTracker {
     url: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ClientType;
+
+    #[test]
+    fn test_parse_torrent_export() {
+        let json = br#"[
+            {"hash": "ABC", "name": "Movie", "size": 1000, "progress": 1.0, "state": "stalledUP",
+             "save_path": "/data", "category": "movies", "tags": "a, b", "tracker": "https://hdsky.me/announce",
+             "added_on": 1700000000, "num_seeds": 3,
+             "files": [{"name": "Movie/movie.mkv", "size": 1000, "progress": 1.0}],
+             "trackers": [{"url": "** [DHT] **"}, {"url": "https://hdsky.me/announce"}]},
+            {"hash": "def", "name": "Show", "size": 2000, "progress": 0.5, "state": "pausedDL",
+             "save_path": "/data", "category": null, "tags": "", "tracker": "", "added_on": null}
+        ]"#;
+
+        let torrents = parse_torrent_export(json).unwrap();
+        assert_eq!(torrents.len(), 2);
+        assert_eq!(torrents[0].hash, "abc");
+        assert_eq!(torrents[0].state, TorrentState::Seeding);
+        assert_eq!(torrents[0].files.len(), 1);
+        assert_eq!(torrents[0].trackers, vec!["https://hdsky.me/announce".to_string()]);
+        assert!(torrents[1].files.is_empty());
+        assert!(torrents[1].added_on.is_none());
+
+        assert!(parse_torrent_export(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_map_all_documented_states() {
+        let cases = [
+            ("error", TorrentState::Error),
+            ("missingFiles", TorrentState::Error),
+            ("uploading", TorrentState::Seeding),
+            ("pausedUP", TorrentState::Paused),
+            ("stoppedUP", TorrentState::Paused),
+            ("queuedUP", TorrentState::Queued),
+            ("stalledUP", TorrentState::Seeding),
+            ("checkingUP", TorrentState::Checking),
+            ("forcedUP", TorrentState::Seeding),
+            ("allocating", TorrentState::Downloading),
+            ("downloading", TorrentState::Downloading),
+            ("metaDL", TorrentState::Downloading),
+            ("forcedMetaDL", TorrentState::Downloading),
+            ("pausedDL", TorrentState::Paused),
+            ("stoppedDL", TorrentState::Paused),
+            ("queuedDL", TorrentState::Queued),
+            ("stalledDL", TorrentState::Stalled),
+            ("checkingDL", TorrentState::Checking),
+            ("forcedDL", TorrentState::Downloading),
+            ("checkingResumeData", TorrentState::Checking),
+            ("moving", TorrentState::Moving),
+            ("unknown", TorrentState::Unknown),
+        ];
+
+        for (state, expected) in cases {
+            assert_eq!(map_state(state), expected, "state {}", state);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_logins_share_one_attempt() {
+        use axum::{routing::{get, post}, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let logins = Arc::new(AtomicUsize::new(0));
+        let counter = logins.clone();
+        let app = Router::new()
+            // Never logged in, so every check asks for a login
+            .route("/api/v2/app/version", get(|| async { StatusCode::FORBIDDEN }))
+            .route(
+                "/api/v2/auth/login",
+                post(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    "Ok."
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = QBittorrentClient::new(ClientConfig {
+            id: "qb".to_string(),
+            name: "qb".to_string(),
+            client_type: ClientType::QBittorrent,
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            use_https: false,
+            category_label_prefix: None,
+            enabled: true,
+            login_timeout_secs: None,
+            login_retries: None,
+            download_root: None,
+        });
+
+        let results = futures::future::join_all((0..5).map(|_| client.ensure_logged_in())).await;
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(logins.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_login_is_retried() {
+        use axum::{routing::{get, post}, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let logins = Arc::new(AtomicUsize::new(0));
+        let counter = logins.clone();
+        let app = Router::new()
+            .route("/api/v2/app/version", get(|| async { "v4.6.0" }))
+            .route(
+                "/api/v2/auth/login",
+                post(move || async move {
+                    // The first login outlasts the login timeout
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+                    }
+                    "Ok."
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = QBittorrentClient::new(ClientConfig {
+            id: "qb".to_string(),
+            name: "qb".to_string(),
+            client_type: ClientType::QBittorrent,
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            use_https: false,
+            category_label_prefix: None,
+            enabled: true,
+            login_timeout_secs: Some(1),
+            login_retries: Some(1),
+            download_root: None,
+        });
+
+        assert!(client.test_connection().await.unwrap());
+        assert_eq!(logins.load(Ordering::SeqCst), 2);
+    }
+}
@@ -4,21 +4,37 @@
 //! Reference: https://github.com/transmission/transmission/blob/main/docs/rpc-spec.md
 
 use super::{
-    AddTorrentOptions, BitTorrentClient, ClientConfig, ClientError, ClientType, Result,
-    TorrentFile, TorrentInfo, TorrentState,
+    read_json, retry_login, AddTorrentOptions, BitTorrentClient, ClientConfig, ClientError,
+    Result, TorrentFile, TorrentInfo, TorrentState,
 };
 use async_trait::async_trait;
 use base64::Engine;
 use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::json;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{OnceCell, RwLock};
+use tracing::warn;
+
+/// Label prefix used to emulate categories when none is configured
+const DEFAULT_CATEGORY_LABEL_PREFIX: &str = "cat:";
+
+/// Fields requested for torrent listings
+const TORRENT_FIELDS: [&str; 11] = [
+    "id", "hashString", "name", "totalSize", "percentDone",
+    "status", "downloadDir", "labels", "trackers", "addedDate", "files",
+];
+
+/// First RPC version whose `torrent-get` accepts `format: "table"`
+/// (Transmission 3.00)
+const TABLE_FORMAT_RPC_VERSION: i64 = 16;
 
 pub struct TransmissionClient {
     config: ClientConfig,
     http: Client,
     session_id: Arc<RwLock<Option<String>>>,
+    /// Daemon RPC version, fetched on first use
+    rpc_version: OnceCell<i64>,
 }
 
 impl TransmissionClient {
@@ -32,18 +48,63 @@ impl TransmissionClient {
             config,
             http,
             session_id: Arc::new(RwLock::new(None)),
+            rpc_version: OnceCell::new(),
         }
     }
 
+    fn category_label_prefix(&self) -> &str {
+        self.config
+            .category_label_prefix
+            .as_deref()
+            .unwrap_or(DEFAULT_CATEGORY_LABEL_PREFIX)
+    }
+
     fn rpc_url(&self) -> String {
         format!("{}/transmission/rpc", self.config.base_url())
     }
 
+    fn with_auth(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let (Some(ref username), Some(ref password)) =
+            (&self.config.username, &self.config.password)
+        {
+            request = request.basic_auth(username, Some(password));
+        }
+        request
+    }
+
+    /// Fetch a CSRF session ID, the login step of the Transmission RPC
+    ///
+    /// Uses the login timeout and retries, so a daemon that is slow to wake
+    /// doesn't fail the first call.
+    async fn open_session(&self) -> Result<()> {
+        retry_login(self.config.login_retries(), || async {
+            let response = self
+                .with_auth(self.http.post(self.rpc_url()))
+                .json(&json!({ "method": "session-get", "arguments": { "fields": ["rpc-version"] } }))
+                .timeout(self.config.login_timeout())
+                .send()
+                .await?;
+
+            if response.status() == StatusCode::UNAUTHORIZED {
+                return Err(ClientError::AuthenticationFailed);
+            }
+            if let Some(session_id) = response.headers().get("X-Transmission-Session-Id") {
+                *self.session_id.write().await = Some(session_id.to_str().unwrap_or("").to_string());
+            }
+            Ok(())
+        })
+        .await
+    }
+
     async fn rpc_call<T: for<'de> Deserialize<'de>>(
         &self,
         method: &str,
         arguments: serde_json::Value,
     ) -> Result<T> {
+        if self.session_id.read().await.is_none() {
+            self.open_session().await?;
+        }
+
         let url = self.rpc_url();
         let body = json!({
             "method": method,
@@ -57,14 +118,7 @@ impl TransmissionClient {
             request = request.header("X-Transmission-Session-Id", session_id);
         }
 
-        // Add basic auth if credentials provided
-        if let (Some(ref username), Some(ref password)) =
-            (&self.config.username, &self.config.password)
-        {
-            request = request.basic_auth(username, Some(password));
-        }
-
-        let response = request.send().await?;
+        let response = self.with_auth(request).send().await?;
 
         // Handle CSRF token
         if response.status() == StatusCode::CONFLICT {
@@ -87,7 +141,7 @@ impl TransmissionClient {
             )));
         }
 
-        let rpc_response: RpcResponse<T> = response.json().await?;
+        let rpc_response: RpcResponse<T> = read_json(response).await?;
 
         if rpc_response.result != "success" {
             return Err(ClientError::InvalidResponse(rpc_response.result));
@@ -97,48 +151,62 @@ impl TransmissionClient {
             .arguments
             .ok_or_else(|| ClientError::InvalidResponse("Missing arguments".to_string()))
     }
+
+    async fn rpc_version(&self) -> Result<i64> {
+        self.rpc_version
+            .get_or_try_init(|| async {
+                let session: SessionInfo = self
+                    .rpc_call("session-get", json!({ "fields": ["rpc-version"] }))
+                    .await?;
+                Ok(session.rpc_version)
+            })
+            .await
+            .copied()
+    }
 }
 
 #[async_trait]
 impl BitTorrentClient for TransmissionClient {
-    fn client_type(&self) -> ClientType {
-        ClientType::Transmission
-    }
-
     fn client_id(&self) -> &str {
         &self.config.id
     }
 
+    fn download_root(&self) -> Option<&str> {
+        self.config.download_root.as_deref()
+    }
+
     async fn test_connection(&self) -> Result<bool> {
         let _: SessionStats = self.rpc_call("session-stats", json!({})).await?;
         Ok(true)
     }
 
     async fn get_torrents(&self) -> Result<Vec<TorrentInfo>> {
-        let args = json!({
-            "fields": [
-                "id", "hashString", "name", "totalSize", "percentDone",
-                "status", "downloadDir", "labels", "trackers", "addedDate", "files"
-            ]
-        });
-
-        let response: TorrentsResponse = self.rpc_call("torrent-get", args).await?;
+        // The table format repeats no keys per torrent, which adds up on
+        // instances with thousands of torrents
+        let torrents = if self.rpc_version().await? >= TABLE_FORMAT_RPC_VERSION {
+            let args = json!({ "fields": TORRENT_FIELDS, "format": "table" });
+            let response: TorrentTable = self.rpc_call("torrent-get", args).await?;
+            response.into_torrents()?
+        } else {
+            let args = json!({ "fields": TORRENT_FIELDS });
+            let response: TorrentsResponse = self.rpc_call("torrent-get", args).await?;
+            response.torrents
+        };
 
-        Ok(response.torrents.into_iter().map(|t| t.into()).collect())
+        let prefix = self.category_label_prefix();
+        Ok(torrents.into_iter().map(|t| t.into_torrent_info(prefix)).collect())
     }
 
     async fn get_torrent(&self, hash: &str) -> Result<Option<TorrentInfo>> {
         let args = json!({
             "ids": [hash],
-            "fields": [
-                "id", "hashString", "name", "totalSize", "percentDone",
-                "status", "downloadDir", "labels", "trackers", "addedDate", "files"
-            ]
+            "fields": TORRENT_FIELDS,
         });
 
         let response: TorrentsResponse = self.rpc_call("torrent-get", args).await?;
 
-        Ok(response.torrents.into_iter().next().map(|t| t.into()))
+        let prefix = self.category_label_prefix();
+        Ok(response.torrents.into_iter().next().map(|t| t.into_torrent_info(prefix)))
     }
 
     async fn get_torrent_files(&self, hash: &str) -> Result<Vec<TorrentFile>> {
@@ -201,44 +269,59 @@ impl BitTorrentClient for TransmissionClient {
             args["download-dir"] = json!(path);
         }
 
-        if !options.tags.is_empty() {
-            args["labels"] = json!(options.tags);
+        // Categories are stored as a prefixed label
+        let mut labels = options.tags.clone();
+        if let Some(ref category) = options.category {
+            labels.push(format!("{}{}", self.category_label_prefix(), category));
+        }
+
+        if !labels.is_empty() {
+            args["labels"] = json!(labels);
         }
 
         let response: AddTorrentResponse = self.rpc_call("torrent-add", args).await?;
 
-        Ok(response
+        let hash = response
             .torrent_added
             .or(response.torrent_duplicate)
             .map(|t| t.hash_string)
-            .unwrap_or_default())
-    }
+            .unwrap_or_default();
 
-    async fn remove_torrent(&self, hash: &str, delete_files: bool) -> Result<()> {
-        let args = json!({
-            "ids": [hash],
-            "delete-local-data": delete_files,
-        });
+        // The torrent is already added, so failing to adjust it afterwards
+        // must not report the add as failed
 
-        let _: serde_json::Value = self.rpc_call("torrent-remove", args).await?;
-        Ok(())
+        // New torrents are appended to the bottom of the queue by default
+        if options.add_top_of_queue && !hash.is_empty() {
+            let args = json!({ "ids": [hash], "queuePosition": 0 });
+            if let Err(e) = self.rpc_call::<serde_json::Value>("torrent-set", args).await {
+                warn!("Failed to move {} to the top of the queue: {}", hash, e);
+            }
+        }
+
+        // torrent-add takes no speed limits, so they are set afterwards
+        if let Some(args) = speed_limit_args(&hash, &options) {
+            if let Err(e) = self.rpc_call::<serde_json::Value>("torrent-set", args).await {
+                warn!("Failed to set speed limits on {}: {}", hash, e);
+            }
+        }
+
+        Ok(hash)
     }
 
-    async fn pause_torrent(&self, hash: &str) -> Result<()> {
+    async fn recheck_torrent(&self, hash: &str) -> Result<()> {
         let args = json!({ "ids": [hash] });
-        let _: serde_json::Value = self.rpc_call("torrent-stop", args).await?;
+        let _: serde_json::Value = self.rpc_call("torrent-verify", args).await?;
         Ok(())
     }
 
-    async fn resume_torrent(&self, hash: &str) -> Result<()> {
-        let args = json!({ "ids": [hash] });
-        let _: serde_json::Value = self.rpc_call("torrent-start", args).await?;
-        Ok(())
+    async fn export_torrent(&self, _hash: &str) -> Result<Vec<u8>> {
+        // The RPC only exposes the path of the torrent file on the daemon's host
+        Err(ClientError::NotSupported)
     }
 
-    async fn recheck_torrent(&self, hash: &str) -> Result<()> {
-        let args = json!({ "ids": [hash] });
-        let _: serde_json::Value = self.rpc_call("torrent-verify", args).await?;
+    async fn add_trackers(&self, hash: &str, urls: &[String]) -> Result<()> {
+        let args = json!({ "ids": [hash], "trackerAdd": urls });
+        let _: serde_json::Value = self.rpc_call("torrent-set", args).await?;
         Ok(())
     }
 }
@@ -258,11 +341,74 @@ struct SessionStats {
     active_torrent_count: Option<i32>,
 }
 
+#[derive(Debug, Deserialize)]
+struct SessionInfo {
+    #[serde(rename = "rpc-version")]
+    rpc_version: i64,
+}
+
 #[derive(Debug, Deserialize)]
 struct TorrentsResponse {
     torrents: Vec<TrTorrent>,
 }
 
+/// `torrent-get` response in table format: a header row of field names,
+/// then one row of values per torrent in header order
+#[derive(Debug, Deserialize)]
+struct TorrentTable {
+    torrents: Vec<Vec<serde_json::Value>>,
+}
+
+impl TorrentTable {
+    fn into_torrents(self) -> Result<Vec<TrTorrent>> {
+        let mut rows = self.torrents.into_iter();
+        let Some(header) = rows.next() else {
+            return Ok(Vec::new());
+        };
+
+        let position = |name: &str| header.iter().position(|column| column == name);
+        let required = |name: &str| {
+            position(name)
+                .ok_or_else(|| ClientError::InvalidResponse(format!("Torrent table has no {} column", name)))
+        };
+        let hash_string = required("hashString")?;
+        let name = required("name")?;
+        let total_size = required("totalSize")?;
+        let percent_done = required("percentDone")?;
+        let status = required("status")?;
+        let download_dir = required("downloadDir")?;
+        let (labels, trackers, added_date, files) =
+            (position("labels"), position("trackers"), position("addedDate"), position("files"));
+
+        rows.map(|mut row| {
+            let mut take = |column: Option<usize>| {
+                column
+                    .and_then(|i| row.get_mut(i))
+                    .map(serde_json::Value::take)
+                    .unwrap_or_default()
+            };
+            Ok(TrTorrent {
+                hash_string: decode_column(take(Some(hash_string)))?,
+                name: decode_column(take(Some(name)))?,
+                total_size: decode_column(take(Some(total_size)))?,
+                percent_done: decode_column(take(Some(percent_done)))?,
+                status: decode_column(take(Some(status)))?,
+                download_dir: decode_column(take(Some(download_dir)))?,
+                labels: decode_column(take(labels))?,
+                trackers: decode_column(take(trackers))?,
+                added_date: decode_column(take(added_date))?,
+                files: decode_column(take(files))?,
+            })
+        })
+        .collect()
+    }
+}
+
+fn decode_column<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> Result<T> {
+    serde_json::from_value(value)
+        .map_err(|e| ClientError::InvalidResponse(format!("Invalid torrent table row: {}", e)))
+}
+
 #[derive(Debug, Deserialize)]
 struct TrTorrent {
     #[serde(rename = "hashString")]
@@ -309,8 +455,11 @@ struct AddedTorrent {
     hash_string: String,
 }
 
-impl From<TrTorrent> for TorrentInfo {
-    fn from(t: TrTorrent) -> Self {
+impl TrTorrent {
+    /// Convert to `TorrentInfo`, extracting the first label starting with
+    /// `category_prefix` as the category; any further prefixed labels stay tags
+    fn into_torrent_info(self, category_prefix: &str) -> TorrentInfo {
+        let t = self;
         // Transmission status codes:
         // 0 = stopped, 1 = queued to verify, 2 = verifying, 3 = queued to download
         // 4 = downloading, 5 = queued to seed, 6 = seeding
@@ -326,6 +475,18 @@ impl From<TrTorrent> for TorrentInfo {
             chrono::DateTime::from_timestamp(ts, 0)
         });
 
+        // Transmission doesn't have categories; emulate them with a prefixed label
+        let mut category = None;
+        let mut tags = Vec::new();
+        for label in t.labels.unwrap_or_default() {
+            match label.strip_prefix(category_prefix) {
+                Some(name) if category.is_none() && !category_prefix.is_empty() => {
+                    category = Some(name.to_string());
+                }
+                _ => tags.push(label),
+            }
+        }
+
         let trackers: Vec<String> = t
             .trackers
             .as_ref()
@@ -354,8 +515,8 @@ impl From<TrTorrent> for TorrentInfo {
             progress: t.percent_done,
             state,
             save_path: t.download_dir,
-            category: None, // Transmission doesn't have categories
-            tags: t.labels.unwrap_or_default(),
+            category,
+            tags,
             tracker: trackers.first().cloned(),
             trackers,
             added_on,
@@ -363,3 +524,105 @@ impl From<TrTorrent> for TorrentInfo {
         }
     }
 }
+
+/// `torrent-set` arguments applying the speed limits of `options`, if any
+///
+/// Transmission takes limits in kB/s, so bytes per second are rounded up.
+fn speed_limit_args(hash: &str, options: &AddTorrentOptions) -> Option<serde_json::Value> {
+    if hash.is_empty() || (options.upload_limit.is_none() && options.download_limit.is_none()) {
+        return None;
+    }
+
+    let mut args = json!({ "ids": [hash] });
+    if let Some(limit) = options.upload_limit {
+        args["uploadLimited"] = json!(true);
+        args["uploadLimit"] = json!(limit.div_ceil(1000));
+    }
+    if let Some(limit) = options.download_limit {
+        args["downloadLimited"] = json!(true);
+        args["downloadLimit"] = json!(limit.div_ceil(1000));
+    }
+    Some(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_limit_args() {
+        let mut options = AddTorrentOptions::default();
+        assert!(speed_limit_args("abc", &options).is_none());
+
+        options.upload_limit = Some(1_500);
+        let args = speed_limit_args("abc", &options).unwrap();
+        assert_eq!(args["uploadLimited"], json!(true));
+        assert_eq!(args["uploadLimit"], json!(2));
+        assert!(args.get("downloadLimit").is_none());
+    }
+
+    fn torrent_with_labels(labels: &[&str]) -> TrTorrent {
+        TrTorrent {
+            hash_string: "ABC".to_string(),
+            name: "test".to_string(),
+            total_size: 100,
+            percent_done: 1.0,
+            status: 6,
+            download_dir: "/data".to_string(),
+            labels: Some(labels.iter().map(|l| l.to_string()).collect()),
+            trackers: None,
+            added_date: None,
+            files: None,
+        }
+    }
+
+    #[test]
+    fn test_category_from_prefixed_label() {
+        let info = torrent_with_labels(&["cross-seed", "cat:movies"]).into_torrent_info("cat:");
+        assert_eq!(info.category.as_deref(), Some("movies"));
+        assert_eq!(info.tags, vec!["cross-seed".to_string()]);
+    }
+
+    #[test]
+    fn test_extra_category_labels_kept_as_tags() {
+        let info = torrent_with_labels(&["cat:movies", "cross-seed", "cat:4k"]).into_torrent_info("cat:");
+        assert_eq!(info.category.as_deref(), Some("movies"));
+        assert_eq!(info.tags, vec!["cross-seed".to_string(), "cat:4k".to_string()]);
+    }
+
+    #[test]
+    fn test_no_category_label() {
+        let info = torrent_with_labels(&["cross-seed"]).into_torrent_info("cat:");
+        assert_eq!(info.category, None);
+        assert_eq!(info.tags, vec!["cross-seed".to_string()]);
+    }
+
+    #[test]
+    fn test_decode_torrent_table() {
+        let table: TorrentTable = serde_json::from_value(json!({
+            "torrents": [
+                ["id", "hashString", "name", "totalSize", "percentDone", "status", "downloadDir",
+                 "labels", "trackers", "addedDate", "files"],
+                [1, "ABC", "Movie", 1000, 1.0, 6, "/data", ["cat:movies"],
+                 [{ "announce": "https://t.example/announce" }], 1700000000,
+                 [{ "name": "Movie/movie.mkv", "length": 1000, "bytesCompleted": 1000 }]],
+                [2, "DEF", "Show", 50, 0.5, 4, "/data", [], [], 0, []]
+            ]
+        }))
+        .unwrap();
+
+        let torrents = table.into_torrents().unwrap();
+        assert_eq!(torrents.len(), 2);
+        let info = torrents.into_iter().next().unwrap().into_torrent_info("cat:");
+        assert_eq!(info.hash, "abc");
+        assert_eq!(info.category.as_deref(), Some("movies"));
+        assert_eq!(info.tracker.as_deref(), Some("https://t.example/announce"));
+        assert_eq!(info.files.len(), 1);
+
+        let missing: TorrentTable = serde_json::from_value(json!({ "torrents": [["id", "name"], [1, "x"]] })).unwrap();
+        assert!(missing.into_torrents().is_err());
+
+        let empty: TorrentTable = serde_json::from_value(json!({ "torrents": [] })).unwrap();
+        assert!(empty.into_torrents().unwrap().is_empty());
+    }
+}
@@ -1,11 +1,37 @@
 //! Configuration management module
 
 use anyhow::{Context, Result};
+use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
+use crate::service::UserAgentRotation;
+
+/// Command-line arguments
+///
+/// Flags take precedence over both the config file and environment variables.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(name = "graft", version, about)]
+pub struct CliArgs {
+    /// Path to the config file (skips the default search locations)
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Host to bind to
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Port to listen on
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// Path to the SQLite database file
+    #[arg(long, value_name = "FILE")]
+    pub db: Option<PathBuf>,
+}
+
 /// Application settings
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Settings {
     #[serde(default)]
     pub server: ServerSettings,
@@ -16,6 +42,12 @@ pub struct Settings {
     #[serde(default)]
     pub reseed: ReseedSettings,
 
+    #[serde(default)]
+    pub fingerprint: FingerprintSettings,
+
+    #[serde(default)]
+    pub schedule: ScheduleSettings,
+
     #[serde(skip)]
     config_file: Option<PathBuf>,
 }
@@ -27,12 +59,54 @@ pub struct ServerSettings {
 
     #[serde(default = "default_port")]
     pub port: u16,
+
+    /// Reject every state-changing API request and database backups
+    /// (browsing and previews still work)
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Path prefix when served behind a reverse proxy under a subpath (e.g. "/graft")
+    #[serde(default)]
+    pub base_path: String,
+
+    /// Use `X-Forwarded-Proto` / `X-Forwarded-Host` for absolute URLs.
+    /// Only enable behind a proxy that sets them.
+    #[serde(default)]
+    pub trust_forwarded_headers: bool,
+}
+
+impl ServerSettings {
+    /// `base_path` with a leading and without a trailing slash ("" for root)
+    pub fn normalized_base_path(&self) -> String {
+        let trimmed = self.base_path.trim().trim_matches('/');
+        if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseSettings {
     #[serde(default = "default_db_path")]
     pub path: PathBuf,
+
+    /// How long SQLite waits on a locked database before failing, in milliseconds
+    #[serde(default = "default_busy_timeout")]
+    pub busy_timeout_ms: u64,
+
+    /// Size of the memory-mapped I/O region in bytes (disabled when unset)
+    #[serde(default)]
+    pub mmap_size: Option<u64>,
+}
+
+impl DatabaseSettings {
+    /// Key file for stored passwords, next to the database. Backups of the
+    /// database need it to be restored with their passwords.
+    pub fn secret_key_path(&self) -> PathBuf {
+        self.path.with_file_name("secret.key")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,9 +119,153 @@ pub struct ReseedSettings {
     #[serde(default = "default_request_interval")]
     pub request_interval_ms: u64,
 
+    /// Maximum requests per minute to all sites and clients together
+    /// (unlimited when unset). Applies to imports as well as reseeds.
+    #[serde(default)]
+    pub global_rpm: Option<u32>,
+
     /// Maximum number of torrents to process per run
     #[serde(default = "default_max_per_run")]
     pub max_per_run: usize,
+
+    /// Matches below this confidence are never added (0.0 - 1.0)
+    #[serde(default)]
+    pub min_confidence: f64,
+
+    /// Consecutive authentication failures after which a site is skipped
+    /// for the rest of a run; 0 never skips it
+    #[serde(default = "default_site_auth_failure_limit")]
+    pub site_auth_failure_limit: u32,
+
+    /// Check that the source data exists under the save path before adding.
+    /// Only useful when Graft sees the same filesystem paths as the client.
+    #[serde(default)]
+    pub verify_save_path: bool,
+
+    /// How long a preview is reused by a following execute, in seconds
+    /// (0 disables the cache)
+    #[serde(default = "default_preview_cache_ttl")]
+    pub preview_cache_ttl_secs: u64,
+
+    /// Store a summary of every run in `reseed_runs`
+    #[serde(default = "default_true")]
+    pub record_runs: bool,
+
+    /// For matches with an identical info hash, build the target torrent from
+    /// the source client's copy and the site's `announce_url` instead of
+    /// downloading it. The target tracker must accept that info hash.
+    #[serde(default)]
+    pub rewrite_announce: bool,
+
+    /// Preferred target sites, best first. With `best_target_only`, a torrent
+    /// matching several sites is only reseeded to the highest-ranked one
+    /// (unlisted sites rank last).
+    #[serde(default)]
+    pub site_priority: Vec<String>,
+
+    /// Client IDs asked, in order, for a torrent's files and trackers when
+    /// the client holding it cannot return them. Used by imports and previews.
+    #[serde(default)]
+    pub detail_fallback_clients: Vec<String>,
+
+    /// Number of source torrents whose files and trackers a preview fetches
+    /// from the clients at once
+    #[serde(default = "default_preview_concurrency")]
+    pub preview_concurrency: usize,
+
+    /// How long runs with `verify_after_add` wait for each added torrent to
+    /// finish checking, in seconds
+    #[serde(default = "default_verify_timeout")]
+    pub verify_timeout_secs: u64,
+
+    /// User-Agents rotated across torrent downloads, so requests don't all
+    /// carry the same one. Empty sends a single fixed User-Agent; a site's
+    /// own `User-Agent` header always wins.
+    #[serde(default)]
+    pub user_agents: Vec<String>,
+
+    /// How downloads pick from `user_agents`: "round_robin" or "random"
+    #[serde(default)]
+    pub user_agent_rotation: UserAgentRotation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleSettings {
+    /// Whether scheduled reseed tasks run at all
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Minimum confidence for matches added by scheduled runs. Matches
+    /// between `reseed.min_confidence` and this are queued for manual
+    /// approval instead.
+    #[serde(default = "default_auto_min_confidence")]
+    pub auto_min_confidence: f64,
+
+    /// Days a site's cleared index entries can be restored before they are
+    /// purged (0 keeps them until the index is cleared)
+    #[serde(default = "default_index_purge_days")]
+    pub index_purge_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FingerprintSettings {
+    /// File extensions left out of fingerprints (e.g. "nfo", "srt").
+    /// Changing this makes existing index entries incomparable until re-imported.
+    #[serde(default)]
+    pub ignore_extensions: Vec<String>,
+
+    /// Refuse matches between video, audio and other content of equal size
+    #[serde(default)]
+    pub require_same_media_type: bool,
+
+    /// Hash file paths without their top-level directory, so a single file
+    /// and the same file wrapped in a folder match.
+    /// Changing this makes existing index entries incomparable until re-imported.
+    #[serde(default)]
+    pub strip_root_dir: bool,
+
+    /// Leave BitTorrent v1 padding files (`.pad/N`, `_____padding_file_N`)
+    /// out of fingerprints, so padded and unpadded copies of the same
+    /// content match
+    #[serde(default = "default_true")]
+    pub exclude_padding_files: bool,
+
+    /// Size buckets with more index entries than this only match identical
+    /// file lists (0 disables the guard)
+    #[serde(default = "default_max_candidates_per_bucket")]
+    pub max_candidates_per_bucket: usize,
+
+    /// Keep the file list of indexed torrents and suggest packs containing
+    /// them (`/api/index/suggestions`). Existing entries need a re-import.
+    #[serde(default)]
+    pub subset_matching: bool,
+
+    /// Weighted match scoring, replacing the fixed confidence levels
+    #[serde(default)]
+    pub scoring: ScoringSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_weight_total_size")]
+    pub total_size: f64,
+
+    #[serde(default = "default_weight_largest_file")]
+    pub largest_file: f64,
+
+    #[serde(default = "default_weight_file_count")]
+    pub file_count: f64,
+
+    /// Weight of torrent name similarity
+    #[serde(default = "default_weight_name")]
+    pub name: f64,
+
+    /// Minimum weighted score (0.0 - 1.0) for a match
+    #[serde(default = "default_min_score")]
+    pub min_score: f64,
 }
 
 fn default_host() -> String {
@@ -62,6 +280,54 @@ fn default_db_path() -> PathBuf {
     PathBuf::from("./data/graft.db")
 }
 
+fn default_preview_concurrency() -> usize {
+    8
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_auto_min_confidence() -> f64 {
+    0.9
+}
+
+fn default_index_purge_days() -> u32 {
+    30
+}
+
+fn default_weight_total_size() -> f64 {
+    0.4
+}
+
+fn default_weight_largest_file() -> f64 {
+    0.3
+}
+
+fn default_weight_file_count() -> f64 {
+    0.1
+}
+
+fn default_weight_name() -> f64 {
+    0.2
+}
+
+fn default_verify_timeout() -> u64 {
+    300
+}
+
+fn default_max_candidates_per_bucket() -> usize {
+    200
+}
+
+fn default_min_score() -> f64 {
+    0.7
+}
+
+fn default_busy_timeout() -> u64 {
+    5000
+}
+
 fn default_request_interval() -> u64 {
     500
 }
@@ -70,11 +336,22 @@ fn default_max_per_run() -> usize {
     100
 }
 
+fn default_site_auth_failure_limit() -> u32 {
+    3
+}
+
+fn default_preview_cache_ttl() -> u64 {
+    60
+}
+
 impl Default for ServerSettings {
     fn default() -> Self {
         Self {
             host: default_host(),
             port: default_port(),
+            read_only: false,
+            base_path: String::new(),
+            trust_forwarded_headers: false,
         }
     }
 }
@@ -83,58 +360,108 @@ impl Default for DatabaseSettings {
     fn default() -> Self {
         Self {
             path: default_db_path(),
+            busy_timeout_ms: default_busy_timeout(),
+            mmap_size: None,
         }
     }
 }
 
-impl Default for ReseedSettings {
+impl Default for ScheduleSettings {
     fn default() -> Self {
         Self {
-            default_paused: false,
-            request_interval_ms: default_request_interval(),
-            max_per_run: default_max_per_run(),
+            enabled: true,
+            auto_min_confidence: default_auto_min_confidence(),
+            index_purge_days: default_index_purge_days(),
+        }
+    }
+}
+
+impl Default for FingerprintSettings {
+    fn default() -> Self {
+        Self {
+            ignore_extensions: Vec::new(),
+            require_same_media_type: false,
+            strip_root_dir: false,
+            exclude_padding_files: true,
+            max_candidates_per_bucket: default_max_candidates_per_bucket(),
+            subset_matching: false,
+            scoring: ScoringSettings::default(),
         }
     }
 }
 
-impl Default for Settings {
+impl Default for ScoringSettings {
     fn default() -> Self {
         Self {
-            server: ServerSettings::default(),
-            database: DatabaseSettings::default(),
-            reseed: ReseedSettings::default(),
-            config_file: None,
+            enabled: false,
+            total_size: default_weight_total_size(),
+            largest_file: default_weight_largest_file(),
+            file_count: default_weight_file_count(),
+            name: default_weight_name(),
+            min_score: default_min_score(),
+        }
+    }
+}
+
+impl Default for ReseedSettings {
+    fn default() -> Self {
+        Self {
+            default_paused: false,
+            request_interval_ms: default_request_interval(),
+            global_rpm: None,
+            max_per_run: default_max_per_run(),
+            min_confidence: 0.0,
+            site_auth_failure_limit: default_site_auth_failure_limit(),
+            verify_save_path: false,
+            preview_cache_ttl_secs: default_preview_cache_ttl(),
+            record_runs: true,
+            rewrite_announce: false,
+            site_priority: Vec::new(),
+            detail_fallback_clients: Vec::new(),
+            preview_concurrency: default_preview_concurrency(),
+            verify_timeout_secs: default_verify_timeout(),
+            user_agents: Vec::new(),
+            user_agent_rotation: UserAgentRotation::default(),
         }
     }
 }
 
 impl Settings {
-    /// Load settings from environment and config file
-    pub fn load() -> Result<Self> {
+    /// Load settings from config file, environment and command-line flags
+    ///
+    /// If `args.config` is set, that file must exist and the default search
+    /// locations are skipped.
+    pub fn load(args: &CliArgs) -> Result<Self> {
         // Load .env file if present
         let _ = dotenvy::dotenv();
 
-        // Try to find config file
-        let mut config_paths = vec![
-            PathBuf::from("config.toml"),
-            PathBuf::from("./data/config.toml"),
-        ];
-        if let Some(path) = dirs_config_path() {
-            config_paths.push(path);
-        }
-
         let mut settings = Settings::default();
 
-        for path in config_paths.iter() {
-            if path.exists() {
-                settings = Self::load_from_file(path)?;
-                settings.config_file = Some(path.clone());
-                break;
+        if let Some(ref path) = args.config {
+            settings = Self::load_from_file(path)?;
+            settings.config_file = Some(path.clone());
+        } else {
+            // Try to find config file
+            let mut config_paths = vec![
+                PathBuf::from("config.toml"),
+                PathBuf::from("./data/config.toml"),
+            ];
+            if let Some(path) = dirs_config_path() {
+                config_paths.push(path);
+            }
+
+            for path in config_paths.iter() {
+                if path.exists() {
+                    settings = Self::load_from_file(path)?;
+                    settings.config_file = Some(path.clone());
+                    break;
+                }
             }
         }
 
-        // Override with environment variables
+        // Override with environment variables, then command-line flags
         settings.apply_env_overrides();
+        settings.apply_cli_overrides(args);
 
         // Ensure data directory exists
         if let Some(parent) = settings.database.path.parent() {
@@ -164,6 +491,12 @@ impl Settings {
                 self.server.port = port;
             }
         }
+        if let Ok(read_only) = std::env::var("GRAFT_READ_ONLY") {
+            self.server.read_only = matches!(read_only.as_str(), "1" | "true" | "yes");
+        }
+        if let Ok(base_path) = std::env::var("GRAFT_BASE_PATH") {
+            self.server.base_path = base_path;
+        }
         if let Ok(path) = std::env::var("GRAFT_DATA_DIR") {
             self.database.path = PathBuf::from(path).join("graft.db");
         }
@@ -172,6 +505,18 @@ impl Settings {
         }
     }
 
+    fn apply_cli_overrides(&mut self, args: &CliArgs) {
+        if let Some(ref host) = args.host {
+            self.server.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            self.server.port = port;
+        }
+        if let Some(ref path) = args.db {
+            self.database.path = path.clone();
+        }
+    }
+
     /// Get the path to the config file (if loaded from file)
     pub fn config_path(&self) -> Option<&Path> {
         self.config_file.as_deref()
@@ -213,7 +558,43 @@ fn dirs_config_path() -> Option<PathBuf> {
     }
 }
 
-// Add toml dependency
-fn _toml_parse_helper() {
-    // This is a marker to remind us to add toml to Cargo.toml
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_overrides_take_precedence() {
+        let mut settings = Settings::default();
+        settings.server.port = 8080;
+
+        let args = CliArgs::parse_from(["graft", "--host", "127.0.0.1", "--port", "9000", "--db", "/tmp/g.db"]);
+        settings.apply_cli_overrides(&args);
+
+        assert_eq!(settings.server.host, "127.0.0.1");
+        assert_eq!(settings.server.port, 9000);
+        assert_eq!(settings.database.path, PathBuf::from("/tmp/g.db"));
+    }
+
+    #[test]
+    fn test_normalized_base_path() {
+        let mut server = ServerSettings::default();
+        assert_eq!(server.normalized_base_path(), "");
+
+        server.base_path = "graft/".to_string();
+        assert_eq!(server.normalized_base_path(), "/graft");
+
+        server.base_path = "/".to_string();
+        assert_eq!(server.normalized_base_path(), "");
+    }
+
+    #[test]
+    fn test_cli_overrides_absent_keep_settings() {
+        let mut settings = Settings::default();
+        settings.server.port = 8080;
+
+        settings.apply_cli_overrides(&CliArgs::parse_from(["graft"]));
+
+        assert_eq!(settings.server.port, 8080);
+        assert_eq!(settings.database.path, default_db_path());
+    }
 }
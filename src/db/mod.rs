@@ -1,12 +1,57 @@
 //! Database layer with SQLite
 
 pub mod repository;
+mod store;
 
 use anyhow::{Context, Result};
 use rusqlite::Connection;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+use crate::config::DatabaseSettings;
+
+pub use store::{SqliteStore, Store};
+
+/// Incremental migrations applied after the initial schema
+///
+/// Each entry is applied once, in order; the number of applied migrations is
+/// tracked in SQLite's `user_version` pragma.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/002_history_site_disabled.sql"),
+    include_str!("../../migrations/003_history_data_not_found.sql"),
+    include_str!("../../migrations/004_client_category_label_prefix.sql"),
+    include_str!("../../migrations/005_fingerprint_profile_hash.sql"),
+    include_str!("../../migrations/006_reseed_runs.sql"),
+    include_str!("../../migrations/007_fingerprint_media_type.sql"),
+    include_str!("../../migrations/008_site_extra_headers.sql"),
+    include_str!("../../migrations/009_site_daily_download_limit.sql"),
+    include_str!("../../migrations/010_site_announce_url.sql"),
+    include_str!("../../migrations/011_reseed_progress.sql"),
+    include_str!("../../migrations/012_site_defaults.sql"),
+    include_str!("../../migrations/013_history_verify_statuses.sql"),
+    include_str!("../../migrations/014_match_approvals.sql"),
+    include_str!("../../migrations/015_site_proxy.sql"),
+    include_str!("../../migrations/016_history_failure_reason.sql"),
+    include_str!("../../migrations/017_fingerprint_v2_root.sql"),
+    include_str!("../../migrations/018_fingerprint_size_only.sql"),
+    include_str!("../../migrations/019_client_login_timeout.sql"),
+    include_str!("../../migrations/020_site_rsskey.sql"),
+    include_str!("../../migrations/021_client_download_root.sql"),
+    include_str!("../../migrations/022_cross_seeded.sql"),
+    include_str!("../../migrations/023_fingerprint_padding_excluded.sql"),
+    include_str!("../../migrations/024_site_login.sql"),
+    include_str!("../../migrations/025_index_soft_delete.sql"),
+    include_str!("../../migrations/026_health_status.sql"),
+    include_str!("../../migrations/027_index_files.sql"),
+    include_str!("../../migrations/028_history_downloaded.sql"),
+    include_str!("../../migrations/029_history_downloaded_flag.sql"),
+    include_str!("../../migrations/030_history_verify_failure_reasons.sql"),
+];
+
+/// Prepared statements kept per connection; the default of 16 is smaller
+/// than the set of queries the dashboard and list pages poll
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
 /// Database wrapper with connection pooling
 #[derive(Clone)]
 pub struct Database {
@@ -15,7 +60,9 @@ pub struct Database {
 
 impl Database {
     /// Create a new database connection
-    pub fn new(path: &Path) -> Result<Self> {
+    pub fn new(settings: &DatabaseSettings) -> Result<Self> {
+        let path = settings.path.as_path();
+
         // Ensure parent directory exists
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
@@ -34,6 +81,15 @@ impl Database {
              PRAGMA temp_store = MEMORY;",
         )?;
 
+        // Wait for concurrent writers instead of failing with "database is locked"
+        conn.busy_timeout(std::time::Duration::from_millis(settings.busy_timeout_ms))?;
+
+        if let Some(mmap_size) = settings.mmap_size {
+            conn.pragma_update(None, "mmap_size", mmap_size as i64)?;
+        }
+
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
         })
@@ -44,6 +100,7 @@ impl Database {
     pub fn in_memory() -> Result<Self> {
         let conn = Connection::open_in_memory()?;
         conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
         })
@@ -56,11 +113,79 @@ impl Database {
         conn.execute_batch(include_str!("../../migrations/001_initial.sql"))
             .context("Failed to run database migrations")?;
 
+        let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            conn.execute_batch(&format!(
+                "BEGIN;\n{}\nPRAGMA user_version = {};\nCOMMIT;",
+                migration,
+                i + 1
+            ))
+            .with_context(|| format!("Failed to run database migration {}", i + 2))?;
+        }
+
         Ok(())
     }
 
     /// Get a connection for executing queries
-    pub fn conn(&self) -> std::sync::MutexGuard<Connection> {
+    pub fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
         self.conn.lock().unwrap()
     }
+
+    /// Write a consistent snapshot of the database to a new file at `path`
+    ///
+    /// Uses `VACUUM INTO`, so the copy is compacted and safe to take while
+    /// the database is in use.
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        self.conn()
+            .execute("VACUUM INTO ?1", [path.to_string_lossy()])
+            .with_context(|| format!("Failed to back up database to {:?}", path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        db.migrate().unwrap();
+
+        let version: i64 = db.conn().query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn test_history_accepts_site_disabled() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+
+        db.conn()
+            .execute(
+                "INSERT INTO reseed_history (info_hash, target_site, status) VALUES ('abc', 'hdsky', 'site_disabled')",
+                [],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_backup_to() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        db.conn()
+            .execute("INSERT INTO sites (id, name, base_url) VALUES ('hdsky', 'hdsky', 'https://example.com')", [])
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("graft-test-backup-{}.db", uuid::Uuid::new_v4()));
+        db.backup_to(&path).unwrap();
+
+        let copy = Connection::open(&path).unwrap();
+        let sites: i64 = copy.query_row("SELECT COUNT(*) FROM sites", [], |row| row.get(0)).unwrap();
+        assert_eq!(sites, 1);
+        drop(copy);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
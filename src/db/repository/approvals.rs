@@ -0,0 +1,175 @@
+//! Match approval queue queries
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::service::ReseedMatch;
+
+const APPROVAL_COLUMNS: &str = "id, task_id, target_client, add_paused, status, created_at, resolved_at,
+     source_hash, source_name, source_site, source_client, target_site, target_torrent_id, target_hash,
+     save_path, size, confidence, reason";
+
+/// A stored match awaiting (or past) a manual decision
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Approval {
+    pub id: i64,
+    pub task_id: Option<String>,
+    pub target_client: String,
+    pub add_paused: bool,
+    pub status: String,
+    pub created_at: String,
+    pub resolved_at: Option<String>,
+    #[serde(flatten)]
+    pub reseed_match: ReseedMatch,
+}
+
+/// A match to queue for approval
+#[derive(Debug)]
+pub struct ApprovalRecord<'a> {
+    pub task_id: Option<&'a str>,
+    pub target_client: &'a str,
+    pub add_paused: bool,
+    pub reseed_match: &'a ReseedMatch,
+}
+
+fn approval_from_row(row: &Row) -> rusqlite::Result<Approval> {
+    Ok(Approval {
+        id: row.get(0)?,
+        task_id: row.get(1)?,
+        target_client: row.get(2)?,
+        add_paused: row.get::<_, i32>(3)? != 0,
+        status: row.get(4)?,
+        created_at: row.get(5)?,
+        resolved_at: row.get(6)?,
+        reseed_match: ReseedMatch {
+            source_hash: row.get(7)?,
+            source_name: row.get(8)?,
+            source_site: row.get(9)?,
+            source_client_id: row.get(10)?,
+            target_site: row.get(11)?,
+            target_torrent_id: row.get(12)?,
+            target_hash: row.get(13)?,
+            save_path: row.get(14)?,
+            size: row.get::<_, i64>(15)? as u64,
+            confidence: row.get(16)?,
+            reason: row.get(17)?,
+            outcome: None,
+        },
+    })
+}
+
+/// Queue a match, returning false when the same match is already pending or
+/// was rejected
+pub fn insert_approval(conn: &Connection, record: &ApprovalRecord) -> rusqlite::Result<bool> {
+    let m = record.reseed_match;
+    let inserted = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO match_approvals (task_id, source_client, target_client, source_hash, source_name,
+                source_site, target_site, target_torrent_id, target_hash, save_path, size, confidence, reason, add_paused)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )?
+        .execute(params![
+            record.task_id,
+            m.source_client_id,
+            record.target_client,
+            m.source_hash,
+            m.source_name,
+            m.source_site,
+            m.target_site,
+            m.target_torrent_id,
+            m.target_hash,
+            m.save_path,
+            m.size as i64,
+            m.confidence,
+            m.reason,
+            record.add_paused,
+        ])?;
+    Ok(inserted > 0)
+}
+
+/// Pending approvals, oldest first
+pub fn list_pending_approvals(conn: &Connection) -> rusqlite::Result<Vec<Approval>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM match_approvals WHERE status = 'pending' ORDER BY id",
+        APPROVAL_COLUMNS
+    ))?;
+    let approvals = stmt.query_map([], approval_from_row)?.collect();
+    approvals
+}
+
+pub fn get_approval(conn: &Connection, id: i64) -> rusqlite::Result<Option<Approval>> {
+    conn.prepare_cached(&format!("SELECT {} FROM match_approvals WHERE id = ?1", APPROVAL_COLUMNS))?
+        .query_row([id], approval_from_row)
+        .optional()
+}
+
+/// Mark a pending approval as approved or rejected
+///
+/// Returns false when it was already resolved, so concurrent decisions on
+/// the same match cannot both act on it.
+pub fn resolve_approval(conn: &Connection, id: i64, status: &str) -> rusqlite::Result<bool> {
+    let updated = conn.execute(
+        "UPDATE match_approvals SET status = ?2, resolved_at = datetime('now')
+         WHERE id = ?1 AND status = 'pending'",
+        params![id, status],
+    )?;
+    Ok(updated > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_approval_queue() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        conn.execute(
+            "INSERT INTO clients (id, name, client_type, host, port) VALUES ('qb', 'qb', 'qbittorrent', 'localhost', 8080)",
+            [],
+        )
+        .unwrap();
+
+        let m = ReseedMatch {
+            source_hash: "abc".to_string(),
+            source_name: "Movie".to_string(),
+            source_site: Some("hdsky".to_string()),
+            source_client_id: "qb".to_string(),
+            target_site: "ourbits".to_string(),
+            target_torrent_id: Some("42".to_string()),
+            target_hash: "def".to_string(),
+            save_path: "/data".to_string(),
+            size: 1000,
+            confidence: 0.6,
+            reason: "size match".to_string(),
+            outcome: None,
+        };
+        let record = ApprovalRecord {
+            task_id: None,
+            target_client: "qb",
+            add_paused: true,
+            reseed_match: &m,
+        };
+
+        assert!(insert_approval(&conn, &record).unwrap());
+        // Already pending
+        assert!(!insert_approval(&conn, &record).unwrap());
+
+        let pending = list_pending_approvals(&conn).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert!(pending[0].add_paused);
+        assert_eq!(pending[0].reseed_match.target_torrent_id.as_deref(), Some("42"));
+
+        let id = pending[0].id;
+        assert!(resolve_approval(&conn, id, "rejected").unwrap());
+        assert!(!resolve_approval(&conn, id, "approved").unwrap());
+        assert_eq!(get_approval(&conn, id).unwrap().unwrap().status, "rejected");
+        assert!(list_pending_approvals(&conn).unwrap().is_empty());
+
+        // A rejected match is not queued again
+        assert!(!insert_approval(&conn, &record).unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+//! Download client queries
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::client::{ClientConfig, ClientType};
+
+const CLIENT_COLUMNS: &str =
+    "id, name, client_type, host, port, username, password_encrypted, use_https, category_label_prefix, enabled,
+     login_timeout_secs, login_retries, download_root";
+
+fn client_from_row(row: &Row) -> rusqlite::Result<ClientConfig> {
+    let client_type_str: String = row.get(2)?;
+    Ok(ClientConfig {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        client_type: client_type_str.parse().unwrap_or(ClientType::QBittorrent),
+        host: row.get(3)?,
+        port: row.get(4)?,
+        username: row.get(5)?,
+        password: row.get(6)?,
+        use_https: row.get::<_, i32>(7)? != 0,
+        category_label_prefix: row.get(8)?,
+        enabled: row.get::<_, i32>(9)? != 0,
+        login_timeout_secs: row.get(10)?,
+        login_retries: row.get(11)?,
+        download_root: row.get(12)?,
+    })
+}
+
+/// All clients, ordered by name
+pub fn list_clients(conn: &Connection) -> rusqlite::Result<Vec<ClientConfig>> {
+    let mut stmt = conn.prepare_cached(&format!("SELECT {} FROM clients ORDER BY name", CLIENT_COLUMNS))?;
+    let clients = stmt.query_map([], client_from_row)?.collect();
+    clients
+}
+
+/// Enabled clients, ordered by name
+pub fn list_enabled_clients(conn: &Connection) -> rusqlite::Result<Vec<ClientConfig>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM clients WHERE enabled = 1 ORDER BY name",
+        CLIENT_COLUMNS
+    ))?;
+    let clients = stmt.query_map([], client_from_row)?.collect();
+    clients
+}
+
+pub fn count_clients(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.prepare_cached("SELECT COUNT(*) FROM clients")?
+        .query_row([], |row| row.get(0))
+}
+
+pub fn get_client(conn: &Connection, id: &str) -> rusqlite::Result<Option<ClientConfig>> {
+    conn.prepare_cached(&format!("SELECT {} FROM clients WHERE id = ?1", CLIENT_COLUMNS))?
+        .query_row([id], client_from_row)
+        .optional()
+}
+
+pub fn insert_client(conn: &Connection, client: &ClientConfig) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO clients (id, name, client_type, host, port, username, password_encrypted, use_https, enabled, category_label_prefix,
+                              login_timeout_secs, login_retries, download_root)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            client.id,
+            client.name,
+            client.client_type.to_string(),
+            client.host,
+            client.port,
+            client.username,
+            client.password, // TODO: encrypt
+            client.use_https as i32,
+            client.enabled as i32,
+            client.category_label_prefix,
+            client.login_timeout_secs,
+            client.login_retries,
+            client.download_root,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Update a client's connection settings, leaving `enabled` alone
+///
+/// Returns the number of rows changed (0 when the client does not exist).
+pub fn update_client(conn: &Connection, client: &ClientConfig) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE clients SET name = ?1, client_type = ?2, host = ?3, port = ?4, username = ?5, password_encrypted = ?6,
+                use_https = ?7, category_label_prefix = ?8, login_timeout_secs = ?9, login_retries = ?10,
+                download_root = ?11, updated_at = datetime('now')
+         WHERE id = ?12",
+        params![
+            client.name,
+            client.client_type.to_string(),
+            client.host,
+            client.port,
+            client.username,
+            client.password,
+            client.use_https as i32,
+            client.category_label_prefix,
+            client.login_timeout_secs,
+            client.login_retries,
+            client.download_root,
+            client.id,
+        ],
+    )
+}
+
+pub fn set_client_enabled(conn: &Connection, id: &str, enabled: bool) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE clients SET enabled = ?1, updated_at = datetime('now') WHERE id = ?2",
+        params![enabled as i32, id],
+    )
+}
+
+pub fn set_client_type(conn: &Connection, id: &str, client_type: ClientType) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE clients SET client_type = ?1, updated_at = datetime('now') WHERE id = ?2",
+        params![client_type.to_string(), id],
+    )
+}
+
+pub fn delete_client(conn: &Connection, id: &str) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM clients WHERE id = ?1", [id])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_client_round_trip() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+
+        let mut client = ClientConfig {
+            id: "qb".to_string(),
+            name: "qBittorrent".to_string(),
+            client_type: ClientType::QBittorrent,
+            host: "localhost".to_string(),
+            port: 8080,
+            username: Some("admin".to_string()),
+            password: Some("secret".to_string()),
+            use_https: false,
+            category_label_prefix: None,
+            enabled: true,
+            login_timeout_secs: Some(5),
+            login_retries: None,
+            download_root: Some("/downloads".to_string()),
+        };
+        insert_client(&conn, &client).unwrap();
+
+        client.port = 9090;
+        assert_eq!(update_client(&conn, &client).unwrap(), 1);
+        assert_eq!(set_client_enabled(&conn, "qb", false).unwrap(), 1);
+
+        let stored = get_client(&conn, "qb").unwrap().unwrap();
+        assert_eq!(stored.port, 9090);
+        assert_eq!(stored.password.as_deref(), Some("secret"));
+        assert_eq!(stored.login_timeout_secs, Some(5));
+        assert_eq!(stored.login_retries, None);
+        assert_eq!(stored.download_root.as_deref(), Some("/downloads"));
+        assert!(!stored.enabled);
+        assert!(list_enabled_clients(&conn).unwrap().is_empty());
+        assert_eq!(list_clients(&conn).unwrap().len(), 1);
+
+        assert_eq!(delete_client(&conn, "qb").unwrap(), 1);
+        assert!(get_client(&conn, "qb").unwrap().is_none());
+    }
+}
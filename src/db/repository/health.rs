@@ -0,0 +1,110 @@
+//! Last success and failure of requests to clients and sites
+
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// What a health record is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthKind {
+    Client,
+    Site,
+}
+
+impl HealthKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthKind::Client => "client",
+            HealthKind::Site => "site",
+        }
+    }
+}
+
+/// Latest request outcomes for one client or site
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Health {
+    pub last_ok_at: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+}
+
+impl Health {
+    /// Whether nothing has failed since the last success
+    pub fn is_ok(&self) -> bool {
+        match (&self.last_ok_at, &self.last_error_at) {
+            (_, None) => true,
+            (Some(ok), Some(error)) => ok >= error,
+            (None, Some(_)) => false,
+        }
+    }
+}
+
+pub fn record_health_ok(conn: &Connection, kind: HealthKind, id: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO health_status (kind, id, last_ok_at) VALUES (?1, ?2, datetime('now'))
+         ON CONFLICT(kind, id) DO UPDATE SET last_ok_at = excluded.last_ok_at",
+    )?
+    .execute(params![kind.as_str(), id])?;
+    Ok(())
+}
+
+pub fn record_health_error(conn: &Connection, kind: HealthKind, id: &str, error: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO health_status (kind, id, last_error, last_error_at) VALUES (?1, ?2, ?3, datetime('now'))
+         ON CONFLICT(kind, id) DO UPDATE SET last_error = excluded.last_error, last_error_at = excluded.last_error_at",
+    )?
+    .execute(params![kind.as_str(), id, error])?;
+    Ok(())
+}
+
+/// Health of every client or site with a recorded request, by id
+pub fn list_health(conn: &Connection, kind: HealthKind) -> rusqlite::Result<HashMap<String, Health>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, last_ok_at, last_error, last_error_at FROM health_status WHERE kind = ?1",
+    )?;
+    let health = stmt
+        .query_map([kind.as_str()], |row| {
+            Ok((
+                row.get(0)?,
+                Health {
+                    last_ok_at: row.get(1)?,
+                    last_error: row.get(2)?,
+                    last_error_at: row.get(3)?,
+                },
+            ))
+        })?
+        .collect();
+    health
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_record_health() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+
+        record_health_error(&conn, HealthKind::Site, "hdsky", "Authentication failed").unwrap();
+        record_health_ok(&conn, HealthKind::Client, "hdsky").unwrap();
+
+        let sites = list_health(&conn, HealthKind::Site).unwrap();
+        assert_eq!(sites.len(), 1);
+        assert!(!sites["hdsky"].is_ok());
+        assert_eq!(sites["hdsky"].last_error.as_deref(), Some("Authentication failed"));
+
+        // A later success clears the failure but keeps its message
+        conn.execute("UPDATE health_status SET last_error_at = datetime('now', '-1 hour')", []).unwrap();
+        record_health_ok(&conn, HealthKind::Site, "hdsky").unwrap();
+        let sites = list_health(&conn, HealthKind::Site).unwrap();
+        assert!(sites["hdsky"].is_ok());
+        assert!(sites["hdsky"].last_error.is_some());
+
+        assert!(list_health(&conn, HealthKind::Client).unwrap()["hdsky"].is_ok());
+    }
+}
@@ -0,0 +1,390 @@
+//! Reseed history and run summary queries
+
+use std::collections::HashSet;
+
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A stored reseed attempt
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub info_hash: String,
+    pub source_site: Option<String>,
+    pub target_site: String,
+    pub status: String,
+    pub message: Option<String>,
+    pub failure_reason: Option<String>,
+    pub created_at: String,
+}
+
+/// Why a reseed attempt failed, stored next to its free-text message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// The site rejected the passkey or cookie
+    AuthFailed,
+    DownloadFailed,
+    /// The site kept answering HTTP 429
+    RateLimited,
+    /// The torrent or data did not match what was expected
+    Mismatch,
+    NoPasskey,
+    NoTorrentId,
+    /// The target client refused the torrent
+    AddFailed,
+    /// The added torrent was still being checked, or not yet rechecked, at
+    /// the verify timeout
+    VerifyTimeout,
+    /// The target client could not be asked to recheck or report the torrent
+    VerifyError,
+    /// The target site is not configured or its settings are unusable
+    SiteConfigMissing,
+}
+
+impl FailureReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureReason::AuthFailed => "auth_failed",
+            FailureReason::DownloadFailed => "download_failed",
+            FailureReason::RateLimited => "rate_limited",
+            FailureReason::Mismatch => "mismatch",
+            FailureReason::NoPasskey => "no_passkey",
+            FailureReason::NoTorrentId => "no_torrent_id",
+            FailureReason::AddFailed => "add_failed",
+            FailureReason::VerifyTimeout => "verify_timeout",
+            FailureReason::VerifyError => "verify_error",
+            FailureReason::SiteConfigMissing => "site_config_missing",
+        }
+    }
+}
+
+/// Number of failures of one kind on one target site
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FailureStat {
+    pub target_site: String,
+    pub failure_reason: String,
+    pub count: i64,
+}
+
+/// A reseed attempt to store
+#[derive(Debug)]
+pub struct HistoryRecord<'a> {
+    pub task_id: Option<&'a str>,
+    pub info_hash: &'a str,
+    pub source_site: Option<&'a str>,
+    pub target_site: &'a str,
+    pub status: &'a str,
+    pub message: Option<&'a str>,
+    pub failure_reason: Option<FailureReason>,
+    /// The torrent file was fetched, whether or not the attempt succeeded
+    pub downloaded: bool,
+}
+
+/// A stored run summary
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RunEntry {
+    pub id: i64,
+    pub task_id: Option<String>,
+    pub source_clients: Vec<String>,
+    pub target_client: String,
+    pub target_sites: Vec<String>,
+    pub total: i64,
+    pub success: i64,
+    pub failed: i64,
+    pub skipped: i64,
+    pub duration_ms: i64,
+    pub created_at: String,
+}
+
+/// A run summary to store
+#[derive(Debug)]
+pub struct RunRecord<'a> {
+    pub task_id: Option<&'a str>,
+    pub source_clients: &'a [String],
+    pub target_client: &'a str,
+    pub target_sites: &'a [String],
+    pub total: usize,
+    pub success: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub duration_ms: u64,
+}
+
+fn history_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        info_hash: row.get(1)?,
+        source_site: row.get(2)?,
+        target_site: row.get(3)?,
+        status: row.get(4)?,
+        message: row.get(5)?,
+        failure_reason: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+pub fn insert_history(conn: &Connection, record: &HistoryRecord) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO reseed_history (task_id, info_hash, source_site, target_site, status, message, failure_reason, downloaded)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?
+    .execute(params![
+        record.task_id,
+        record.info_hash,
+        record.source_site,
+        record.target_site,
+        record.status,
+        record.message,
+        record.failure_reason.map(|r| r.as_str()),
+        record.downloaded,
+    ])?;
+    Ok(())
+}
+
+/// Record that a source torrent was reseeded to a target site
+pub fn mark_cross_seeded(conn: &Connection, source_hash: &str, target_site: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached("INSERT OR IGNORE INTO cross_seeded (source_hash, target_site) VALUES (?1, ?2)")?
+        .execute(params![source_hash.to_lowercase(), target_site])?;
+    Ok(())
+}
+
+/// Every (source hash, target site) pair reseeded so far
+pub fn list_cross_seeded(conn: &Connection) -> rusqlite::Result<HashSet<(String, String)>> {
+    let mut stmt = conn.prepare_cached("SELECT source_hash, target_site FROM cross_seeded")?;
+    let pairs = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+    pairs
+}
+
+/// (source hash, target site) pairs already processed by a task's current run
+pub fn load_reseed_progress(conn: &Connection, task_id: &str) -> rusqlite::Result<HashSet<(String, String)>> {
+    let mut stmt = conn.prepare_cached("SELECT source_hash, target_site FROM reseed_progress WHERE task_id = ?1")?;
+    let processed = stmt.query_map([task_id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+    processed
+}
+
+/// Record that a task's current run processed a match
+pub fn record_reseed_progress(
+    conn: &Connection,
+    task_id: &str,
+    source_hash: &str,
+    target_site: &str,
+) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT OR IGNORE INTO reseed_progress (task_id, source_hash, target_site) VALUES (?1, ?2, ?3)",
+    )?
+    .execute([task_id, source_hash, target_site])?;
+    Ok(())
+}
+
+/// Forget a task's progress once its run completes
+pub fn clear_reseed_progress(conn: &Connection, task_id: &str) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM reseed_progress WHERE task_id = ?1", [task_id])
+}
+
+/// History entries, newest first, optionally limited to one status
+pub fn list_history(
+    conn: &Connection,
+    status: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> rusqlite::Result<Vec<HistoryEntry>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, info_hash, source_site, target_site, status, message, failure_reason, created_at
+         FROM reseed_history
+         WHERE ?1 IS NULL OR status = ?1
+         ORDER BY created_at DESC
+         LIMIT ?2 OFFSET ?3",
+    )?;
+    let entries = stmt.query_map(params![status, limit, offset], history_from_row)?.collect();
+    entries
+}
+
+/// Number of history entries with one of `statuses` recorded today (UTC)
+pub fn count_today(conn: &Connection, statuses: &[&str]) -> rusqlite::Result<i64> {
+    let placeholders = vec!["?"; statuses.len()].join(", ");
+    conn.prepare_cached(&format!(
+        "SELECT COUNT(*) FROM reseed_history WHERE status IN ({}) AND date(created_at) = date('now')",
+        placeholders
+    ))?
+    .query_row(params_from_iter(statuses), |row| row.get(0))
+}
+
+/// Number of torrents downloaded from a site since midnight (UTC),
+/// including attempts that failed after the download
+pub fn count_added_today(conn: &Connection, site_id: &str) -> rusqlite::Result<u32> {
+    let count: i64 = conn
+        .prepare_cached(
+            "SELECT COUNT(*) FROM reseed_history
+             WHERE target_site = ?1 AND downloaded = 1 AND created_at >= date('now')",
+        )?
+        .query_row([site_id], |row| row.get(0))?;
+    Ok(count as u32)
+}
+
+/// Failure counts per target site and reason, most frequent first,
+/// optionally limited to the last `days` days
+pub fn failure_stats(conn: &Connection, days: Option<u32>) -> rusqlite::Result<Vec<FailureStat>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT target_site, failure_reason, COUNT(*) AS count
+         FROM reseed_history
+         WHERE failure_reason IS NOT NULL
+           AND (?1 IS NULL OR created_at >= datetime('now', '-' || ?1 || ' days'))
+         GROUP BY target_site, failure_reason
+         ORDER BY count DESC, target_site, failure_reason",
+    )?;
+    let stats = stmt
+        .query_map([days], |row| {
+            Ok(FailureStat {
+                target_site: row.get(0)?,
+                failure_reason: row.get(1)?,
+                count: row.get(2)?,
+            })
+        })?
+        .collect();
+    stats
+}
+
+pub fn insert_run(conn: &Connection, run: &RunRecord) -> rusqlite::Result<()> {
+    let source_clients = serde_json::to_string(run.source_clients).unwrap_or_default();
+    let target_sites = serde_json::to_string(run.target_sites).unwrap_or_default();
+    conn.execute(
+        "INSERT INTO reseed_runs (task_id, source_clients, target_client, target_sites, total, success, failed, skipped, duration_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            run.task_id,
+            source_clients,
+            run.target_client,
+            target_sites,
+            run.total as i64,
+            run.success as i64,
+            run.failed as i64,
+            run.skipped as i64,
+            run.duration_ms as i64,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Run summaries, newest first
+pub fn list_runs(conn: &Connection, limit: i64, offset: i64) -> rusqlite::Result<Vec<RunEntry>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, task_id, source_clients, target_client, target_sites, total, success, failed, skipped, duration_ms, created_at
+         FROM reseed_runs
+         ORDER BY created_at DESC, id DESC
+         LIMIT ?1 OFFSET ?2",
+    )?;
+
+    let runs = stmt
+        .query_map(params![limit, offset], |row| {
+            let source_clients: String = row.get(2)?;
+            let target_sites: String = row.get(4)?;
+            Ok(RunEntry {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                source_clients: serde_json::from_str(&source_clients).unwrap_or_default(),
+                target_client: row.get(3)?,
+                target_sites: serde_json::from_str(&target_sites).unwrap_or_default(),
+                total: row.get(5)?,
+                success: row.get(6)?,
+                failed: row.get(7)?,
+                skipped: row.get(8)?,
+                duration_ms: row.get(9)?,
+                created_at: row.get(10)?,
+            })
+        })?
+        .collect();
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_history_filters_and_counts() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+
+        for (hash, status) in [("a", "success"), ("b", "failed"), ("c", "verified_100")] {
+            insert_history(
+                &conn,
+                &HistoryRecord {
+                    task_id: None,
+                    info_hash: hash,
+                    source_site: None,
+                    target_site: "hdsky",
+                    status,
+                    message: None,
+                    failure_reason: (status == "failed").then_some(FailureReason::AuthFailed),
+                    downloaded: status != "failed",
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(list_history(&conn, None, 50, 0).unwrap().len(), 3);
+        let failed = list_history(&conn, Some("failed"), 50, 0).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].info_hash, "b");
+        assert_eq!(failed[0].failure_reason.as_deref(), Some("auth_failed"));
+
+        let stats = failure_stats(&conn, Some(7)).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].target_site.as_str(), stats[0].count), ("hdsky", 1));
+        assert_eq!(failure_stats(&conn, None).unwrap()[0].failure_reason, "auth_failed");
+
+        assert_eq!(count_today(&conn, &["success", "verified_100"]).unwrap(), 2);
+        assert_eq!(count_added_today(&conn, "hdsky").unwrap(), 2);
+        assert_eq!(count_added_today(&conn, "ourbits").unwrap(), 0);
+
+        // A torrent the client refused was still downloaded
+        insert_history(
+            &conn,
+            &HistoryRecord {
+                task_id: None,
+                info_hash: "d",
+                source_site: None,
+                target_site: "hdsky",
+                status: "failed",
+                message: None,
+                failure_reason: Some(FailureReason::AddFailed),
+                downloaded: true,
+            },
+        )
+        .unwrap();
+        assert_eq!(count_added_today(&conn, "hdsky").unwrap(), 3);
+
+        mark_cross_seeded(&conn, "ABC", "hdsky").unwrap();
+        mark_cross_seeded(&conn, "abc", "hdsky").unwrap();
+        let pairs = list_cross_seeded(&conn).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert!(pairs.contains(&("abc".to_string(), "hdsky".to_string())));
+    }
+
+    #[test]
+    fn test_reseed_progress() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        conn.execute_batch(
+            "INSERT INTO clients (id, name, client_type, host, port) VALUES ('qb', 'qb', 'qbittorrent', 'localhost', 8080);
+             INSERT INTO reseed_tasks (id, name, source_client, target_client, target_sites)
+                 VALUES ('t1', 'nightly', 'qb', 'qb', '[]');",
+        )
+        .unwrap();
+
+        record_reseed_progress(&conn, "t1", "abc", "hdsky").unwrap();
+        record_reseed_progress(&conn, "t1", "abc", "hdsky").unwrap();
+
+        let processed = load_reseed_progress(&conn, "t1").unwrap();
+        assert!(processed.contains(&("abc".to_string(), "hdsky".to_string())));
+        assert!(load_reseed_progress(&conn, "t2").unwrap().is_empty());
+
+        assert_eq!(clear_reseed_progress(&conn, "t1").unwrap(), 1);
+        assert!(load_reseed_progress(&conn, "t1").unwrap().is_empty());
+    }
+}
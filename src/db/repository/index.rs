@@ -0,0 +1,487 @@
+//! Torrent index and content fingerprint queries
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::service::{ContentFingerprint, IndexExportEntry, SiteIndexCount};
+use crate::site::SiteIdentification;
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<(i64, IndexExportEntry)> {
+    Ok((
+        row.get(0)?,
+        IndexExportEntry {
+            info_hash: row.get(1)?,
+            site_id: row.get(2)?,
+            torrent_id: row.get(3)?,
+            name: row.get(4)?,
+            save_path: row.get(5)?,
+            source_client: row.get(6)?,
+            total_size: row.get::<_, i64>(7)? as u64,
+            file_count: row.get::<_, i64>(8)? as usize,
+            largest_file_size: row.get::<_, i64>(9)? as u64,
+            files_hash: row.get(10)?,
+            profile_hash: row.get(11)?,
+            media_type: row.get::<_, Option<String>>(12)?.and_then(|t| t.parse().ok()),
+            v2_root: row.get(13)?,
+            size_only: row.get(14)?,
+            padding_excluded: row.get(15)?,
+        },
+    ))
+}
+
+/// The site an info hash was first indexed under
+pub fn find_indexed_site(conn: &Connection, info_hash: &str) -> rusqlite::Result<Option<SiteIdentification>> {
+    conn.prepare_cached(
+        "SELECT site_id, torrent_id FROM torrent_index WHERE info_hash = ?1 AND deleted_at IS NULL ORDER BY id LIMIT 1",
+    )?
+    .query_row([info_hash.to_lowercase()], |row| {
+        Ok(SiteIdentification {
+            site_id: row.get(0)?,
+            torrent_id: row.get(1)?,
+        })
+    })
+    .optional()
+}
+
+/// Whether a live (not cleared) entry exists
+pub fn index_entry_exists(conn: &Connection, info_hash: &str, site_id: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached(
+        "SELECT 1 FROM torrent_index WHERE info_hash = ?1 AND site_id = ?2 AND deleted_at IS NULL LIMIT 1",
+    )?
+    .exists([info_hash, site_id])
+}
+
+/// Id of an identical stored fingerprint, creating it if there is none
+fn get_or_create_fingerprint(conn: &Connection, fingerprint: &ContentFingerprint) -> rusqlite::Result<i64> {
+    let media_type = fingerprint.media_type.map(|t| t.to_string());
+    let existing: Option<i64> = conn
+        .prepare_cached(
+            "SELECT id FROM content_fingerprints
+             WHERE total_size = ?1 AND file_count = ?2 AND largest_file_size = ?3
+               AND profile_hash IS ?4 AND media_type IS ?5 AND v2_root IS ?6
+               AND size_only = ?7 AND padding_excluded = ?8
+             LIMIT 1",
+        )?
+        .query_row(
+            params![
+                fingerprint.total_size as i64,
+                fingerprint.file_count as i64,
+                fingerprint.largest_file_size as i64,
+                fingerprint.profile_hash,
+                media_type,
+                fingerprint.v2_root,
+                fingerprint.size_only,
+                fingerprint.padding_excluded,
+            ],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+
+    conn.execute(
+        "INSERT INTO content_fingerprints (total_size, file_count, largest_file_size, files_hash, profile_hash, media_type,
+                                           v2_root, size_only, padding_excluded)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            fingerprint.total_size as i64,
+            fingerprint.file_count as i64,
+            fingerprint.largest_file_size as i64,
+            fingerprint.files_hash,
+            fingerprint.profile_hash,
+            media_type,
+            fingerprint.v2_root,
+            fingerprint.size_only,
+            fingerprint.padding_excluded,
+        ],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Insert an entry, replacing a cleared one with the same key
+pub fn insert_index_entry(conn: &Connection, entry: &IndexExportEntry) -> rusqlite::Result<()> {
+    let fingerprint_id = get_or_create_fingerprint(conn, &entry.fingerprint())?;
+
+    conn.execute(
+        "INSERT INTO torrent_index (info_hash, site_id, torrent_id, fingerprint_id, name, size, save_path, source_client)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(info_hash, site_id) DO UPDATE SET
+            torrent_id = excluded.torrent_id,
+            fingerprint_id = excluded.fingerprint_id,
+            name = excluded.name,
+            size = excluded.size,
+            save_path = excluded.save_path,
+            source_client = excluded.source_client,
+            created_at = datetime('now'),
+            deleted_at = NULL
+         WHERE deleted_at IS NOT NULL",
+        params![
+            entry.info_hash,
+            entry.site_id,
+            entry.torrent_id,
+            fingerprint_id,
+            entry.name,
+            entry.total_size as i64,
+            entry.save_path,
+            entry.source_client,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Point an entry at `fingerprint`
+///
+/// Returns false when the entry already has that fingerprint.
+pub fn update_index_fingerprint(
+    conn: &Connection,
+    info_hash: &str,
+    site_id: &str,
+    fingerprint: &ContentFingerprint,
+) -> rusqlite::Result<bool> {
+    let fingerprint_id = get_or_create_fingerprint(conn, fingerprint)?;
+    let updated = conn
+        .prepare_cached(
+            "UPDATE torrent_index SET fingerprint_id = ?3, size = ?4
+             WHERE info_hash = ?1 AND site_id = ?2 AND fingerprint_id IS NOT ?3",
+        )?
+        .execute(params![info_hash, site_id, fingerprint_id, fingerprint.total_size as i64])?;
+    Ok(updated > 0)
+}
+
+/// Insert or update an entry keyed on (info_hash, site_id), restoring it if
+/// it was cleared
+///
+/// Returns false without storing anything when the entry's site is not
+/// configured.
+pub fn upsert_index_entry(conn: &Connection, entry: &IndexExportEntry) -> rusqlite::Result<bool> {
+    let site_exists = conn
+        .prepare_cached("SELECT 1 FROM sites WHERE id = ?1")?
+        .exists([&entry.site_id])?;
+    if !site_exists {
+        return Ok(false);
+    }
+
+    let fingerprint_id = get_or_create_fingerprint(conn, &entry.fingerprint())?;
+
+    conn.prepare_cached(
+        "INSERT INTO torrent_index (info_hash, site_id, torrent_id, fingerprint_id, name, size, save_path, source_client)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(info_hash, site_id) DO UPDATE SET
+            torrent_id = COALESCE(excluded.torrent_id, torrent_id),
+            fingerprint_id = excluded.fingerprint_id,
+            name = COALESCE(excluded.name, name),
+            size = excluded.size,
+            save_path = COALESCE(excluded.save_path, save_path),
+            source_client = COALESCE(excluded.source_client, source_client),
+            deleted_at = NULL",
+    )?
+    .execute(params![
+        entry.info_hash.to_lowercase(),
+        entry.site_id,
+        entry.torrent_id,
+        fingerprint_id,
+        entry.name,
+        entry.total_size as i64,
+        entry.save_path,
+        entry.source_client,
+    ])?;
+    Ok(true)
+}
+
+/// Store the `(path, size)` file list of an entry, replacing any stored one
+///
+/// With `only_if_missing`, an entry that already has a file list is left
+/// alone. Returns whether the list was written.
+pub fn replace_index_files(
+    conn: &Connection,
+    info_hash: &str,
+    site_id: &str,
+    files: &[(String, u64)],
+    only_if_missing: bool,
+) -> rusqlite::Result<bool> {
+    let id: Option<i64> = conn
+        .prepare_cached("SELECT id FROM torrent_index WHERE info_hash = ?1 AND site_id = ?2")?
+        .query_row([info_hash, site_id], |row| row.get(0))
+        .optional()?;
+    let Some(id) = id else {
+        return Ok(false);
+    };
+
+    if only_if_missing && conn.prepare_cached("SELECT 1 FROM index_files WHERE index_id = ?1 LIMIT 1")?.exists([id])? {
+        return Ok(false);
+    }
+
+    conn.prepare_cached("DELETE FROM index_files WHERE index_id = ?1")?.execute([id])?;
+    let mut insert = conn.prepare_cached("INSERT INTO index_files (index_id, path, size) VALUES (?1, ?2, ?3)")?;
+    for (path, size) in files {
+        insert.execute(params![id, path, *size as i64])?;
+    }
+    Ok(true)
+}
+
+/// File lists of live entries, by entry row id
+pub fn list_index_files(conn: &Connection) -> rusqlite::Result<HashMap<i64, Vec<(String, u64)>>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT f.index_id, f.path, f.size FROM index_files f
+         JOIN torrent_index ti ON ti.id = f.index_id
+         WHERE ti.deleted_at IS NULL",
+    )?;
+    let mut files: HashMap<i64, Vec<(String, u64)>> = HashMap::new();
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? as u64)))?;
+    for row in rows {
+        let (id, path, size) = row?;
+        files.entry(id).or_default().push((path, size));
+    }
+    Ok(files)
+}
+
+/// Up to `limit` live entries with a row id above `after_id`, in id order
+pub fn list_index_entries(
+    conn: &Connection,
+    after_id: i64,
+    limit: usize,
+) -> rusqlite::Result<Vec<(i64, IndexExportEntry)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT ti.id, ti.info_hash, ti.site_id, ti.torrent_id, ti.name, ti.save_path, ti.source_client,
+                cf.total_size, cf.file_count, cf.largest_file_size, cf.files_hash, cf.profile_hash,
+                cf.media_type, cf.v2_root, cf.size_only, cf.padding_excluded
+         FROM torrent_index ti
+         JOIN content_fingerprints cf ON ti.fingerprint_id = cf.id
+         WHERE ti.id > ?1 AND ti.deleted_at IS NULL
+         ORDER BY ti.id
+         LIMIT ?2",
+    )?;
+    let entries = stmt.query_map(params![after_id, limit as i64], entry_from_row)?.collect();
+    entries
+}
+
+pub fn count_index_entries(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.prepare_cached("SELECT COUNT(*) FROM torrent_index WHERE deleted_at IS NULL")?
+        .query_row([], |row| row.get(0))
+}
+
+/// Entry counts per site, largest first
+pub fn count_index_by_site(conn: &Connection) -> rusqlite::Result<Vec<SiteIndexCount>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT site_id, COUNT(*) as count FROM torrent_index WHERE deleted_at IS NULL
+         GROUP BY site_id ORDER BY count DESC",
+    )?;
+    let counts = stmt
+        .query_map([], |row| {
+            Ok(SiteIndexCount {
+                site_id: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect();
+    counts
+}
+
+/// Delete every index entry and fingerprint
+pub fn clear_index(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM torrent_index", [])?;
+    conn.execute("DELETE FROM content_fingerprints", [])?;
+    Ok(())
+}
+
+/// Mark a site's entries deleted, returning how many were cleared
+///
+/// Cleared entries stay in the table until `purge_deleted_index_entries`,
+/// so `restore_index_site` can bring them back.
+pub fn clear_index_site(conn: &Connection, site_id: &str) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE torrent_index SET deleted_at = datetime('now') WHERE site_id = ?1 AND deleted_at IS NULL",
+        [site_id],
+    )
+}
+
+/// Undo `clear_index_site`, returning how many entries were restored
+pub fn restore_index_site(conn: &Connection, site_id: &str) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE torrent_index SET deleted_at = NULL WHERE site_id = ?1 AND deleted_at IS NOT NULL",
+        [site_id],
+    )
+}
+
+/// Delete entries cleared before `before`
+pub fn purge_deleted_index_entries(conn: &Connection, before: DateTime<Utc>) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM torrent_index WHERE deleted_at < ?1", [sqlite_datetime(before)])
+}
+
+/// `created_at` and `deleted_at` are stored as SQLite `datetime('now')` text, in UTC
+fn sqlite_datetime(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Number of live entries indexed before `before`
+///
+/// Cleared entries are left to `purge_deleted_index_entries`, so a prune
+/// does not take away what `restore_index_site` could bring back.
+pub fn count_index_entries_before(conn: &Connection, before: DateTime<Utc>) -> rusqlite::Result<usize> {
+    let count: i64 = conn
+        .prepare_cached("SELECT COUNT(*) FROM torrent_index WHERE created_at < ?1 AND deleted_at IS NULL")?
+        .query_row([sqlite_datetime(before)], |row| row.get(0))?;
+    Ok(count as usize)
+}
+
+pub fn delete_index_entries_before(conn: &Connection, before: DateTime<Utc>) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM torrent_index WHERE created_at < ?1 AND deleted_at IS NULL",
+        [sqlite_datetime(before)],
+    )
+}
+
+/// Info hashes of the live entries imported from a client, one per entry
+pub fn list_client_index_hashes(conn: &Connection, client_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT info_hash FROM torrent_index WHERE source_client = ?1 AND deleted_at IS NULL",
+    )?;
+    let hashes = stmt.query_map([client_id], |row| row.get(0))?.collect();
+    hashes
+}
+
+/// Delete a client's live entries for the given info hashes, on every site
+pub fn delete_client_index_hashes(conn: &Connection, client_id: &str, hashes: &[String]) -> rusqlite::Result<usize> {
+    let mut stmt = conn.prepare_cached(
+        "DELETE FROM torrent_index WHERE source_client = ?1 AND info_hash = ?2 AND deleted_at IS NULL",
+    )?;
+    let mut deleted = 0;
+    for hash in hashes {
+        deleted += stmt.execute([client_id, hash])?;
+    }
+    Ok(deleted)
+}
+
+/// Delete fingerprints no longer referenced by any index entry, returning
+/// how many were removed
+pub fn gc_fingerprints(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM content_fingerprints
+         WHERE NOT EXISTS (
+            SELECT 1 FROM torrent_index ti WHERE ti.fingerprint_id = content_fingerprints.id
+         )",
+        [],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_prune_queries() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        conn.execute_batch(
+            "INSERT INTO sites (id, name, base_url) VALUES ('hdsky', 'hdsky', 'https://example.com');
+             INSERT INTO torrent_index (info_hash, site_id, source_client, created_at)
+                 VALUES ('old', 'hdsky', 'qb', datetime('now', '-40 days'));
+             INSERT INTO torrent_index (info_hash, site_id, source_client) VALUES ('new', 'hdsky', 'qb');
+             INSERT INTO torrent_index (info_hash, site_id, source_client) VALUES ('other', 'hdsky', 'tr');
+             INSERT INTO torrent_index (info_hash, site_id, source_client, created_at, deleted_at)
+                 VALUES ('cleared', 'hdsky', 'qb', datetime('now', '-40 days'), datetime('now'));",
+        )
+        .unwrap();
+
+        let cutoff = Utc::now() - chrono::Duration::days(30);
+        assert_eq!(count_index_entries_before(&conn, cutoff).unwrap(), 1);
+        assert_eq!(delete_index_entries_before(&conn, cutoff).unwrap(), 1);
+        assert_eq!(count_index_entries(&conn).unwrap(), 2);
+
+        assert_eq!(list_client_index_hashes(&conn, "qb").unwrap(), vec!["new".to_string()]);
+        assert_eq!(delete_client_index_hashes(&conn, "qb", &["new".to_string()]).unwrap(), 1);
+        assert_eq!(delete_client_index_hashes(&conn, "qb", &["other".to_string()]).unwrap(), 0);
+        assert_eq!(delete_client_index_hashes(&conn, "qb", &["cleared".to_string()]).unwrap(), 0);
+        assert_eq!(count_index_entries(&conn).unwrap(), 1);
+
+        // The cleared entry is untouched and can still be restored
+        assert_eq!(restore_index_site(&conn, "hdsky").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_clear_and_restore_site() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        conn.execute_batch(
+            "INSERT INTO sites (id, name, base_url) VALUES ('hdsky', 'hdsky', 'https://example.com');
+             INSERT INTO sites (id, name, base_url) VALUES ('ourbits', 'ourbits', 'https://example.org');
+             INSERT INTO torrent_index (info_hash, site_id) VALUES ('a', 'hdsky');
+             INSERT INTO torrent_index (info_hash, site_id) VALUES ('b', 'hdsky');
+             INSERT INTO torrent_index (info_hash, site_id) VALUES ('c', 'ourbits');",
+        )
+        .unwrap();
+
+        assert_eq!(clear_index_site(&conn, "hdsky").unwrap(), 2);
+        assert_eq!(count_index_entries(&conn).unwrap(), 1);
+        assert_eq!(count_index_by_site(&conn).unwrap().len(), 1);
+        assert!(!index_entry_exists(&conn, "a", "hdsky").unwrap());
+        assert!(find_indexed_site(&conn, "a").unwrap().is_none());
+
+        assert_eq!(restore_index_site(&conn, "hdsky").unwrap(), 2);
+        assert_eq!(count_index_entries(&conn).unwrap(), 3);
+
+        // Only entries cleared before the cutoff are purged
+        clear_index_site(&conn, "hdsky").unwrap();
+        assert_eq!(purge_deleted_index_entries(&conn, Utc::now() - chrono::Duration::days(1)).unwrap(), 0);
+        conn.execute("UPDATE torrent_index SET deleted_at = datetime('now', '-40 days') WHERE info_hash = 'a'", [])
+            .unwrap();
+        assert_eq!(purge_deleted_index_entries(&conn, Utc::now() - chrono::Duration::days(30)).unwrap(), 1);
+        assert_eq!(restore_index_site(&conn, "hdsky").unwrap(), 1);
+        assert_eq!(count_index_entries(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_index_files() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        conn.execute_batch(
+            "INSERT INTO sites (id, name, base_url) VALUES ('hdsky', 'hdsky', 'https://example.com');
+             INSERT INTO torrent_index (info_hash, site_id) VALUES ('a', 'hdsky');",
+        )
+        .unwrap();
+        let files = vec![("Show/E01.mkv".to_string(), 600), ("Show/E02.mkv".to_string(), 700)];
+
+        assert!(replace_index_files(&conn, "a", "hdsky", &files, true).unwrap());
+        assert!(!replace_index_files(&conn, "a", "hdsky", &files[..1], true).unwrap());
+        assert!(!replace_index_files(&conn, "missing", "hdsky", &files, false).unwrap());
+        assert_eq!(list_index_files(&conn).unwrap().into_values().next().unwrap(), files);
+
+        assert!(replace_index_files(&conn, "a", "hdsky", &files[..1], false).unwrap());
+        assert_eq!(list_index_files(&conn).unwrap().into_values().next().unwrap().len(), 1);
+
+        // Cleared entries' files are left out, and purged with them
+        clear_index_site(&conn, "hdsky").unwrap();
+        assert!(list_index_files(&conn).unwrap().is_empty());
+        conn.execute("DELETE FROM torrent_index", []).unwrap();
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM index_files", [], |row| row.get(0)).unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn test_size_only_fingerprints_kept_apart() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        conn.execute("INSERT INTO sites (id, name, base_url) VALUES ('hdsky', 'hdsky', 'https://example.com')", [])
+            .unwrap();
+
+        let entry = |hash: &str, fingerprint: ContentFingerprint| {
+            IndexExportEntry::from_fingerprint(hash, "hdsky", &fingerprint)
+        };
+        insert_index_entry(&conn, &entry("a", ContentFingerprint::size_only(1000))).unwrap();
+        insert_index_entry(&conn, &entry("b", ContentFingerprint::from_size(1000, 1, 1000))).unwrap();
+
+        let entries = list_index_entries(&conn, 0, 10).unwrap();
+        let flags: Vec<_> = entries.iter().map(|(_, e)| (e.info_hash.as_str(), e.size_only)).collect();
+        assert_eq!(flags, vec![("a", true), ("b", false)]);
+    }
+}
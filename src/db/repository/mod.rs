@@ -1,7 +1,37 @@
 //! Database repositories
 //!
-//! This module will contain repository implementations for database access.
-//! Currently, most database access is done directly in services and handlers.
-//! As the project grows, this can be refactored to use proper repository pattern.
+//! Typed queries for clients, sites, the torrent index, reseed history, the
+//! approval queue and client/site health, so the SQL for each table lives in
+//! one place. Functions take a `Connection` (or a transaction, which derefs
+//! to one) so callers control locking and atomicity.
 
-// Placeholder for future repository implementations
+mod approvals;
+mod clients;
+mod health;
+mod history;
+mod index;
+mod sites;
+
+pub use approvals::{
+    get_approval, insert_approval, list_pending_approvals, resolve_approval, Approval, ApprovalRecord,
+};
+pub use clients::{
+    count_clients, delete_client, get_client, insert_client, list_clients, list_enabled_clients,
+    set_client_enabled, set_client_type, update_client,
+};
+pub use health::{list_health, record_health_error, record_health_ok, Health, HealthKind};
+pub use history::{
+    clear_reseed_progress, count_added_today, count_today, failure_stats, insert_history, insert_run,
+    list_cross_seeded, list_history, list_runs, load_reseed_progress, mark_cross_seeded, record_reseed_progress,
+    FailureReason, FailureStat, HistoryEntry, HistoryRecord, RunEntry, RunRecord,
+};
+pub use index::{
+    clear_index, clear_index_site, count_index_by_site, count_index_entries, count_index_entries_before,
+    delete_client_index_hashes, delete_index_entries_before, find_indexed_site, gc_fingerprints, index_entry_exists,
+    insert_index_entry, list_client_index_hashes, list_index_entries, list_index_files, purge_deleted_index_entries,
+    replace_index_files, restore_index_site, update_index_fingerprint, upsert_index_entry,
+};
+pub use sites::{
+    add_tracker_domains, count_enabled_sites, delete_site, encrypt_site_passwords, get_enabled_site, get_site,
+    list_enabled_sites, list_sites, list_tracker_domains, set_sites_enabled, sync_builtin_site, update_site, upsert_site, SiteUpdate,
+};
@@ -0,0 +1,419 @@
+//! Site queries
+
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use tracing::warn;
+
+use crate::site::{SiteConfig, TemplateType};
+use crate::utils::{decrypt_password, encrypt_password, is_encrypted};
+
+const SITE_COLUMNS: &str = "id, name, base_url, template_type, passkey, cookie_encrypted, enabled, rate_limit_rpm,
+    extra_headers, daily_download_limit, announce_url, default_category, default_save_path, proxy, rsskey,
+    site_username, site_password";
+
+fn site_from_row(row: &Row) -> rusqlite::Result<SiteConfig> {
+    let template_str: String = row.get(3)?;
+    let template_type = template_str.parse().unwrap_or(TemplateType::NexusPHP);
+    Ok(SiteConfig {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        base_url: row.get(2)?,
+        template_type,
+        tracker_domains: Vec::new(), // Stored in tracker_domains, not needed for downloads
+        download_pattern: template_type.default_download_pattern().to_string(),
+        passkey: row.get(4)?,
+        cookie: row.get(5)?,
+        enabled: row.get::<_, i32>(6)? != 0,
+        rate_limit_rpm: row.get(7)?,
+        extra_headers: row
+            .get::<_, Option<String>>(8)?
+            .and_then(|h| serde_json::from_str(&h).ok())
+            .unwrap_or_default(),
+        daily_download_limit: row.get(9)?,
+        announce_url: row.get(10)?,
+        default_category: row.get(11)?,
+        default_save_path: row.get(12)?,
+        proxy: row.get(13)?,
+        rsskey: row.get(14)?,
+        site_username: row.get(15)?,
+        site_password: row.get::<_, Option<String>>(16)?.and_then(|p| {
+            let password = decrypt_password(&p);
+            if password.is_none() {
+                warn!("Stored password of site {} was encrypted under another secret key", row.get::<_, String>(0).unwrap_or_default());
+            }
+            password
+        }),
+    })
+}
+
+/// All configured sites, ordered by name
+pub fn list_sites(conn: &Connection) -> rusqlite::Result<Vec<SiteConfig>> {
+    let mut stmt = conn.prepare_cached(&format!("SELECT {} FROM sites ORDER BY name", SITE_COLUMNS))?;
+    let sites = stmt.query_map([], site_from_row)?.collect();
+    sites
+}
+
+/// Enabled sites, ordered by name
+pub fn list_enabled_sites(conn: &Connection) -> rusqlite::Result<Vec<SiteConfig>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM sites WHERE enabled = 1 ORDER BY name",
+        SITE_COLUMNS
+    ))?;
+    let sites = stmt.query_map([], site_from_row)?.collect();
+    sites
+}
+
+pub fn count_enabled_sites(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.prepare_cached("SELECT COUNT(*) FROM sites WHERE enabled = 1")?
+        .query_row([], |row| row.get(0))
+}
+
+pub fn get_site(conn: &Connection, id: &str) -> rusqlite::Result<Option<SiteConfig>> {
+    conn.prepare_cached(&format!("SELECT {} FROM sites WHERE id = ?1", SITE_COLUMNS))?
+        .query_row([id], site_from_row)
+        .optional()
+}
+
+/// The site, unless it is missing or disabled
+pub fn get_enabled_site(conn: &Connection, id: &str) -> rusqlite::Result<Option<SiteConfig>> {
+    conn.prepare_cached(&format!("SELECT {} FROM sites WHERE id = ?1 AND enabled = 1", SITE_COLUMNS))?
+        .query_row([id], site_from_row)
+        .optional()
+}
+
+/// Insert a site, or update an existing one
+///
+/// On update, optional settings that are unset in `site` keep their stored
+/// value, and `template_type` and `enabled` are left alone.
+pub fn upsert_site(conn: &Connection, site: &SiteConfig) -> rusqlite::Result<()> {
+    let extra_headers = if site.extra_headers.is_empty() {
+        None
+    } else {
+        serde_json::to_string(&site.extra_headers).ok()
+    };
+
+    conn.execute(
+        "INSERT INTO sites (id, name, base_url, template_type, passkey, cookie_encrypted, enabled, extra_headers,
+                            daily_download_limit, announce_url, default_category, default_save_path, proxy, rsskey,
+                            site_username, site_password)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+         ON CONFLICT(id) DO UPDATE SET
+            name = excluded.name,
+            base_url = excluded.base_url,
+            passkey = COALESCE(excluded.passkey, passkey),
+            cookie_encrypted = COALESCE(excluded.cookie_encrypted, cookie_encrypted),
+            extra_headers = COALESCE(excluded.extra_headers, extra_headers),
+            daily_download_limit = COALESCE(excluded.daily_download_limit, daily_download_limit),
+            announce_url = COALESCE(excluded.announce_url, announce_url),
+            default_category = COALESCE(excluded.default_category, default_category),
+            default_save_path = COALESCE(excluded.default_save_path, default_save_path),
+            proxy = COALESCE(excluded.proxy, proxy),
+            rsskey = COALESCE(excluded.rsskey, rsskey),
+            site_username = COALESCE(excluded.site_username, site_username),
+            site_password = COALESCE(excluded.site_password, site_password),
+            updated_at = datetime('now')",
+        params![
+            site.id,
+            site.name,
+            site.base_url,
+            site.template_type.to_string(),
+            site.passkey,
+            site.cookie,
+            site.enabled as i32,
+            extra_headers,
+            site.daily_download_limit,
+            site.announce_url,
+            site.default_category,
+            site.default_save_path,
+            site.proxy,
+            site.rsskey,
+            site.site_username,
+            site.site_password.as_deref().map(encrypt_password),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Map tracker domains to a site, keeping existing mappings
+///
+/// Returns how many domains were newly mapped.
+pub fn add_tracker_domains(conn: &Connection, site_id: &str, domains: &[String]) -> rusqlite::Result<usize> {
+    let mut stmt = conn.prepare_cached("INSERT OR IGNORE INTO tracker_domains (domain, site_id) VALUES (?1, ?2)")?;
+    let mut added = 0;
+    for domain in domains {
+        added += stmt.execute([domain, site_id])?;
+    }
+    Ok(added)
+}
+
+/// Every stored (domain, site id) tracker mapping
+pub fn list_tracker_domains(conn: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare_cached("SELECT domain, site_id FROM tracker_domains")?;
+    let domains = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+    domains
+}
+
+/// Bring a configured builtin site up to date with its builtin definition
+///
+/// Registers any missing tracker domains and resets the template type, from
+/// which the download pattern follows. Credentials and other settings are
+/// left alone. Returns the number of domains added, or `None` when the site
+/// is not configured.
+pub fn sync_builtin_site(conn: &Connection, builtin: &SiteConfig) -> rusqlite::Result<Option<usize>> {
+    let updated = conn.execute(
+        "UPDATE sites SET template_type = ?2, updated_at = datetime('now')
+         WHERE id = ?1 AND template_type != ?2",
+        params![builtin.id, builtin.template_type.to_string()],
+    )?;
+    let configured = updated > 0
+        || conn
+            .prepare_cached("SELECT 1 FROM sites WHERE id = ?1")?
+            .exists([&builtin.id])?;
+    if !configured {
+        return Ok(None);
+    }
+
+    add_tracker_domains(conn, &builtin.id, &builtin.tracker_domains).map(Some)
+}
+
+/// Changes to a site's settings; `None` leaves a column unchanged
+///
+/// For the nullable settings, `Some(None)` clears the stored value.
+#[derive(Debug, Default)]
+pub struct SiteUpdate {
+    pub name: Option<String>,
+    pub base_url: Option<String>,
+    pub passkey: Option<String>,
+    pub rsskey: Option<Option<String>>,
+    pub cookie: Option<String>,
+    pub enabled: Option<bool>,
+    /// Headers already encoded as a JSON object
+    pub extra_headers: Option<Option<String>>,
+    pub daily_download_limit: Option<Option<u32>>,
+    pub announce_url: Option<Option<String>>,
+    pub default_category: Option<Option<String>>,
+    pub default_save_path: Option<Option<String>>,
+    pub proxy: Option<Option<String>>,
+    pub site_username: Option<Option<String>>,
+    pub site_password: Option<Option<String>>,
+}
+
+impl SiteUpdate {
+    fn assignments(&self) -> Vec<(&'static str, &dyn ToSql)> {
+        let mut assignments: Vec<(&'static str, &dyn ToSql)> = Vec::new();
+        if let Some(ref name) = self.name {
+            assignments.push(("name", name));
+        }
+        if let Some(ref base_url) = self.base_url {
+            assignments.push(("base_url", base_url));
+        }
+        if let Some(ref passkey) = self.passkey {
+            assignments.push(("passkey", passkey));
+        }
+        if let Some(ref rsskey) = self.rsskey {
+            assignments.push(("rsskey", rsskey));
+        }
+        if let Some(ref cookie) = self.cookie {
+            assignments.push(("cookie_encrypted", cookie));
+        }
+        if let Some(ref enabled) = self.enabled {
+            assignments.push(("enabled", enabled));
+        }
+        if let Some(ref extra_headers) = self.extra_headers {
+            assignments.push(("extra_headers", extra_headers));
+        }
+        if let Some(ref limit) = self.daily_download_limit {
+            assignments.push(("daily_download_limit", limit));
+        }
+        if let Some(ref announce_url) = self.announce_url {
+            assignments.push(("announce_url", announce_url));
+        }
+        if let Some(ref category) = self.default_category {
+            assignments.push(("default_category", category));
+        }
+        if let Some(ref save_path) = self.default_save_path {
+            assignments.push(("default_save_path", save_path));
+        }
+        if let Some(ref proxy) = self.proxy {
+            assignments.push(("proxy", proxy));
+        }
+        if let Some(ref username) = self.site_username {
+            assignments.push(("site_username", username));
+        }
+        assignments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assignments().is_empty() && self.site_password.is_none()
+    }
+}
+
+/// Apply `update` to a site
+///
+/// Returns the number of rows changed (0 when the site does not exist or
+/// there is nothing to change).
+pub fn update_site(conn: &Connection, id: &str, update: &SiteUpdate) -> rusqlite::Result<usize> {
+    let site_password = update.site_password.as_ref().map(|p| p.as_deref().map(encrypt_password));
+    let mut assignments = update.assignments();
+    if let Some(ref password) = site_password {
+        assignments.push(("site_password", password));
+    }
+    if assignments.is_empty() {
+        return Ok(0);
+    }
+
+    let columns: Vec<String> = assignments.iter().map(|(column, _)| format!("{} = ?", column)).collect();
+    let sql = format!(
+        "UPDATE sites SET {}, updated_at = datetime('now') WHERE id = ?",
+        columns.join(", ")
+    );
+
+    let mut values: Vec<&dyn ToSql> = assignments.into_iter().map(|(_, value)| value).collect();
+    values.push(&id);
+    conn.execute(&sql, values.as_slice())
+}
+
+/// Encrypt site passwords stored before passwords were encrypted
+///
+/// Returns the number of passwords encrypted.
+pub fn encrypt_site_passwords(conn: &Connection) -> rusqlite::Result<usize> {
+    let plain: Vec<(String, String)> = conn
+        .prepare("SELECT id, site_password FROM sites WHERE site_password IS NOT NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, password): &(String, String)| !is_encrypted(password))
+        .collect();
+
+    let mut stmt = conn.prepare("UPDATE sites SET site_password = ?2 WHERE id = ?1")?;
+    for (id, password) in &plain {
+        stmt.execute(params![id, encrypt_password(password)])?;
+    }
+    Ok(plain.len())
+}
+
+/// Enable or disable sites in one transaction
+///
+/// Returns how many sites changed state; sites already in the requested
+/// state are not counted.
+pub fn set_sites_enabled(conn: &Connection, ids: &[String], enabled: bool) -> rusqlite::Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let mut changed = 0;
+
+    for id in ids {
+        changed += tx
+            .prepare_cached(
+                "UPDATE sites SET enabled = ?1, updated_at = datetime('now') WHERE id = ?2 AND enabled != ?1",
+            )?
+            .execute(params![enabled as i32, id])?;
+    }
+
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Delete a site and its tracker domain mappings
+///
+/// Returns the number of sites deleted. Index entries go with the site
+/// through the foreign key cascade.
+pub fn delete_site(conn: &Connection, id: &str) -> rusqlite::Result<usize> {
+    let rows = conn.execute("DELETE FROM sites WHERE id = ?1", [id])?;
+    if rows > 0 {
+        conn.execute("DELETE FROM tracker_domains WHERE site_id = ?1", [id])?;
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_upsert_and_update_site() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+
+        let mut site = crate::site::builtin_sites().remove(0);
+        site.passkey = Some("key".to_string());
+        site.rsskey = Some("rss".to_string());
+        site.daily_download_limit = Some(10);
+        upsert_site(&conn, &site).unwrap();
+
+        // Unset optional settings keep their stored value
+        site.passkey = None;
+        site.rsskey = None;
+        site.name = "Renamed".to_string();
+        upsert_site(&conn, &site).unwrap();
+        let stored = get_site(&conn, &site.id).unwrap().unwrap();
+        assert_eq!(stored.name, "Renamed");
+        assert_eq!(stored.passkey.as_deref(), Some("key"));
+        assert_eq!(stored.rsskey.as_deref(), Some("rss"));
+
+        let update = SiteUpdate {
+            rsskey: Some(None),
+            enabled: Some(false),
+            daily_download_limit: Some(None),
+            ..Default::default()
+        };
+        assert_eq!(update_site(&conn, &site.id, &update).unwrap(), 1);
+        assert_eq!(update_site(&conn, &site.id, &SiteUpdate::default()).unwrap(), 0);
+
+        let stored = get_site(&conn, &site.id).unwrap().unwrap();
+        assert!(!stored.enabled);
+        assert_eq!(stored.rsskey, None);
+        assert_eq!(stored.daily_download_limit, None);
+        assert!(get_enabled_site(&conn, &site.id).unwrap().is_none());
+
+        assert_eq!(set_sites_enabled(&conn, &[site.id.clone()], true).unwrap(), 1);
+        assert_eq!(set_sites_enabled(&conn, &[site.id.clone()], true).unwrap(), 0);
+        assert_eq!(list_enabled_sites(&conn).unwrap().len(), 1);
+
+        // Syncing restores domains and the template without touching credentials
+        conn.execute("UPDATE sites SET template_type = 'unit3d' WHERE id = ?1", [&site.id]).unwrap();
+        let domains = site.tracker_domains.len();
+        assert_eq!(sync_builtin_site(&conn, &site).unwrap(), Some(domains));
+        assert_eq!(sync_builtin_site(&conn, &site).unwrap(), Some(0));
+        assert_eq!(list_tracker_domains(&conn).unwrap().len(), domains);
+        let stored = get_site(&conn, &site.id).unwrap().unwrap();
+        assert_eq!(stored.template_type, site.template_type);
+        assert_eq!(stored.passkey.as_deref(), Some("key"));
+        assert_eq!(sync_builtin_site(&conn, &crate::site::builtin_sites().remove(1)).unwrap(), None);
+
+        assert_eq!(delete_site(&conn, &site.id).unwrap(), 1);
+        assert!(list_sites(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_site_password_encrypted() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        let conn = db.conn();
+        let stored_password = || -> String {
+            conn.query_row("SELECT site_password FROM sites", [], |row| row.get(0)).unwrap()
+        };
+
+        let mut site = crate::site::builtin_sites().remove(0);
+        site.site_username = Some("user".to_string());
+        site.site_password = Some("hunter2".to_string());
+        upsert_site(&conn, &site).unwrap();
+        assert!(is_encrypted(&stored_password()));
+        assert_eq!(get_site(&conn, &site.id).unwrap().unwrap().site_password.as_deref(), Some("hunter2"));
+
+        let update = SiteUpdate {
+            site_password: Some(Some("changed".to_string())),
+            ..Default::default()
+        };
+        assert!(!update.is_empty());
+        update_site(&conn, &site.id, &update).unwrap();
+        assert!(is_encrypted(&stored_password()));
+        assert_eq!(get_site(&conn, &site.id).unwrap().unwrap().site_password.as_deref(), Some("changed"));
+
+        // Passwords stored in plain text are encrypted once
+        conn.execute("UPDATE sites SET site_password = 'legacy'", []).unwrap();
+        assert_eq!(get_site(&conn, &site.id).unwrap().unwrap().site_password.as_deref(), Some("legacy"));
+        assert_eq!(encrypt_site_passwords(&conn).unwrap(), 1);
+        assert_eq!(encrypt_site_passwords(&conn).unwrap(), 0);
+        assert_eq!(get_site(&conn, &site.id).unwrap().unwrap().site_password.as_deref(), Some("legacy"));
+    }
+}
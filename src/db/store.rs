@@ -0,0 +1,308 @@
+//! Storage backend abstraction
+//!
+//! Services reach the torrent index and reseed history through the `Store`
+//! trait rather than raw SQL, so another backend (e.g. Postgres, for several
+//! instances sharing one index) can be added next to `SqliteStore`. Client
+//! and site health is recorded here too; clients, sites and tasks themselves
+//! still go through `Database` and the repository directly.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+use super::repository::{self, FailureStat, HealthKind, HistoryEntry, HistoryRecord, RunEntry, RunRecord};
+use super::Database;
+use crate::service::{ContentFingerprint, IndexExportEntry, SiteIndexCount};
+use crate::site::SiteIdentification;
+
+/// Index and history storage
+pub trait Store: Send + Sync {
+    /// The site an info hash was first indexed under
+    fn find_indexed_site(&self, info_hash: &str) -> Result<Option<SiteIdentification>>;
+
+    fn index_entry_exists(&self, info_hash: &str, site_id: &str) -> Result<bool>;
+
+    /// Add an entry, reusing an identical fingerprint when one is stored
+    fn insert_index_entry(&self, entry: &IndexExportEntry) -> Result<()>;
+
+    /// Replace an entry's fingerprint, returning false when it is unchanged
+    fn update_index_fingerprint(&self, info_hash: &str, site_id: &str, fingerprint: &ContentFingerprint) -> Result<bool>;
+
+    /// Insert or update entries keyed on (info_hash, site_id), atomically
+    ///
+    /// Returns one flag per entry; false means its site is not configured
+    /// and it was not stored.
+    fn upsert_index_entries(&self, entries: &[IndexExportEntry]) -> Result<Vec<bool>>;
+
+    /// Up to `limit` entries after the row id `after_id`, with their row ids,
+    /// for keyset pagination
+    fn list_index_entries(&self, after_id: i64, limit: usize) -> Result<Vec<(i64, IndexExportEntry)>>;
+
+    /// Store an entry's `(path, size)` file list, replacing a stored one
+    /// unless `only_if_missing`; returns whether it was written
+    fn store_index_files(&self, info_hash: &str, site_id: &str, files: &[(String, u64)], only_if_missing: bool)
+        -> Result<bool>;
+
+    /// File lists of live entries, by the row ids `list_index_entries` returns
+    fn list_index_files(&self) -> Result<HashMap<i64, Vec<(String, u64)>>>;
+
+    fn count_index_entries(&self) -> Result<i64>;
+
+    /// Entry counts per site, largest first
+    fn count_index_by_site(&self) -> Result<Vec<SiteIndexCount>>;
+
+    /// Remove every index entry and fingerprint
+    fn clear_index(&self) -> Result<()>;
+
+    /// Mark a site's entries deleted, returning how many
+    fn clear_index_site(&self, site_id: &str) -> Result<usize>;
+
+    /// Bring back a site's cleared entries, returning how many
+    fn restore_index_site(&self, site_id: &str) -> Result<usize>;
+
+    /// Remove entries cleared before `before`, returning how many
+    fn purge_deleted_index_entries(&self, before: DateTime<Utc>) -> Result<usize>;
+
+    /// Number of entries indexed before `before`
+    fn count_index_entries_before(&self, before: DateTime<Utc>) -> Result<usize>;
+
+    /// Remove entries indexed before `before`, returning how many
+    fn delete_index_entries_before(&self, before: DateTime<Utc>) -> Result<usize>;
+
+    /// Info hashes of the entries imported from a client
+    fn list_client_index_hashes(&self, client_id: &str) -> Result<Vec<String>>;
+
+    /// Remove a client's entries for these info hashes, returning how many
+    fn delete_client_index_hashes(&self, client_id: &str, hashes: &[String]) -> Result<usize>;
+
+    /// Remove fingerprints no index entry refers to, returning how many
+    fn gc_fingerprints(&self) -> Result<usize>;
+
+    /// Stored (domain, site id) mappings for identifying sites by tracker
+    fn list_tracker_domains(&self) -> Result<Vec<(String, String)>>;
+
+    fn insert_history(&self, record: &HistoryRecord) -> Result<()>;
+
+    /// History entries, newest first, optionally limited to one status
+    fn list_history(&self, status: Option<&str>, limit: i64, offset: i64) -> Result<Vec<HistoryEntry>>;
+
+    /// Number of history entries with one of `statuses` recorded today (UTC)
+    fn count_history_today(&self, statuses: &[&str]) -> Result<i64>;
+
+    /// Number of torrents downloaded from a site since midnight (UTC)
+    fn count_added_today(&self, site_id: &str) -> Result<u32>;
+
+    /// Failure counts per target site and reason, most frequent first,
+    /// optionally limited to the last `days` days
+    fn failure_stats(&self, days: Option<u32>) -> Result<Vec<FailureStat>>;
+
+    fn insert_run(&self, run: &RunRecord) -> Result<()>;
+
+    /// Record that a source torrent was reseeded to a target site
+    fn mark_cross_seeded(&self, source_hash: &str, target_site: &str) -> Result<()>;
+
+    /// Every (lowercase source hash, target site) pair reseeded so far
+    fn list_cross_seeded(&self) -> Result<HashSet<(String, String)>>;
+
+    /// Run summaries, newest first
+    fn list_runs(&self, limit: i64, offset: i64) -> Result<Vec<RunEntry>>;
+
+    /// (source hash, target site) pairs already processed by a task's current run
+    fn load_reseed_progress(&self, task_id: &str) -> Result<HashSet<(String, String)>>;
+
+    /// Record that a task's current run processed a match
+    fn record_reseed_progress(&self, task_id: &str, source_hash: &str, target_site: &str) -> Result<()>;
+
+    /// Forget a task's progress once its run completes
+    fn clear_reseed_progress(&self, task_id: &str) -> Result<()>;
+
+    /// Record a request to a client or site; `error` is None when it succeeded
+    fn record_health(&self, kind: HealthKind, id: &str, error: Option<&str>) -> Result<()>;
+}
+
+impl dyn Store {
+    /// Record the outcome of a request to a client or site for `/api/status`
+    ///
+    /// Failing to record it is logged rather than returned, so it never
+    /// fails the request itself.
+    pub fn record_outcome<T, E: std::fmt::Display>(&self, kind: HealthKind, id: &str, outcome: &std::result::Result<T, E>) {
+        let error = outcome.as_ref().err().map(|e| format!("{:#}", e));
+        if let Err(e) = self.record_health(kind, id, error.as_deref()) {
+            warn!("Failed to record the health of {} {}: {}", kind.as_str(), id, e);
+        }
+    }
+}
+
+/// `Store` backed by the SQLite database
+#[derive(Clone)]
+pub struct SqliteStore {
+    db: Database,
+}
+
+impl SqliteStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+impl Store for SqliteStore {
+    fn find_indexed_site(&self, info_hash: &str) -> Result<Option<SiteIdentification>> {
+        Ok(repository::find_indexed_site(&self.db.conn(), info_hash)?)
+    }
+
+    fn index_entry_exists(&self, info_hash: &str, site_id: &str) -> Result<bool> {
+        Ok(repository::index_entry_exists(&self.db.conn(), info_hash, site_id)?)
+    }
+
+    fn insert_index_entry(&self, entry: &IndexExportEntry) -> Result<()> {
+        Ok(repository::insert_index_entry(&self.db.conn(), entry)?)
+    }
+
+    fn update_index_fingerprint(&self, info_hash: &str, site_id: &str, fingerprint: &ContentFingerprint) -> Result<bool> {
+        let conn = self.db.conn();
+        let tx = conn.unchecked_transaction()?;
+        let updated = repository::update_index_fingerprint(&tx, info_hash, site_id, fingerprint)?;
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    fn upsert_index_entries(&self, entries: &[IndexExportEntry]) -> Result<Vec<bool>> {
+        let conn = self.db.conn();
+        let tx = conn.unchecked_transaction()?;
+        let stored = entries
+            .iter()
+            .map(|entry| repository::upsert_index_entry(&tx, entry))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    fn list_index_entries(&self, after_id: i64, limit: usize) -> Result<Vec<(i64, IndexExportEntry)>> {
+        Ok(repository::list_index_entries(&self.db.conn(), after_id, limit)?)
+    }
+
+    fn store_index_files(&self, info_hash: &str, site_id: &str, files: &[(String, u64)], only_if_missing: bool)
+        -> Result<bool> {
+        let conn = self.db.conn();
+        let tx = conn.unchecked_transaction()?;
+        let stored = repository::replace_index_files(&tx, info_hash, site_id, files, only_if_missing)?;
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    fn list_index_files(&self) -> Result<HashMap<i64, Vec<(String, u64)>>> {
+        Ok(repository::list_index_files(&self.db.conn())?)
+    }
+
+    fn count_index_entries(&self) -> Result<i64> {
+        Ok(repository::count_index_entries(&self.db.conn())?)
+    }
+
+    fn count_index_by_site(&self) -> Result<Vec<SiteIndexCount>> {
+        Ok(repository::count_index_by_site(&self.db.conn())?)
+    }
+
+    fn clear_index(&self) -> Result<()> {
+        Ok(repository::clear_index(&self.db.conn())?)
+    }
+
+    fn clear_index_site(&self, site_id: &str) -> Result<usize> {
+        Ok(repository::clear_index_site(&self.db.conn(), site_id)?)
+    }
+
+    fn restore_index_site(&self, site_id: &str) -> Result<usize> {
+        Ok(repository::restore_index_site(&self.db.conn(), site_id)?)
+    }
+
+    fn purge_deleted_index_entries(&self, before: DateTime<Utc>) -> Result<usize> {
+        Ok(repository::purge_deleted_index_entries(&self.db.conn(), before)?)
+    }
+
+    fn count_index_entries_before(&self, before: DateTime<Utc>) -> Result<usize> {
+        Ok(repository::count_index_entries_before(&self.db.conn(), before)?)
+    }
+
+    fn delete_index_entries_before(&self, before: DateTime<Utc>) -> Result<usize> {
+        Ok(repository::delete_index_entries_before(&self.db.conn(), before)?)
+    }
+
+    fn list_client_index_hashes(&self, client_id: &str) -> Result<Vec<String>> {
+        Ok(repository::list_client_index_hashes(&self.db.conn(), client_id)?)
+    }
+
+    fn delete_client_index_hashes(&self, client_id: &str, hashes: &[String]) -> Result<usize> {
+        let conn = self.db.conn();
+        let tx = conn.unchecked_transaction()?;
+        let deleted = repository::delete_client_index_hashes(&tx, client_id, hashes)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn gc_fingerprints(&self) -> Result<usize> {
+        Ok(repository::gc_fingerprints(&self.db.conn())?)
+    }
+
+    fn list_tracker_domains(&self) -> Result<Vec<(String, String)>> {
+        Ok(repository::list_tracker_domains(&self.db.conn())?)
+    }
+
+    fn insert_history(&self, record: &HistoryRecord) -> Result<()> {
+        Ok(repository::insert_history(&self.db.conn(), record)?)
+    }
+
+    fn list_history(&self, status: Option<&str>, limit: i64, offset: i64) -> Result<Vec<HistoryEntry>> {
+        Ok(repository::list_history(&self.db.conn(), status, limit, offset)?)
+    }
+
+    fn count_history_today(&self, statuses: &[&str]) -> Result<i64> {
+        Ok(repository::count_today(&self.db.conn(), statuses)?)
+    }
+
+    fn count_added_today(&self, site_id: &str) -> Result<u32> {
+        Ok(repository::count_added_today(&self.db.conn(), site_id)?)
+    }
+
+    fn failure_stats(&self, days: Option<u32>) -> Result<Vec<FailureStat>> {
+        Ok(repository::failure_stats(&self.db.conn(), days)?)
+    }
+
+    fn insert_run(&self, run: &RunRecord) -> Result<()> {
+        Ok(repository::insert_run(&self.db.conn(), run)?)
+    }
+
+    fn mark_cross_seeded(&self, source_hash: &str, target_site: &str) -> Result<()> {
+        Ok(repository::mark_cross_seeded(&self.db.conn(), source_hash, target_site)?)
+    }
+
+    fn list_cross_seeded(&self) -> Result<HashSet<(String, String)>> {
+        Ok(repository::list_cross_seeded(&self.db.conn())?)
+    }
+
+    fn list_runs(&self, limit: i64, offset: i64) -> Result<Vec<RunEntry>> {
+        Ok(repository::list_runs(&self.db.conn(), limit, offset)?)
+    }
+
+    fn load_reseed_progress(&self, task_id: &str) -> Result<HashSet<(String, String)>> {
+        Ok(repository::load_reseed_progress(&self.db.conn(), task_id)?)
+    }
+
+    fn record_reseed_progress(&self, task_id: &str, source_hash: &str, target_site: &str) -> Result<()> {
+        Ok(repository::record_reseed_progress(&self.db.conn(), task_id, source_hash, target_site)?)
+    }
+
+    fn clear_reseed_progress(&self, task_id: &str) -> Result<()> {
+        repository::clear_reseed_progress(&self.db.conn(), task_id)?;
+        Ok(())
+    }
+
+    fn record_health(&self, kind: HealthKind, id: &str, error: Option<&str>) -> Result<()> {
+        let conn = self.db.conn();
+        match error {
+            None => repository::record_health_ok(&conn, kind, id)?,
+            Some(error) => repository::record_health_error(&conn, kind, id, error)?,
+        }
+        Ok(())
+    }
+}
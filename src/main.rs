@@ -5,7 +5,8 @@
 //! on cloud-based hash matching services.
 
 use anyhow::Result;
-use tracing::info;
+use clap::Parser;
+use tracing::{info, warn};
 
 mod api;
 mod client;
@@ -16,11 +17,14 @@ mod site;
 mod utils;
 
 use api::AppState;
-use config::Settings;
+use config::{CliArgs, Settings};
 use db::Database;
+use service::TaskScheduler;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = CliArgs::parse();
+
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -32,17 +36,42 @@ async fn main() -> Result<()> {
     info!("Starting Graft v{}", env!("CARGO_PKG_VERSION"));
 
     // Load configuration
-    let settings = Settings::load()?;
+    let settings = Settings::load(&args)?;
     info!("Configuration loaded from {:?}", settings.config_path());
 
     // Initialize database
-    let db = Database::new(&settings.database.path)?;
+    utils::init_secret_key(&settings.database.secret_key_path())?;
+    let db = Database::new(&settings.database)?;
     db.migrate()?;
+    let encrypted = db::repository::encrypt_site_passwords(&db.conn())?;
+    if encrypted > 0 {
+        info!("Encrypted {} stored site passwords", encrypted);
+    }
     info!("Database initialized at {:?}", settings.database.path);
 
     // Create application state
     let state = AppState::new(db, settings.clone());
 
+    // Start scheduled reseed tasks
+    let scheduler = if settings.schedule.enabled {
+        let scheduler = TaskScheduler::new(
+            state.db.clone(),
+            state.reseed_service.clone(),
+            state.index_service.clone(),
+            settings.schedule.clone(),
+        )
+        .await?;
+        let count = scheduler.load_tasks().await?;
+        if scheduler.add_purge_job().await? {
+            info!("Cleared index entries are purged after {} days", settings.schedule.index_purge_days);
+        }
+        scheduler.start().await?;
+        info!("Scheduled {} reseed tasks", count);
+        Some(scheduler)
+    } else {
+        None
+    };
+
     // Build router
     let app = api::create_router(state);
 
@@ -50,8 +79,50 @@ async fn main() -> Result<()> {
     let addr = format!("{}:{}", settings.server.host, settings.server.port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     info!("Server listening on http://{}", addr);
+    if settings.server.read_only {
+        info!("Read-only mode enabled: state-changing API requests will be rejected");
+    }
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
 
-    axum::serve(listener, app).await?;
+    // Stop scheduled jobs before exiting
+    if let Some(mut scheduler) = scheduler {
+        scheduler.shutdown().await?;
+    }
+    info!("Server stopped");
 
     Ok(())
 }
+
+/// Resolves on Ctrl+C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("Shutting down");
+}
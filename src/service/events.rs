@@ -0,0 +1,76 @@
+//! Live events for connected dashboards
+//!
+//! Services publish to an `EventBus`; the WebSocket endpoint forwards
+//! everything it receives to its clients.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::service::reseed::ReseedResult;
+
+/// Events buffered per subscriber before the slowest ones start lagging
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// An event pushed to dashboards
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Index entries were added or removed
+    IndexChanged { matcher_version: u64 },
+    /// A reseed run started processing its matches
+    ReseedStarted { task_id: Option<String>, total: usize },
+    /// One match of a reseed run was processed
+    ReseedProgress {
+        task_id: Option<String>,
+        site: String,
+        name: String,
+        outcome: String,
+        processed: usize,
+        total: usize,
+    },
+    /// A reseed run finished
+    ReseedFinished { task_id: Option<String>, result: ReseedResult },
+}
+
+/// Broadcast channel shared by the services that publish events
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Send an event to every subscriber (dropped when nobody listens)
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serialization() {
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+        bus.publish(Event::IndexChanged { matcher_version: 3 });
+
+        let json = serde_json::to_value(events.try_recv().unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "index_changed", "matcher_version": 3}));
+    }
+}
@@ -6,14 +6,15 @@
 
 use serde::{Deserialize, Serialize};
 use sha1_smol::Sha1;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
 
 use crate::client::TorrentFile;
 
 /// Content fingerprint for a torrent
 ///
 /// Used to identify identical content across different sites.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
 pub struct ContentFingerprint {
     /// Total size of all files in bytes (primary matching key)
     pub total_size: u64,
@@ -26,11 +27,216 @@ pub struct ContentFingerprint {
 
     /// Hash of the file list (paths + sizes) for strict matching
     pub files_hash: Option<String>,
+
+    /// Hash of the `FingerprintOptions` used to compute this fingerprint
+    /// (`None` for the default options). Fingerprints are only comparable
+    /// when computed with the same options.
+    #[serde(default)]
+    pub profile_hash: Option<String>,
+
+    /// Broad content class, when the file list was available
+    #[serde(default)]
+    pub media_type: Option<MediaType>,
+
+    /// BitTorrent v2 file tree root, when computed from a v2 or hybrid
+    /// torrent file. Identical roots mean identical files, whatever the
+    /// fingerprint options.
+    #[serde(default)]
+    pub v2_root: Option<String>,
+
+    /// Built from the total size alone because the file list was
+    /// unavailable; file count and largest file are placeholders
+    #[serde(default)]
+    pub size_only: bool,
+
+    /// BitTorrent v1 padding files were left out of the counts, sizes and
+    /// files_hash
+    #[serde(default)]
+    pub padding_excluded: bool,
+}
+
+/// Broad content class of a torrent, used to keep e.g. a movie and an album
+/// of the same total size from matching
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaType {
+    Video,
+    Audio,
+    Other,
+}
+
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mkv", "mp4", "m4v", "avi", "ts", "m2ts", "mts", "wmv", "mov", "vob", "rmvb", "flv", "webm", "iso",
+];
+
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "flac", "mp3", "ape", "wav", "m4a", "aac", "ogg", "opus", "wv", "tak", "dsf", "dff", "alac",
+];
+
+impl MediaType {
+    fn from_file_name(name: &str) -> Self {
+        let ext = std::path::Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match ext.as_deref() {
+            Some(e) if VIDEO_EXTENSIONS.contains(&e) => MediaType::Video,
+            Some(e) if AUDIO_EXTENSIONS.contains(&e) => MediaType::Audio,
+            _ => MediaType::Other,
+        }
+    }
+}
+
+impl std::fmt::Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaType::Video => write!(f, "video"),
+            MediaType::Audio => write!(f, "audio"),
+            MediaType::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl std::str::FromStr for MediaType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "video" => Ok(MediaType::Video),
+            "audio" => Ok(MediaType::Audio),
+            "other" => Ok(MediaType::Other),
+            _ => Err(format!("Unknown media type: {}", s)),
+        }
+    }
+}
+
+/// Classify a torrent by the class holding the most bytes
+///
+/// Returns `None` for an empty file list.
+pub fn classify_media(files: &[TorrentFile]) -> Option<MediaType> {
+    let mut bytes: HashMap<MediaType, u64> = HashMap::new();
+    for file in files {
+        *bytes.entry(MediaType::from_file_name(&file.name)).or_default() += file.size;
+    }
+
+    // max_by_key keeps the last maximum, so ties favour video, then audio
+    [MediaType::Other, MediaType::Audio, MediaType::Video]
+        .into_iter()
+        .filter_map(|t| bytes.get(&t).map(|&b| (t, b)))
+        .max_by_key(|&(_, b)| b)
+        .map(|(t, _)| t)
+}
+
+/// Whether a file is a BitTorrent v1 padding file (`.pad/N` as written by
+/// libtorrent, or `_____padding_file_N` as written by BitComet)
+pub fn is_padding_file(file_name: &str) -> bool {
+    let mut components = file_name.split(['/', '\\']);
+    let base = components.next_back().unwrap_or_default();
+    base.starts_with("_____padding_file") || components.next_back() == Some(".pad")
+}
+
+/// Options controlling which files participate in a fingerprint
+#[derive(Debug, Clone)]
+pub struct FingerprintOptions {
+    /// Lowercased file extensions (without the leading dot) to leave out
+    ignore_extensions: Vec<String>,
+    /// Hash file paths without their top-level directory, so `Movie.mkv`
+    /// and `Movie/Movie.mkv` get the same files_hash
+    strip_root_dir: bool,
+    /// Leave out padding files, which only some sites' torrents carry
+    exclude_padding: bool,
+}
+
+impl Default for FingerprintOptions {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl FingerprintOptions {
+    pub fn new(ignore_extensions: &[String]) -> Self {
+        let mut ignore_extensions: Vec<String> = ignore_extensions
+            .iter()
+            .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+        ignore_extensions.sort();
+        ignore_extensions.dedup();
+
+        Self {
+            ignore_extensions,
+            strip_root_dir: false,
+            exclude_padding: true,
+        }
+    }
+
+    pub fn with_strip_root_dir(mut self, strip_root_dir: bool) -> Self {
+        self.strip_root_dir = strip_root_dir;
+        self
+    }
+
+    pub fn with_exclude_padding(mut self, exclude_padding: bool) -> Self {
+        self.exclude_padding = exclude_padding;
+        self
+    }
+
+    /// Path of a file as it goes into the files_hash
+    pub fn hashed_name<'a>(&self, file_name: &'a str) -> &'a str {
+        if !self.strip_root_dir {
+            return file_name;
+        }
+
+        match file_name.split_once(['/', '\\']) {
+            Some((_, rest)) => rest,
+            None => file_name,
+        }
+    }
+
+    /// Whether a file participates in the fingerprint
+    pub fn includes(&self, file_name: &str) -> bool {
+        if self.exclude_padding && is_padding_file(file_name) {
+            return false;
+        }
+        if self.ignore_extensions.is_empty() {
+            return true;
+        }
+
+        match std::path::Path::new(file_name).extension().and_then(|e| e.to_str()) {
+            Some(ext) => !self.ignore_extensions.iter().any(|i| i.eq_ignore_ascii_case(ext)),
+            None => true,
+        }
+    }
+
+    /// Hash identifying these options, `None` for the defaults
+    ///
+    /// Padding exclusion is not part of it: fingerprints of torrents without
+    /// padding files are the same either way, and those with them are
+    /// marked by `padding_excluded`.
+    pub fn profile_hash(&self) -> Option<String> {
+        if self.ignore_extensions.is_empty() && !self.strip_root_dir {
+            return None;
+        }
+
+        let mut hasher = Sha1::new();
+        hasher.update(b"ignore_extensions:");
+        hasher.update(self.ignore_extensions.join(",").as_bytes());
+        // Only hashed when set, so existing profiles keep their hash
+        if self.strip_root_dir {
+            hasher.update(b";strip_root_dir");
+        }
+        Some(hasher.digest().to_string())
+    }
 }
 
 impl ContentFingerprint {
-    /// Create a fingerprint from a list of torrent files
-    pub fn from_files(files: &[TorrentFile]) -> Self {
+    /// Create a fingerprint from a list of torrent files, leaving out files
+    /// excluded by `options`
+    pub fn from_files_with_options(files: &[TorrentFile], options: &FingerprintOptions) -> Self {
+        let media_type = classify_media(files);
+        let padding_excluded = options.exclude_padding && files.iter().any(|f| is_padding_file(&f.name));
+        let files: Vec<&TorrentFile> = files.iter().filter(|f| options.includes(&f.name)).collect();
+
         let total_size: u64 = files.iter().map(|f| f.size).sum();
         let file_count = files.len();
         let largest_file_size = files.iter().map(|f| f.size).max().unwrap_or(0);
@@ -40,12 +246,15 @@ impl ContentFingerprint {
             let mut hasher = Sha1::new();
 
             // Sort files by name for consistent hashing
-            let mut sorted_files: Vec<_> = files.iter().collect();
-            sorted_files.sort_by(|a, b| a.name.cmp(&b.name));
+            let mut sorted_files: Vec<(&str, u64)> = files
+                .iter()
+                .map(|f| (options.hashed_name(&f.name), f.size))
+                .collect();
+            sorted_files.sort();
 
-            for file in sorted_files {
-                hasher.update(file.name.as_bytes());
-                hasher.update(&file.size.to_le_bytes());
+            for (name, size) in sorted_files {
+                hasher.update(name.as_bytes());
+                hasher.update(&size.to_le_bytes());
             }
 
             Some(hasher.digest().to_string())
@@ -58,6 +267,11 @@ impl ContentFingerprint {
             file_count,
             largest_file_size,
             files_hash,
+            profile_hash: options.profile_hash(),
+            media_type,
+            v2_root: None,
+            size_only: false,
+            padding_excluded,
         }
     }
 
@@ -68,6 +282,22 @@ impl ContentFingerprint {
             file_count,
             largest_file_size,
             files_hash: None,
+            profile_hash: None,
+            media_type: None,
+            v2_root: None,
+            size_only: false,
+            padding_excluded: false,
+        }
+    }
+
+    /// Create a fingerprint for a torrent whose file list is unavailable
+    ///
+    /// Matches against it are capped at `MediumConfidence`, since nothing
+    /// but the total size can be compared.
+    pub fn size_only(total_size: u64) -> Self {
+        Self {
+            size_only: true,
+            ..Self::from_size(total_size, 1, total_size)
         }
     }
 
@@ -78,12 +308,28 @@ impl ContentFingerprint {
     /// 2. File count should be close (allowing for small metadata files)
     /// 3. Largest file size should match (high confidence)
     /// 4. If files_hash is available, use for verification
+    ///
+    /// Two v2 fingerprints are compared on their file tree roots alone.
     pub fn matches(&self, other: &ContentFingerprint) -> MatchResult {
+        if let (Some(a), Some(b)) = (&self.v2_root, &other.v2_root) {
+            return if a == b { MatchResult::ExactMatch } else { MatchResult::NoMatch };
+        }
+
+        // Fingerprints computed with different options are not comparable
+        if self.profile_hash != other.profile_hash {
+            return MatchResult::NoMatch;
+        }
+
         // Primary key: total size must match exactly
         if self.total_size != other.total_size {
             return MatchResult::NoMatch;
         }
 
+        // Nothing else is known about a size-only fingerprint
+        if self.size_only || other.size_only {
+            return MatchResult::MediumConfidence;
+        }
+
         // If files_hash is available on both, use it for definitive matching
         if let (Some(ref hash1), Some(ref hash2)) = (&self.files_hash, &other.files_hash) {
             if hash1 == hash2 {
@@ -113,6 +359,87 @@ impl ContentFingerprint {
             MatchResult::MediumConfidence
         }
     }
+
+    /// Weighted similarity to `other` in 0.0 - 1.0
+    ///
+    /// Identical files hashes score 1.0. Against a size-only fingerprint only
+    /// the total size and name count, and the score is capped at 0.7.
+    /// Otherwise each component scores
+    /// 0.0 - 1.0 and is weighted; the name component only counts when both
+    /// names are known (`names`), the other weights are rescaled otherwise.
+    pub fn score(
+        &self,
+        other: &ContentFingerprint,
+        names: Option<(&str, &str)>,
+        weights: &ScoringWeights,
+    ) -> f64 {
+        if let (Some(a), Some(b)) = (&self.v2_root, &other.v2_root) {
+            if a == b {
+                return 1.0;
+            }
+        }
+        if let (Some(a), Some(b)) = (&self.files_hash, &other.files_hash) {
+            if a == b {
+                return 1.0;
+            }
+        }
+
+        let exact = |a: u64, b: u64| if a == b { 1.0 } else { 0.0 };
+        let max_count = self.file_count.max(other.file_count).max(1) as f64;
+        let count_diff = self.file_count.abs_diff(other.file_count) as f64;
+
+        let size_only = self.size_only || other.size_only;
+        let mut total = weights.total_size * exact(self.total_size, other.total_size);
+        let mut weight_sum = weights.total_size;
+        // A size-only fingerprint's file count and largest file are placeholders
+        if !size_only {
+            total += weights.largest_file * exact(self.largest_file_size, other.largest_file_size)
+                + weights.file_count * (1.0 - count_diff / max_count);
+            weight_sum += weights.largest_file + weights.file_count;
+        }
+
+        if let Some((a, b)) = names {
+            total += weights.name * name_similarity(a, b);
+            weight_sum += weights.name;
+        }
+
+        if weight_sum <= 0.0 {
+            return 0.0;
+        }
+        let score = (total / weight_sum).clamp(0.0, 1.0);
+        if size_only {
+            score.min(MatchResult::MediumConfidence.confidence())
+        } else {
+            score
+        }
+    }
+
+    /// Describe which fingerprint fields a match against `other` was based on
+    pub fn explain_match(&self, other: &ContentFingerprint, result: MatchResult) -> String {
+        if result.is_match() && (self.size_only || other.size_only) {
+            return "total_size only; file list unavailable".to_string();
+        }
+
+        let basis = match result {
+            MatchResult::NoMatch => return "no match".to_string(),
+            MatchResult::ExactMatch if self.v2_root.is_some() && self.v2_root == other.v2_root => {
+                return "identical v2 file tree".to_string()
+            }
+            MatchResult::ExactMatch => return "exact files_hash".to_string(),
+            MatchResult::HighConfidence => "total_size + largest_file + file_count".to_string(),
+            MatchResult::MediumConfidence => format!(
+                "total_size + largest_file (file_count {} vs {})",
+                self.file_count, other.file_count
+            ),
+            MatchResult::LowConfidence => "total_size only".to_string(),
+        };
+
+        match (&self.files_hash, &other.files_hash) {
+            (None, None) => format!("{}; files_hash unavailable", basis),
+            (None, Some(_)) | (Some(_), None) => format!("{}; files_hash missing on one side", basis),
+            (Some(_), Some(_)) => basis,
+        }
+    }
 }
 
 /// Result of fingerprint matching
@@ -151,10 +478,66 @@ impl MatchResult {
     }
 }
 
+/// Weights for scored matching (see `ContentFingerprint::score`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringWeights {
+    pub total_size: f64,
+    pub largest_file: f64,
+    pub file_count: f64,
+    pub name: f64,
+    /// Minimum score for a candidate to be returned as a match
+    pub min_score: f64,
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        Self {
+            total_size: 0.4,
+            largest_file: 0.3,
+            file_count: 0.1,
+            name: 0.2,
+            min_score: 0.7,
+        }
+    }
+}
+
+/// Similarity of two torrent names in 0.0 - 1.0, ignoring case and
+/// punctuation (Jaccard index of their word sets)
+fn name_similarity(a: &str, b: &str) -> f64 {
+    fn words(name: &str) -> HashSet<String> {
+        name.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
 /// Fingerprint matcher for finding matching content across sites
 pub struct FingerprintMatcher {
-    /// Fingerprints indexed by total_size for fast lookup
-    size_index: HashMap<u64, Vec<FingerprintEntry>>,
+    entries: Vec<FingerprintEntry>,
+    /// Entry indices by total_size for fast lookup
+    size_index: HashMap<u64, Vec<usize>>,
+    /// Entry indices by lowercase info_hash, for hash-identical torrents
+    hash_index: HashMap<String, Vec<usize>>,
+    /// Refuse matches between different media types (when both are known)
+    require_same_media_type: bool,
+    /// Score candidates with these weights instead of the fixed match levels
+    scoring: Option<ScoringWeights>,
+    /// Size buckets with more entries than this only match on identical file lists
+    max_candidates_per_bucket: Option<usize>,
+    /// Keep file lists to find entries containing another's files
+    subset_matching: bool,
+    /// (lowercase base name, size) of each file, by entry index
+    file_lists: HashMap<usize, Vec<(String, u64)>>,
+    /// Indices of the entries with a file of each size
+    file_size_index: HashMap<u64, Vec<usize>>,
 }
 
 #[derive(Debug, Clone)]
@@ -170,71 +553,313 @@ pub struct FingerprintEntry {
 impl FingerprintMatcher {
     pub fn new() -> Self {
         Self {
+            entries: Vec::new(),
             size_index: HashMap::new(),
+            hash_index: HashMap::new(),
+            require_same_media_type: false,
+            scoring: None,
+            max_candidates_per_bucket: None,
+            subset_matching: false,
+            file_lists: HashMap::new(),
+            file_size_index: HashMap::new(),
         }
     }
 
+    pub fn with_require_same_media_type(mut self, require: bool) -> Self {
+        self.require_same_media_type = require;
+        self
+    }
+
+    pub fn with_scoring(mut self, scoring: Option<ScoringWeights>) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    pub fn with_max_candidates_per_bucket(mut self, max: Option<usize>) -> Self {
+        self.max_candidates_per_bucket = max;
+        self
+    }
+
+    /// Keep the file lists given to `add_with_files` for `find_packs`
+    pub fn with_subset_matching(mut self, enabled: bool) -> Self {
+        self.subset_matching = enabled;
+        self
+    }
+
+    /// Size buckets over `max_candidates_per_bucket`, as (total size, entry count)
+    pub fn oversized_buckets(&self) -> Vec<(u64, usize)> {
+        let Some(max) = self.max_candidates_per_bucket else {
+            return Vec::new();
+        };
+        let mut buckets: Vec<_> = self
+            .size_index
+            .iter()
+            .filter(|(_, indices)| indices.len() > max)
+            .map(|(&size, indices)| (size, indices.len()))
+            .collect();
+        buckets.sort_unstable_by_key(|&(_, count)| std::cmp::Reverse(count));
+        buckets
+    }
+
     /// Add a fingerprint entry to the matcher
     pub fn add(&mut self, entry: FingerprintEntry) {
-        let size = entry.fingerprint.total_size;
-        self.size_index.entry(size).or_default().push(entry);
+        let idx = self.entries.len();
+        self.size_index.entry(entry.fingerprint.total_size).or_default().push(idx);
+        self.hash_index.entry(entry.info_hash.to_lowercase()).or_default().push(idx);
+        self.entries.push(entry);
+    }
+
+    /// The entry for a torrent on a site
+    pub fn get(&self, info_hash: &str, site_id: &str) -> Option<&FingerprintEntry> {
+        self.hash_index
+            .get(&info_hash.to_lowercase())?
+            .iter()
+            .map(|&idx| &self.entries[idx])
+            .find(|entry| entry.site_id == site_id)
+    }
+
+    /// Add an entry with its `(path, size)` file list
+    ///
+    /// The file list is only kept with subset matching enabled.
+    pub fn add_with_files(&mut self, entry: FingerprintEntry, files: &[(String, u64)]) {
+        let idx = self.entries.len();
+        self.add(entry);
+        if !self.subset_matching || files.is_empty() {
+            return;
+        }
+
+        let files = subset_keys(files);
+        let mut sizes: Vec<u64> = files.iter().map(|&(_, size)| size).collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+        for size in sizes {
+            self.file_size_index.entry(size).or_default().push(idx);
+        }
+        self.file_lists.insert(idx, files);
     }
 
-    /// Find matching entries for a given fingerprint
+    /// Entries whose files include every file of `files` (by name and size)
+    /// plus others, e.g. season packs containing an episode
     ///
-    /// Returns entries that match with medium confidence or higher.
-    pub fn find_matches(&self, fingerprint: &ContentFingerprint) -> Vec<MatchedEntry> {
+    /// These are not reseed matches: the pack holds more data than the
+    /// torrent. Smallest packs first; empty without subset matching.
+    pub fn find_packs(&self, info_hash: &str, files: &[(String, u64)]) -> Vec<SubsetMatch> {
+        let files = subset_keys(files);
+        let Some(&(_, largest)) = files.iter().max_by_key(|&&(_, size)| size) else {
+            return Vec::new();
+        };
+        let Some(candidates) = self.file_size_index.get(&largest) else {
+            return Vec::new();
+        };
+
+        let mut packs = Vec::new();
+        for &idx in candidates {
+            let entry = &self.entries[idx];
+            let pack_files = &self.file_lists[&idx];
+            if pack_files.len() <= files.len() || entry.info_hash.eq_ignore_ascii_case(info_hash) {
+                continue;
+            }
+
+            let mut available: HashMap<(&str, u64), usize> = HashMap::new();
+            for (name, size) in pack_files {
+                *available.entry((name.as_str(), *size)).or_default() += 1;
+            }
+            let contained = files.iter().all(|(name, size)| match available.get_mut(&(name.as_str(), *size)) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
+                }
+                _ => false,
+            });
+
+            if contained {
+                packs.push(SubsetMatch {
+                    entry: entry.clone(),
+                    matched_files: files.len(),
+                    pack_file_count: pack_files.len(),
+                });
+            }
+        }
+
+        packs.sort_by_key(|p| (p.entry.fingerprint.total_size, p.entry.info_hash.clone()));
+        packs
+    }
+
+    /// Entries with a kept file list, as `(path, size)` keys for `find_packs`
+    pub fn entries_with_files(&self) -> impl Iterator<Item = (&FingerprintEntry, &[(String, u64)])> {
+        self.file_lists.iter().map(|(&idx, files)| (&self.entries[idx], files.as_slice()))
+    }
+
+    /// Find matching entries for a torrent
+    ///
+    /// Entries with the same info hash are exact matches without comparing
+    /// fingerprints. Other entries are returned if their fingerprint matches
+    /// with medium confidence or higher, or, with scoring enabled, if their
+    /// score reaches the minimum score. A zero-size fingerprint only matches
+    /// by info hash. `name` is compared too when scoring.
+    pub fn find_matches_named(
+        &self,
+        info_hash: &str,
+        name: Option<&str>,
+        fingerprint: &ContentFingerprint,
+    ) -> Vec<MatchedEntry> {
         let mut matches = Vec::new();
 
-        // Fast lookup by size
-        if let Some(candidates) = self.size_index.get(&fingerprint.total_size) {
-            for candidate in candidates {
+        // Identical info hash: same torrent, nothing to compare
+        let same_hash = self
+            .hash_index
+            .get(&info_hash.to_lowercase())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for &idx in same_hash {
+            matches.push(MatchedEntry {
+                entry: self.entries[idx].clone(),
+                match_result: MatchResult::ExactMatch,
+                score: None,
+            });
+        }
+
+        // Fast lookup by size. Zero-size torrents (no metadata yet) would
+        // collide with every other empty torrent, so only match them by hash.
+        let candidates = match fingerprint.total_size {
+            0 => None,
+            size => self.size_index.get(&size),
+        };
+        if let Some(candidates) = candidates {
+            // Too many entries of this size to compare fuzzily with any precision
+            let strict = self
+                .max_candidates_per_bucket
+                .is_some_and(|max| candidates.len() > max);
+
+            for &idx in candidates {
+                if same_hash.contains(&idx) {
+                    continue;
+                }
+                let candidate = &self.entries[idx];
+                if strict
+                    && (fingerprint.files_hash.is_none()
+                        || fingerprint.files_hash != candidate.fingerprint.files_hash)
+                {
+                    continue;
+                }
+                if self.require_same_media_type && !same_media_type(fingerprint, &candidate.fingerprint) {
+                    continue;
+                }
                 let result = fingerprint.matches(&candidate.fingerprint);
-                if result.is_match() {
+
+                if let Some(weights) = &self.scoring {
+                    // Options still have to agree for fingerprints to be comparable
+                    if fingerprint.profile_hash != candidate.fingerprint.profile_hash {
+                        continue;
+                    }
+                    let names = name.zip(candidate.name.as_deref());
+                    let score = fingerprint.score(&candidate.fingerprint, names, weights);
+                    if score >= weights.min_score {
+                        matches.push(MatchedEntry {
+                            entry: candidate.clone(),
+                            match_result: result,
+                            score: Some(score),
+                        });
+                    }
+                } else if result.is_match() {
                     matches.push(MatchedEntry {
                         entry: candidate.clone(),
                         match_result: result,
+                        score: None,
                     });
                 }
             }
         }
 
         // Sort by confidence (highest first)
-        matches.sort_by(|a, b| {
-            b.match_result
-                .confidence()
-                .partial_cmp(&a.match_result.confidence())
-                .unwrap()
-        });
+        matches.sort_by(|a, b| b.confidence().partial_cmp(&a.confidence()).unwrap());
 
         matches
     }
 
-    /// Find matches for a torrent, excluding entries from the same site
-    pub fn find_cross_site_matches(
-        &self,
-        fingerprint: &ContentFingerprint,
-        exclude_site: &str,
-    ) -> Vec<MatchedEntry> {
-        self.find_matches(fingerprint)
-            .into_iter()
-            .filter(|m| m.entry.site_id != exclude_site)
-            .collect()
+    /// Group entries of one site holding the same content under different
+    /// info hashes
+    ///
+    /// Zero-size entries are never grouped. Entries with a files hash are
+    /// grouped on it (and the options it was computed with); entries without
+    /// one fall back to a high-confidence match on total size, largest file
+    /// and file count, and size-only entries to a medium-confidence match on
+    /// total size. The same content on different sites is a cross-seed
+    /// sharing one copy on disk, so groups never span sites. With `site_id`,
+    /// only that site's entries are considered. Groups are sorted by the disk
+    /// space their extra copies take.
+    pub fn find_duplicates(&self, site_id: Option<&str>) -> Vec<DuplicateGroup> {
+        let mut groups = Vec::new();
+
+        for (&size, indices) in &self.size_index {
+            if size == 0 {
+                continue;
+            }
+            // Copies on different sites are cross-seeds, so groups stay within a site
+            let mut by_key: HashMap<(&str, DuplicateKey), Vec<usize>> = HashMap::new();
+            for &idx in indices {
+                let entry = &self.entries[idx];
+                if site_id.is_some_and(|site| entry.site_id != site) {
+                    continue;
+                }
+                let fingerprint = &entry.fingerprint;
+                let key = match &fingerprint.files_hash {
+                    Some(files_hash) => DuplicateKey::Files(files_hash, &fingerprint.profile_hash),
+                    None if fingerprint.size_only => DuplicateKey::SizeOnly,
+                    None => DuplicateKey::Shape(fingerprint.largest_file_size, fingerprint.file_count),
+                };
+                by_key.entry((entry.site_id.as_str(), key)).or_default().push(idx);
+            }
+
+            for ((_, key), members) in by_key {
+                let hashes: HashSet<String> = members
+                    .iter()
+                    .map(|&idx| self.entries[idx].info_hash.to_lowercase())
+                    .collect();
+                if hashes.len() < 2 {
+                    continue;
+                }
+                groups.push(DuplicateGroup {
+                    match_result: match key {
+                        DuplicateKey::Files(..) => MatchResult::ExactMatch,
+                        DuplicateKey::Shape(..) => MatchResult::HighConfidence,
+                        DuplicateKey::SizeOnly => MatchResult::MediumConfidence,
+                    },
+                    copies: hashes.len(),
+                    entries: members.into_iter().map(|idx| self.entries[idx].clone()).collect(),
+                });
+            }
+        }
+
+        groups.sort_by_key(|group| std::cmp::Reverse(group.wasted_size()));
+        groups
     }
 
     /// Get total number of entries
     pub fn len(&self) -> usize {
-        self.size_index.values().map(|v| v.len()).sum()
+        self.entries.len()
     }
+}
 
-    /// Check if the matcher is empty
-    pub fn is_empty(&self) -> bool {
-        self.size_index.is_empty()
-    }
+/// Files compared by lowercase base name and size, so a pack's folder
+/// layout does not matter; padding files are left out
+fn subset_keys(files: &[(String, u64)]) -> Vec<(String, u64)> {
+    files
+        .iter()
+        .filter(|(name, _)| !is_padding_file(name))
+        .map(|(name, size)| {
+            let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+            (base.to_lowercase(), *size)
+        })
+        .collect()
+}
 
-    /// Clear all entries
-    pub fn clear(&mut self) {
-        self.size_index.clear();
+/// Whether two fingerprints are of the same media type, treating an unknown
+/// type as compatible with anything
+fn same_media_type(a: &ContentFingerprint, b: &ContentFingerprint) -> bool {
+    match (a.media_type, b.media_type) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
     }
 }
 
@@ -244,11 +869,69 @@ impl Default for FingerprintMatcher {
     }
 }
 
+#[derive(PartialEq, Eq, Hash)]
+enum DuplicateKey<'a> {
+    /// Files hash and the options it was computed with
+    Files(&'a str, &'a Option<String>),
+    /// Largest file size and file count, within one total size
+    Shape(u64, usize),
+    /// Size-only fingerprints, grouped on total size alone
+    SizeOnly,
+}
+
+/// Index entries with the same content under different info hashes
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    /// `ExactMatch` for identical file lists, `HighConfidence` for a size
+    /// and structure match, `MediumConfidence` for size-only fingerprints
+    pub match_result: MatchResult,
+    /// Number of distinct info hashes in the group
+    pub copies: usize,
+    pub entries: Vec<FingerprintEntry>,
+}
+
+impl DuplicateGroup {
+    /// Bytes taken by all copies but one
+    pub fn wasted_size(&self) -> u64 {
+        let size = self.entries.first().map_or(0, |e| e.fingerprint.total_size);
+        size * (self.copies as u64).saturating_sub(1)
+    }
+}
+
 /// A matched entry with its match result
 #[derive(Debug, Clone)]
 pub struct MatchedEntry {
     pub entry: FingerprintEntry,
     pub match_result: MatchResult,
+    /// Weighted score, when the matcher uses scoring
+    pub score: Option<f64>,
+}
+
+impl MatchedEntry {
+    /// Confidence of this match (0.0 - 1.0)
+    pub fn confidence(&self) -> f64 {
+        self.score.unwrap_or_else(|| self.match_result.confidence())
+    }
+
+    /// Why a torrent with `info_hash` and `fingerprint` matched this entry
+    pub fn reason(&self, info_hash: &str, fingerprint: &ContentFingerprint) -> String {
+        if self.entry.info_hash.eq_ignore_ascii_case(info_hash) {
+            "identical info_hash".to_string()
+        } else if let Some(score) = self.score {
+            format!("weighted score {:.2}", score)
+        } else {
+            fingerprint.explain_match(&self.entry.fingerprint, self.match_result)
+        }
+    }
+}
+
+/// An entry containing every file of a torrent plus others
+#[derive(Debug, Clone)]
+pub struct SubsetMatch {
+    /// The larger torrent, e.g. a season pack
+    pub entry: FingerprintEntry,
+    pub matched_files: usize,
+    pub pack_file_count: usize,
 }
 
 #[cfg(test)]
@@ -270,8 +953,8 @@ mod tests {
             },
         ];
 
-        let fp1 = ContentFingerprint::from_files(&files);
-        let fp2 = ContentFingerprint::from_files(&files);
+        let fp1 = fingerprint_of(&files);
+        let fp2 = fingerprint_of(&files);
 
         assert_eq!(fp1.matches(&fp2), MatchResult::ExactMatch);
     }
@@ -291,4 +974,345 @@ mod tests {
 
         assert_eq!(fp1.matches(&fp2), MatchResult::NoMatch);
     }
+
+    #[test]
+    fn test_ignore_extensions() {
+        let files = vec![
+            TorrentFile {
+                name: "Movie/movie.mkv".to_string(),
+                size: 10_000_000_000,
+                progress: 1.0,
+            },
+            TorrentFile {
+                name: "Movie/Sample/sample.MKV".to_string(),
+                size: 50_000_000,
+                progress: 1.0,
+            },
+            TorrentFile {
+                name: "Movie/movie.srt".to_string(),
+                size: 1000,
+                progress: 1.0,
+            },
+        ];
+
+        let options = FingerprintOptions::new(&[".srt".to_string()]);
+        let fp = ContentFingerprint::from_files_with_options(&files, &options);
+
+        assert_eq!(fp.file_count, 2);
+        assert_eq!(fp.total_size, 10_050_000_000);
+        assert!(fp.profile_hash.is_some());
+
+        // Not comparable with a fingerprint computed without the ignore list
+        let plain = fingerprint_of(&files[..2]);
+        assert_eq!(fp.matches(&plain), MatchResult::NoMatch);
+    }
+
+    #[test]
+    fn test_padding_files_excluded() {
+        let file = |name: &str, size: u64| TorrentFile {
+            name: name.to_string(),
+            size,
+            progress: 1.0,
+        };
+        let plain = vec![file("Album/01.flac", 30_000_000), file("Album/02.flac", 25_000_000)];
+        let padded = vec![
+            file("Album/01.flac", 30_000_000),
+            file("Album/.pad/12345", 12345),
+            file("Album/02.flac", 25_000_000),
+            file("Album/_____padding_file_1_if you see this file, please update to BitComet 0.85 or above____", 800),
+        ];
+
+        assert!(is_padding_file("Album/.pad/12345"));
+        assert!(!is_padding_file("Album/.pad"));
+        assert!(!is_padding_file("Album/notes.pad"));
+
+        let fp_plain = fingerprint_of(&plain);
+        let fp_padded = fingerprint_of(&padded);
+        assert!(!fp_plain.padding_excluded);
+        assert!(fp_padded.padding_excluded);
+        assert_eq!(fp_padded.file_count, 2);
+        assert_eq!(fp_padded.files_hash, fp_plain.files_hash);
+        assert_eq!(fp_padded.matches(&fp_plain), MatchResult::ExactMatch);
+
+        let options = FingerprintOptions::default().with_exclude_padding(false);
+        let fp_kept = ContentFingerprint::from_files_with_options(&padded, &options);
+        assert!(!fp_kept.padding_excluded);
+        assert_eq!(fp_kept.file_count, 4);
+        assert_eq!(fp_kept.total_size, 55_013_145);
+    }
+
+    #[test]
+    fn test_strip_root_dir_matches_wrapped_single_file() {
+        let bare = vec![TorrentFile {
+            name: "Movie.mkv".to_string(),
+            size: 10_000_000_000,
+            progress: 1.0,
+        }];
+        let wrapped = vec![TorrentFile {
+            name: "Movie/Movie.mkv".to_string(),
+            size: 10_000_000_000,
+            progress: 1.0,
+        }];
+
+        let plain_bare = fingerprint_of(&bare);
+        let plain_wrapped = fingerprint_of(&wrapped);
+        assert_ne!(plain_bare.files_hash, plain_wrapped.files_hash);
+
+        let options = FingerprintOptions::default().with_strip_root_dir(true);
+        let fp_bare = ContentFingerprint::from_files_with_options(&bare, &options);
+        let fp_wrapped = ContentFingerprint::from_files_with_options(&wrapped, &options);
+        assert_eq!(fp_bare.files_hash, fp_wrapped.files_hash);
+        assert_eq!(fp_bare.matches(&fp_wrapped), MatchResult::ExactMatch);
+
+        // Never compared with fingerprints computed without normalization
+        assert!(fp_bare.profile_hash.is_some());
+        assert_eq!(fp_bare.matches(&plain_bare), MatchResult::NoMatch);
+    }
+
+    #[test]
+    fn test_scored_matching() {
+        let mut matcher = FingerprintMatcher::new().with_scoring(Some(ScoringWeights::default()));
+        // Same size and largest file, one extra file and a similar name
+        let fingerprint = ContentFingerprint::from_size(1000, 3, 900);
+        matcher.add(entry("a", "hdsky", Some("Movie.2020.1080p.BluRay-GRP"), fingerprint));
+        // Same size only, unrelated name
+        matcher.add(entry("b", "hdsky", Some("Other.Show.S01"), ContentFingerprint::from_size(1000, 9, 100)));
+
+        let query = ContentFingerprint::from_size(1000, 2, 900);
+        let matches = matcher.find_matches_named("x", Some("Movie 2020 1080p BluRay"), &query);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].entry.info_hash, "a");
+        let score = matches[0].score.unwrap();
+        assert!(score > 0.85 && score < 1.0, "{}", score);
+        assert_eq!(matches[0].confidence(), score);
+
+        // Without names the remaining weights are rescaled
+        let unnamed = matcher.find_matches_named("x", None, &query);
+        assert!(unnamed[0].score.unwrap() > score);
+    }
+
+    #[test]
+    fn test_name_similarity() {
+        assert_eq!(name_similarity("Movie.2020", "movie 2020"), 1.0);
+        assert_eq!(name_similarity("Movie.2020", "Other.Show"), 0.0);
+        assert_eq!(name_similarity("", ""), 0.0);
+    }
+
+    #[test]
+    fn test_explain_match() {
+        let fp1 = ContentFingerprint::from_size(10_000_001_000, 2, 10_000_000_000);
+        let fp2 = ContentFingerprint::from_size(10_000_001_000, 3, 10_000_000_000);
+
+        let result = fp1.matches(&fp2);
+        assert_eq!(result, MatchResult::MediumConfidence);
+        assert_eq!(
+            fp1.explain_match(&fp2, result),
+            "total_size + largest_file (file_count 2 vs 3); files_hash unavailable"
+        );
+    }
+
+    #[test]
+    fn test_v2_root_match() {
+        let v2 = |root: &str| ContentFingerprint {
+            v2_root: Some(root.to_string()),
+            ..ContentFingerprint::from_size(1000, 2, 700)
+        };
+
+        assert_eq!(v2("a").matches(&v2("a")), MatchResult::ExactMatch);
+        assert_eq!(v2("a").explain_match(&v2("a"), MatchResult::ExactMatch), "identical v2 file tree");
+        assert_eq!(v2("a").matches(&v2("b")), MatchResult::NoMatch);
+
+        // Against a v1 fingerprint, size matching still applies
+        let v1 = ContentFingerprint::from_size(1000, 2, 700);
+        assert_eq!(v2("a").matches(&v1), MatchResult::HighConfidence);
+    }
+
+    #[test]
+    fn test_size_only_match() {
+        let size_only = ContentFingerprint::size_only(1000);
+        let multi = ContentFingerprint::from_size(1000, 3, 800);
+
+        // Neither the placeholder file count nor largest file counts against it
+        assert_eq!(size_only.matches(&multi), MatchResult::MediumConfidence);
+        assert_eq!(multi.matches(&size_only), MatchResult::MediumConfidence);
+        // Two size-only fingerprints are no stronger
+        assert_eq!(size_only.matches(&ContentFingerprint::size_only(1000)), MatchResult::MediumConfidence);
+        assert_eq!(size_only.matches(&ContentFingerprint::size_only(2000)), MatchResult::NoMatch);
+        assert_eq!(
+            size_only.explain_match(&multi, MatchResult::MediumConfidence),
+            "total_size only; file list unavailable"
+        );
+
+        let weights = ScoringWeights::default();
+        assert_eq!(size_only.score(&multi, None, &weights), 0.7);
+    }
+
+    #[test]
+    fn test_identical_info_hash_is_exact_match() {
+        let mut matcher = FingerprintMatcher::new();
+        // Same torrent on a mirror, indexed with a fingerprint that would not match
+        matcher.add(entry("ABCDEF", "mirror", None, ContentFingerprint::from_size(1, 1, 1)));
+        matcher.add(entry("123456", "hdsky", None, ContentFingerprint::from_size(1000, 2, 900)));
+
+        let query = ContentFingerprint::from_size(1000, 2, 900);
+        let matches = matcher.find_matches_named("abcdef", None, &query);
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].entry.site_id, "mirror");
+        assert_eq!(matches[0].match_result, MatchResult::ExactMatch);
+        assert_eq!(matches[1].match_result, MatchResult::HighConfidence);
+    }
+
+    fn fingerprint_of(files: &[TorrentFile]) -> ContentFingerprint {
+        ContentFingerprint::from_files_with_options(files, &FingerprintOptions::default())
+    }
+
+    fn entry(hash: &str, site: &str, name: Option<&str>, fingerprint: ContentFingerprint) -> FingerprintEntry {
+        FingerprintEntry {
+            fingerprint,
+            info_hash: hash.to_string(),
+            site_id: site.to_string(),
+            torrent_id: None,
+            name: name.map(str::to_string),
+            save_path: None,
+        }
+    }
+
+    fn file(name: &str, size: u64) -> TorrentFile {
+        TorrentFile {
+            name: name.to_string(),
+            size,
+            progress: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_classify_media() {
+        assert_eq!(
+            classify_media(&[file("Movie/movie.MKV", 4_700_000_000), file("Movie/movie.nfo", 1000)]),
+            Some(MediaType::Video)
+        );
+        assert_eq!(
+            classify_media(&[
+                file("Album/01.flac", 40_000_000),
+                file("Album/02.flac", 45_000_000),
+                file("Album/cover.jpg", 2_000_000),
+            ]),
+            Some(MediaType::Audio)
+        );
+        assert_eq!(classify_media(&[file("Game/setup.exe", 4_700_000_000)]), Some(MediaType::Other));
+        assert_eq!(classify_media(&[]), None);
+    }
+
+    #[test]
+    fn test_require_same_media_type() {
+        let movie = fingerprint_of(&[file("movie.mkv", 4_700_000_000)]);
+        let mut album = fingerprint_of(&[file("album.flac", 4_700_000_000)]);
+        // Same structure, only the media type differs
+        album.files_hash = None;
+
+        let entry = entry("album", "hdsky", None, album);
+
+        let mut matcher = FingerprintMatcher::new();
+        matcher.add(entry.clone());
+        assert_eq!(matcher.find_matches_named("movie", None, &movie).len(), 1);
+
+        let mut strict = FingerprintMatcher::new().with_require_same_media_type(true);
+        strict.add(entry);
+        assert!(strict.find_matches_named("movie", None, &movie).is_empty());
+    }
+
+    #[test]
+    fn test_oversized_bucket_requires_identical_files() {
+        let files = [file("Movie/movie.mkv", 900), file("Movie/extra.mkv", 100)];
+        let query = fingerprint_of(&files);
+
+        let mut matcher = FingerprintMatcher::new().with_max_candidates_per_bucket(Some(1));
+        matcher.add(entry("same", "hdsky", None, fingerprint_of(&files)));
+        // Would be a fuzzy match on size and largest file
+        matcher.add(entry("fuzzy", "hdsky", None, ContentFingerprint::from_size(1000, 2, 900)));
+
+        assert_eq!(matcher.oversized_buckets(), vec![(1000, 2)]);
+        let matches = matcher.find_matches_named("x", None, &query);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].entry.info_hash, "same");
+
+        let relaxed = FingerprintMatcher::new().with_max_candidates_per_bucket(Some(2));
+        assert!(relaxed.oversized_buckets().is_empty());
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let files = [file("Movie/movie.mkv", 900), file("Movie/extra.mkv", 100)];
+
+        let mut matcher = FingerprintMatcher::new();
+        matcher.add(entry("a", "hdsky", None, fingerprint_of(&files)));
+        matcher.add(entry("b", "hdsky", None, fingerprint_of(&files)));
+        // The same content on other sites is cross-seeded, not wasted
+        matcher.add(entry("a", "ourbits", None, fingerprint_of(&files)));
+        matcher.add(entry("g", "pterclub", None, fingerprint_of(&files)));
+        matcher.add(entry("c", "ourbits", None, ContentFingerprint::from_size(5000, 1, 5000)));
+        matcher.add(entry("d", "ourbits", None, ContentFingerprint::from_size(5000, 1, 5000)));
+        matcher.add(entry("e", "ourbits", None, ContentFingerprint::from_size(5000, 2, 4000)));
+
+        let groups = matcher.find_duplicates(None);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].match_result, MatchResult::HighConfidence);
+        assert_eq!(groups[0].wasted_size(), 5000);
+        assert_eq!(groups[1].match_result, MatchResult::ExactMatch);
+        assert_eq!(groups[1].copies, 2);
+        assert_eq!(groups[1].entries.len(), 2);
+        assert!(groups.iter().all(|g| g.entries.iter().all(|e| e.site_id == g.entries[0].site_id)));
+
+        let hdsky = matcher.find_duplicates(Some("hdsky"));
+        assert_eq!(hdsky.len(), 1);
+        assert_eq!(hdsky[0].entries.len(), 2);
+        assert!(matcher.find_duplicates(Some("other")).is_empty());
+    }
+
+    #[test]
+    fn test_find_packs() {
+        let list = |files: &[(&str, u64)]| -> Vec<(String, u64)> {
+            files.iter().map(|&(name, size)| (name.to_string(), size)).collect()
+        };
+        let episode = list(&[("Show.S01E02.mkv", 700)]);
+        let season = list(&[("Show.S01/Show.S01E01.mkv", 600), ("Show.S01/show.s01e02.mkv", 700)]);
+        let other_cut = list(&[("Show.S01E01.mkv", 600), ("Show.S01E02.mkv", 701)]);
+
+        let mut matcher = FingerprintMatcher::new().with_subset_matching(true);
+        matcher.add_with_files(entry("season", "hdsky", None, ContentFingerprint::from_size(0, 0, 0)), &season);
+        matcher.add_with_files(entry("other", "hdsky", None, ContentFingerprint::from_size(0, 0, 0)), &other_cut);
+        matcher.add_with_files(entry("episode", "hdsky", None, ContentFingerprint::from_size(0, 0, 0)), &episode);
+
+        let packs = matcher.find_packs("episode", &episode);
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].entry.info_hash, "season");
+        assert_eq!((packs[0].matched_files, packs[0].pack_file_count), (1, 2));
+
+        // A torrent is not its own pack, nor a pack of an identical file set
+        assert!(matcher.find_packs("season", &season).is_empty());
+        assert_eq!(matcher.entries_with_files().count(), 3);
+
+        let mut disabled = FingerprintMatcher::new();
+        disabled.add_with_files(entry("season", "hdsky", None, ContentFingerprint::from_size(0, 0, 0)), &season);
+        assert!(disabled.find_packs("episode", &episode).is_empty());
+    }
+
+    #[test]
+    fn test_zero_size_only_matches_by_hash() {
+        let empty = ContentFingerprint::from_size(0, 0, 0);
+        let mut matcher = FingerprintMatcher::new();
+        matcher.add(FingerprintEntry {
+            fingerprint: empty.clone(),
+            info_hash: "a".to_string(),
+            site_id: "hdsky".to_string(),
+            torrent_id: None,
+            name: None,
+            save_path: None,
+        });
+
+        assert!(matcher.find_matches_named("b", None, &empty).is_empty());
+        assert_eq!(matcher.find_matches_named("a", None, &empty).len(), 1);
+    }
 }
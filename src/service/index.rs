@@ -4,129 +4,437 @@
 //! and builds a local index with content fingerprints for cross-site matching.
 
 use anyhow::{Context, Result};
-use serde::Serialize;
-use std::sync::Arc;
-use tracing::{info, warn};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
 
-use crate::client::{BitTorrentClient, TorrentInfo};
-use crate::db::Database;
-use crate::service::fingerprint::{ContentFingerprint, FingerprintEntry, FingerprintMatcher};
-use crate::site::TrackerIdentifier;
+use crate::client::{BitTorrentClient, TorrentFile, TorrentFilter, TorrentInfo};
+use crate::db::Store;
+use crate::service::events::{Event, EventBus};
+use crate::service::rate_limit::RateLimiter;
+use crate::service::fingerprint::{
+    ContentFingerprint, FingerprintEntry, FingerprintMatcher, FingerprintOptions, MediaType,
+    ScoringWeights,
+};
+use crate::site::{SiteIdentification, TrackerIdentifier};
+use crate::utils::torrent::{read_contents, TorrentContents};
 
 /// Index service for managing the torrent index
 pub struct IndexService {
-    db: Database,
-    tracker_identifier: Arc<TrackerIdentifier>,
+    store: Arc<dyn Store>,
+    fingerprint_options: FingerprintOptions,
+    require_same_media_type: bool,
+    scoring: Option<ScoringWeights>,
+    max_candidates_per_bucket: Option<usize>,
+    /// Keep file lists and find packs containing indexed torrents
+    subset_matching: bool,
+    /// Bumped on every change to the index, so cached matches can be invalidated
+    matcher_version: AtomicU64,
+    /// Last built matcher and the version it was built from
+    matcher_cache: RwLock<Option<(u64, Arc<FingerprintMatcher>)>>,
+    /// Global cap on requests to clients, shared with the reseed service
+    rate_limiter: Option<Arc<RateLimiter>>,
+    events: EventBus,
 }
 
+/// Number of index rows loaded per batch when building the matcher
+const MATCHER_BATCH_SIZE: usize = 5000;
+
+/// How long to wait for a torrent's file list before fingerprinting it on
+/// size alone
+const FILE_LIST_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl IndexService {
-    pub fn new(db: Database) -> Self {
+    pub fn new(store: Arc<dyn Store>) -> Self {
         Self {
-            db,
-            tracker_identifier: Arc::new(TrackerIdentifier::new()),
+            store,
+            fingerprint_options: FingerprintOptions::default(),
+            require_same_media_type: false,
+            scoring: None,
+            max_candidates_per_bucket: None,
+            subset_matching: false,
+            matcher_version: AtomicU64::new(0),
+            matcher_cache: RwLock::new(None),
+            rate_limiter: None,
+            events: EventBus::new(),
+        }
+    }
+
+    pub fn with_require_same_media_type(mut self, require: bool) -> Self {
+        self.require_same_media_type = require;
+        self
+    }
+
+    /// Score matches with these weights instead of the fixed match levels
+    pub fn with_scoring(mut self, scoring: Option<ScoringWeights>) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Only match identical file lists in size buckets larger than this
+    pub fn with_max_candidates_per_bucket(mut self, max: Option<usize>) -> Self {
+        self.max_candidates_per_bucket = max;
+        self
+    }
+
+    /// Store file lists on import and load them into the matcher for
+    /// `pack_suggestions`
+    pub fn with_subset_matching(mut self, enabled: bool) -> Self {
+        self.subset_matching = enabled;
+        self
+    }
+
+    pub fn subset_matching(&self) -> bool {
+        self.subset_matching
+    }
+
+    /// Publish index changes to this event bus
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Wait on this limiter before each request to a client
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<Arc<RateLimiter>>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Wait for the global rate limit, when one is set
+    pub(crate) async fn throttle(&self) {
+        if let Some(ref limiter) = self.rate_limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    /// Current matcher version; changes whenever index entries change
+    pub fn matcher_version(&self) -> u64 {
+        self.matcher_version.load(Ordering::Acquire)
+    }
+
+    /// Record a change to the index made outside this service (e.g. entries
+    /// removed by a cascading site delete)
+    pub fn mark_changed(&self) {
+        let version = self.matcher_version.fetch_add(1, Ordering::AcqRel) + 1;
+        // Free the stale matcher right away rather than on the next build
+        *self.matcher_cache.write().unwrap() = None;
+        self.events.publish(Event::IndexChanged {
+            matcher_version: version,
+        });
+    }
+
+    pub fn with_fingerprint_options(mut self, options: FingerprintOptions) -> Self {
+        self.fingerprint_options = options;
+        self
+    }
+
+    /// Compute the fingerprint for a torrent using the configured options
+    ///
+    /// Falls back to a size-only fingerprint when the file list is unavailable.
+    pub fn compute_fingerprint(&self, torrent: &TorrentInfo, files: &[TorrentFile]) -> ContentFingerprint {
+        if files.is_empty() {
+            let mut fingerprint = ContentFingerprint::size_only(torrent.size);
+            fingerprint.profile_hash = self.fingerprint_options.profile_hash();
+            fingerprint
+        } else {
+            ContentFingerprint::from_files_with_options(files, &self.fingerprint_options)
+        }
+    }
+
+    /// A torrent's file list, fetched from the client unless the torrent
+    /// already carries it
+    ///
+    /// When the client returns no files (e.g. a magnet still fetching
+    /// metadata), the `fallbacks` are asked for the same hash in order.
+    /// Empty when every client fails or takes longer than `FILE_LIST_TIMEOUT`,
+    /// so the torrent is fingerprinted on size alone.
+    pub async fn fetch_files(
+        &self,
+        client: &dyn BitTorrentClient,
+        fallbacks: &[&dyn BitTorrentClient],
+        torrent: &TorrentInfo,
+    ) -> Vec<TorrentFile> {
+        if !torrent.files.is_empty() {
+            return torrent.files.clone();
         }
+
+        for (i, &client) in std::iter::once(&client).chain(fallbacks).enumerate() {
+            self.throttle().await;
+            match tokio::time::timeout(FILE_LIST_TIMEOUT, client.get_torrent_files(&torrent.hash)).await {
+                Ok(Ok(files)) if !files.is_empty() => {
+                    if i > 0 {
+                        debug!("Got files for {} from fallback client {}", torrent.hash, client.client_id());
+                    }
+                    return files;
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Failed to get files for {} from {}: {}", torrent.hash, client.client_id(), e),
+                Err(_) => warn!("Timed out getting files for {} from {}", torrent.hash, client.client_id()),
+            }
+        }
+
+        Vec::new()
+    }
+
+    /// A torrent's tracker URLs, fetched from the client unless the torrent
+    /// already carries them, then from the `fallbacks` in order
+    ///
+    /// Empty when no client returns any.
+    pub async fn fetch_trackers(
+        &self,
+        client: &dyn BitTorrentClient,
+        fallbacks: &[&dyn BitTorrentClient],
+        torrent: &TorrentInfo,
+    ) -> Vec<String> {
+        if !torrent.trackers.is_empty() {
+            return torrent.trackers.clone();
+        }
+
+        for (i, &client) in std::iter::once(&client).chain(fallbacks).enumerate() {
+            self.throttle().await;
+            match client.get_torrent_trackers(&torrent.hash).await {
+                Ok(trackers) if !trackers.is_empty() => {
+                    if i > 0 {
+                        debug!("Got trackers for {} from fallback client {}", torrent.hash, client.client_id());
+                    }
+                    return trackers;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to get trackers for {} from {}: {}", torrent.hash, client.client_id(), e),
+            }
+        }
+
+        Vec::new()
     }
 
     /// Import torrents from a download client into the index
+    ///
+    /// Files and trackers the client cannot return are looked up on the
+    /// `fallbacks` by hash.
     pub async fn import_from_client(
         &self,
         client: &dyn BitTorrentClient,
+        fallbacks: &[&dyn BitTorrentClient],
         client_id: &str,
+        filter: &ImportFilter,
     ) -> Result<ImportResult> {
         info!("Starting import from client: {}", client_id);
 
-        let torrents = client.get_torrents().await
+        self.throttle().await;
+        let torrents = client.get_torrents_filtered(&filter.torrents).await
             .context("Failed to get torrents from client")?;
 
         info!("Found {} torrents in client", torrents.len());
 
         let mut result = ImportResult::default();
+        let identifier = self.tracker_identifier()?;
 
         for torrent in &torrents {
             result.total += 1;
 
+            if !filter.accepts(torrent.added_on) {
+                result.too_old += 1;
+                continue;
+            }
+
             // Get tracker URLs for site identification
-            let trackers = if torrent.trackers.is_empty() {
-                match client.get_torrent_trackers(&torrent.hash).await {
-                    Ok(t) => t,
-                    Err(e) => {
-                        warn!("Failed to get trackers for {}: {}", torrent.hash, e);
-                        Vec::new()
-                    }
-                }
-            } else {
-                torrent.trackers.clone()
-            };
+            let trackers = self.fetch_trackers(client, fallbacks, torrent).await;
 
-            // Identify site from trackers
-            let site_info = match self.tracker_identifier.identify_from_trackers(&trackers) {
-                Some(info) => info,
-                None => {
-                    result.unrecognized += 1;
-                    continue;
-                }
+            let Some(site_info) = self.identify_site(&identifier, &torrent.hash, &trackers, &mut result)? else {
+                continue;
             };
 
             // Get files for fingerprint calculation
-            let files = if torrent.files.is_empty() {
-                match client.get_torrent_files(&torrent.hash).await {
-                    Ok(f) => f,
-                    Err(e) => {
-                        warn!("Failed to get files for {}: {}", torrent.hash, e);
-                        Vec::new()
-                    }
-                }
-            } else {
-                torrent.files.clone()
-            };
+            let files = self.fetch_files(client, fallbacks, torrent).await;
 
-            // Calculate fingerprint
-            let fingerprint = if files.is_empty() {
-                // Fallback: use torrent size info
-                ContentFingerprint::from_size(torrent.size, 1, torrent.size)
+            self.index_torrent(torrent, &site_info, &files, Some(client_id), &mut result)?;
+        }
+
+        info!(
+            "Import complete: {} total, {} imported, {} updated, {} skipped, {} unrecognized, {} trackerless, {} too old, {} empty",
+            result.total, result.imported, result.updated, result.skipped, result.unrecognized, result.trackerless,
+            result.too_old, result.empty
+        );
+        info!("Import by site: {}", result.site_summary());
+
+        Ok(result)
+    }
+
+    /// Import torrents from a saved qBittorrent torrent list, without a live
+    /// client
+    ///
+    /// Torrents without an embedded file list get size-only fingerprints.
+    /// `client_id` is recorded as their source client when given.
+    pub fn import_torrent_list(&self, torrents: &[TorrentInfo], client_id: Option<&str>) -> Result<ImportResult> {
+        let mut result = ImportResult::default();
+        let identifier = self.tracker_identifier()?;
+
+        for torrent in torrents {
+            result.total += 1;
+
+            let trackers = if torrent.trackers.is_empty() {
+                torrent.tracker.iter().filter(|t| !t.is_empty()).cloned().collect()
             } else {
-                ContentFingerprint::from_files(&files)
+                torrent.trackers.clone()
             };
 
-            // Check if already exists
-            if self.exists(&torrent.hash, &site_info.site_id)? {
-                result.skipped += 1;
+            let Some(site_info) = self.identify_site(&identifier, &torrent.hash, &trackers, &mut result)? else {
                 continue;
-            }
-
-            // Insert into database
-            self.insert_entry(
-                &torrent.hash,
-                &site_info.site_id,
-                site_info.torrent_id.as_deref(),
-                &fingerprint,
-                Some(&torrent.name),
-                Some(&torrent.save_path),
-                Some(client_id),
-            )?;
+            };
 
-            result.imported += 1;
+            self.index_torrent(torrent, &site_info, &torrent.files, client_id, &mut result)?;
         }
 
         info!(
-            "Import complete: {} total, {} imported, {} skipped, {} unrecognized",
-            result.total, result.imported, result.skipped, result.unrecognized
+            "Torrent list import complete: {} total, {} imported, {} updated, {} skipped, {} unrecognized, {} trackerless",
+            result.total, result.imported, result.updated, result.skipped, result.unrecognized, result.trackerless
         );
 
         Ok(result)
     }
 
+    /// Fingerprint an identified torrent and add it to the index, or refresh
+    /// its fingerprint when it is already there, counting it in `result`
+    fn index_torrent(
+        &self,
+        torrent: &TorrentInfo,
+        site_info: &SiteIdentification,
+        files: &[TorrentFile],
+        client_id: Option<&str>,
+        result: &mut ImportResult,
+    ) -> Result<()> {
+        let fingerprint = self.compute_fingerprint(torrent, files);
+
+        // Still fetching metadata; an empty fingerprint would match every
+        // other empty torrent
+        if fingerprint.total_size == 0 {
+            result.empty += 1;
+            return Ok(());
+        }
+
+        // Already indexed: keep the fingerprint current, since a
+        // repaired or refetched torrent's files can change
+        if self.exists(&torrent.hash, &site_info.site_id)? {
+            if self.refresh_fingerprint(&torrent.hash, &site_info.site_id, &fingerprint)? {
+                result.updated += 1;
+                self.store_files(&torrent.hash, &site_info.site_id, files, false)?;
+            } else {
+                result.skipped += 1;
+                // Entries indexed before subset matching was turned on
+                self.store_files(&torrent.hash, &site_info.site_id, files, true)?;
+            }
+            return Ok(());
+        }
+
+        self.insert_entry(
+            &torrent.hash,
+            &site_info.site_id,
+            site_info.torrent_id.as_deref(),
+            &fingerprint,
+            Some(&torrent.name),
+            Some(&torrent.save_path),
+            client_id,
+        )?;
+        self.store_files(&torrent.hash, &site_info.site_id, files, false)?;
+
+        result.imported += 1;
+        if fingerprint.size_only {
+            result.size_only += 1;
+        }
+        Ok(())
+    }
+
+    /// Tracker identification with the domains stored for configured sites,
+    /// e.g. those added by syncing builtin sites
+    pub fn tracker_identifier(&self) -> Result<TrackerIdentifier> {
+        Ok(TrackerIdentifier::new().with_domains(self.store.list_tracker_domains()?))
+    }
+
+    /// Identify a torrent's site from its trackers, counting it in `result`
+    ///
+    /// DHT/PeX-only torrents have no trackers, so those fall back to what the
+    /// index already knows about the hash. `None` when neither identifies it.
+    fn identify_site(
+        &self,
+        identifier: &TrackerIdentifier,
+        info_hash: &str,
+        trackers: &[String],
+        result: &mut ImportResult,
+    ) -> Result<Option<SiteIdentification>> {
+        let site_info = if trackers.is_empty() {
+            let found = self.find_indexed_site(info_hash)?;
+            if found.is_none() {
+                result.trackerless += 1;
+                *result.by_site.entry(TRACKERLESS_SITE.to_string()).or_default() += 1;
+            }
+            found
+        } else {
+            let found = identifier.identify_from_trackers(trackers);
+            if found.is_none() {
+                result.unrecognized += 1;
+                *result.by_site.entry(UNRECOGNIZED_SITE.to_string()).or_default() += 1;
+            }
+            found
+        };
+
+        if let Some(ref info) = site_info {
+            *result.by_site.entry(info.site_id.clone()).or_default() += 1;
+        }
+        Ok(site_info)
+    }
+
+    /// Look up the site an info hash is already indexed under
+    fn find_indexed_site(&self, info_hash: &str) -> Result<Option<SiteIdentification>> {
+        self.store.find_indexed_site(info_hash)
+    }
+
     /// Check if an entry already exists
     fn exists(&self, info_hash: &str, site_id: &str) -> Result<bool> {
-        let conn = self.db.conn();
-        let mut stmt = conn.prepare(
-            "SELECT 1 FROM torrent_index WHERE info_hash = ?1 AND site_id = ?2 LIMIT 1"
-        )?;
+        self.store.index_entry_exists(info_hash, site_id)
+    }
 
-        Ok(stmt.exists([info_hash, site_id])?)
+    /// Replace an indexed entry's fingerprint when it differs from `fingerprint`
+    ///
+    /// A size-only fingerprint never replaces a stored one: a missing file
+    /// list says nothing about whether the files changed.
+    fn refresh_fingerprint(&self, info_hash: &str, site_id: &str, fingerprint: &ContentFingerprint) -> Result<bool> {
+        if fingerprint.size_only {
+            return Ok(false);
+        }
+        if !self.store.update_index_fingerprint(info_hash, site_id, fingerprint)? {
+            return Ok(false);
+        }
+
+        info!("Fingerprint of {} on {} changed; updated the index entry", info_hash, site_id);
+        self.mark_changed();
+        Ok(true)
+    }
+
+    /// Store the fingerprinted files of an entry, with subset matching on
+    fn store_files(&self, info_hash: &str, site_id: &str, files: &[TorrentFile], only_if_missing: bool) -> Result<()> {
+        if !self.subset_matching || files.is_empty() {
+            return Ok(());
+        }
+
+        let files: Vec<(String, u64)> = files
+            .iter()
+            .filter(|f| self.fingerprint_options.includes(&f.name))
+            .map(|f| (f.name.clone(), f.size))
+            .collect();
+        if self.store.store_index_files(info_hash, site_id, &files, only_if_missing)? {
+            self.mark_changed();
+        }
+        Ok(())
     }
 
     /// Insert a new index entry
+    #[allow(clippy::too_many_arguments)]
     fn insert_entry(
         &self,
         info_hash: &str,
@@ -137,173 +445,958 @@ impl IndexService {
         save_path: Option<&str>,
         source_client: Option<&str>,
     ) -> Result<()> {
-        let conn = self.db.conn();
-
-        // First, insert or get fingerprint ID
-        let fingerprint_id = self.get_or_create_fingerprint(&conn, fingerprint)?;
-
-        // Insert torrent index entry
-        conn.execute(
-            "INSERT INTO torrent_index (info_hash, site_id, torrent_id, fingerprint_id, name, size, save_path, source_client)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            rusqlite::params![
-                info_hash,
-                site_id,
-                torrent_id,
-                fingerprint_id,
-                name,
-                fingerprint.total_size as i64,
-                save_path,
-                source_client,
-            ],
-        )?;
+        self.store.insert_index_entry(&IndexExportEntry {
+            info_hash: info_hash.to_string(),
+            site_id: site_id.to_string(),
+            torrent_id: torrent_id.map(str::to_string),
+            name: name.map(str::to_string),
+            save_path: save_path.map(str::to_string),
+            source_client: source_client.map(str::to_string),
+            total_size: fingerprint.total_size,
+            file_count: fingerprint.file_count,
+            largest_file_size: fingerprint.largest_file_size,
+            files_hash: fingerprint.files_hash.clone(),
+            profile_hash: fingerprint.profile_hash.clone(),
+            media_type: fingerprint.media_type,
+            v2_root: fingerprint.v2_root.clone(),
+            size_only: fingerprint.size_only,
+            padding_excluded: fingerprint.padding_excluded,
+        })?;
 
+        self.mark_changed();
         Ok(())
     }
 
-    fn get_or_create_fingerprint(
-        &self,
-        conn: &rusqlite::Connection,
-        fingerprint: &ContentFingerprint,
-    ) -> Result<i64> {
-        // Try to find existing fingerprint
-        let mut stmt = conn.prepare(
-            "SELECT id FROM content_fingerprints
-             WHERE total_size = ?1 AND file_count = ?2 AND largest_file_size = ?3
-             LIMIT 1"
-        )?;
+    /// Get a fingerprint matcher for the current index
+    ///
+    /// The matcher is built lazily and cached until the index changes, so
+    /// back-to-back previews share one build.
+    pub fn build_matcher(&self) -> Result<Arc<FingerprintMatcher>> {
+        let version = self.matcher_version();
 
-        let result: Option<i64> = stmt
-            .query_row(
-                rusqlite::params![
-                    fingerprint.total_size as i64,
-                    fingerprint.file_count as i64,
-                    fingerprint.largest_file_size as i64,
-                ],
-                |row| row.get(0),
-            )
-            .ok();
-
-        if let Some(id) = result {
-            return Ok(id);
-        }
-
-        // Create new fingerprint
-        conn.execute(
-            "INSERT INTO content_fingerprints (total_size, file_count, largest_file_size, files_hash)
-             VALUES (?1, ?2, ?3, ?4)",
-            rusqlite::params![
-                fingerprint.total_size as i64,
-                fingerprint.file_count as i64,
-                fingerprint.largest_file_size as i64,
-                fingerprint.files_hash,
-            ],
-        )?;
+        if let Some((cached_version, matcher)) = self.matcher_cache.read().unwrap().as_ref() {
+            if *cached_version == version {
+                return Ok(matcher.clone());
+            }
+        }
+
+        let matcher = Arc::new(self.load_matcher()?);
+        // Don't cache a matcher the index has already moved past
+        if self.matcher_version() == version {
+            *self.matcher_cache.write().unwrap() = Some((version, matcher.clone()));
+        }
 
-        Ok(conn.last_insert_rowid())
+        Ok(matcher)
     }
 
-    /// Build a fingerprint matcher from the index
-    pub fn build_matcher(&self) -> Result<FingerprintMatcher> {
-        let conn = self.db.conn();
-        let mut matcher = FingerprintMatcher::new();
+    /// Load every index entry into a new matcher
+    ///
+    /// Rows are read in batches so the database lock is released between
+    /// batches and other queries are not blocked for the whole load.
+    fn load_matcher(&self) -> Result<FingerprintMatcher> {
+        let mut matcher = FingerprintMatcher::new()
+            .with_require_same_media_type(self.require_same_media_type)
+            .with_scoring(self.scoring)
+            .with_max_candidates_per_bucket(self.max_candidates_per_bucket)
+            .with_subset_matching(self.subset_matching);
+        let mut file_lists = if self.subset_matching {
+            self.store.list_index_files()?
+        } else {
+            HashMap::new()
+        };
+        let mut after_id = 0i64;
 
-        let mut stmt = conn.prepare(
-            "SELECT ti.info_hash, ti.site_id, ti.torrent_id, ti.name, ti.save_path,
-                    cf.total_size, cf.file_count, cf.largest_file_size, cf.files_hash
-             FROM torrent_index ti
-             JOIN content_fingerprints cf ON ti.fingerprint_id = cf.id"
-        )?;
+        loop {
+            let batch = self.store.list_index_entries(after_id, MATCHER_BATCH_SIZE)?;
+            let rows = batch.len();
 
-        let entries = stmt.query_map([], |row| {
-            let fingerprint = ContentFingerprint {
-                total_size: row.get::<_, i64>(5)? as u64,
-                file_count: row.get::<_, i64>(6)? as usize,
-                largest_file_size: row.get::<_, i64>(7)? as u64,
-                files_hash: row.get(8)?,
-            };
+            for (id, entry) in batch {
+                after_id = id;
+                let files = file_lists.remove(&id).unwrap_or_default();
+                matcher.add_with_files(
+                    FingerprintEntry {
+                        fingerprint: entry.fingerprint(),
+                        info_hash: entry.info_hash,
+                        site_id: entry.site_id,
+                        torrent_id: entry.torrent_id,
+                        name: entry.name,
+                        save_path: entry.save_path,
+                    },
+                    &files,
+                );
+            }
 
-            Ok(FingerprintEntry {
-                fingerprint,
-                info_hash: row.get(0)?,
-                site_id: row.get(1)?,
-                torrent_id: row.get(2)?,
-                name: row.get(3)?,
-                save_path: row.get(4)?,
-            })
-        })?;
+            if rows < MATCHER_BATCH_SIZE {
+                break;
+            }
+        }
 
-        for entry in entries {
-            matcher.add(entry?);
+        for (size, count) in matcher.oversized_buckets() {
+            warn!(
+                "{} index entries share total size {}; only identical file lists match at this size",
+                count, size
+            );
         }
 
         Ok(matcher)
     }
 
-    /// Get index statistics
-    pub fn get_stats(&self) -> Result<IndexStats> {
-        let conn = self.db.conn();
+    /// Fetch a batch of index entries for export
+    ///
+    /// Uses keyset pagination on the row id so large indexes can be streamed
+    /// without holding the database lock for the whole export. Returns the
+    /// entries together with the id to pass as `after_id` for the next batch.
+    /// With `scrub`, entries are stripped of local details (see `IndexExportEntry::scrub`).
+    pub fn export_batch(&self, after_id: i64, limit: usize, scrub: bool) -> Result<(Vec<IndexExportEntry>, i64)> {
+        let mut last_id = after_id;
+        let entries = self
+            .store
+            .list_index_entries(after_id, limit)?
+            .into_iter()
+            .map(|(id, mut entry)| {
+                last_id = id;
+                if scrub {
+                    entry.scrub();
+                }
+                entry
+            })
+            .collect();
 
-        let total_entries: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM torrent_index",
-            [],
-            |row| row.get(0),
-        )?;
+        Ok((entries, last_id))
+    }
 
-        let mut stmt = conn.prepare(
-            "SELECT site_id, COUNT(*) as count FROM torrent_index GROUP BY site_id ORDER BY count DESC"
-        )?;
+    /// Index the `.torrent` files in a directory (not its subdirectories)
+    ///
+    /// Each file's site is identified from its announce URLs, as for client
+    /// imports. Entries have no save path or source client. Files that cannot
+    /// be read or parsed are counted as `invalid`.
+    pub async fn import_from_dir(&self, dir: &Path) -> Result<ImportResult> {
+        info!("Starting import from directory: {}", dir.display());
+
+        let mut paths = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(dir)
+            .await
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            let is_torrent = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("torrent"));
+            if is_torrent && entry.file_type().await?.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut result = ImportResult::default();
+        let identifier = self.tracker_identifier()?;
+
+        for path in &paths {
+            result.total += 1;
+
+            let read = tokio::fs::read(path).await.map_err(anyhow::Error::from);
+            let contents = match read.and_then(|bytes| read_contents(&bytes)) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    warn!("Skipping {}: {:#}", path.display(), e);
+                    result.invalid += 1;
+                    continue;
+                }
+            };
+
+            let found = self.identify_site(&identifier, &contents.info_hash, &contents.trackers, &mut result)?;
+            let Some(site_info) = found else {
+                continue;
+            };
+
+            let mut fingerprint = ContentFingerprint::from_files_with_options(&contents.files, &self.fingerprint_options);
+            fingerprint.v2_root = contents.v2_root.clone();
+            if fingerprint.total_size == 0 {
+                result.empty += 1;
+                continue;
+            }
+
+            if self.exists(&contents.info_hash, &site_info.site_id)? {
+                result.skipped += 1;
+                continue;
+            }
+
+            self.insert_entry(
+                &contents.info_hash,
+                &site_info.site_id,
+                site_info.torrent_id.as_deref(),
+                &fingerprint,
+                Some(&contents.name),
+                None,
+                None,
+            )?;
+
+            result.imported += 1;
+        }
+
+        info!(
+            "Directory import complete: {} total, {} imported, {} skipped, {} unrecognized, {} trackerless, {} invalid",
+            result.total, result.imported, result.skipped, result.unrecognized, result.trackerless, result.invalid
+        );
+
+        Ok(result)
+    }
+
+    /// Import previously exported index entries
+    ///
+    /// Fingerprints are reused when an identical one exists, and entries are
+    /// upserted on (info_hash, site_id). Entries for sites that are not
+    /// configured locally are counted as unrecognized.
+    pub fn import_entries(&self, entries: &[IndexExportEntry]) -> Result<ImportResult> {
+        let stored = self.store.upsert_index_entries(entries)?;
+        let mut result = ImportResult::default();
+
+        for (entry, stored) in entries.iter().zip(stored) {
+            result.total += 1;
+
+            if stored {
+                result.imported += 1;
+                *result.by_site.entry(entry.site_id.clone()).or_default() += 1;
+            } else {
+                result.unrecognized += 1;
+                *result.by_site.entry(UNRECOGNIZED_SITE.to_string()).or_default() += 1;
+            }
+        }
+
+        self.mark_changed();
+
+        info!(
+            "Index file import complete: {} total, {} imported, {} unrecognized",
+            result.total, result.imported, result.unrecognized
+        );
+
+        Ok(result)
+    }
+
+    /// Fingerprint a parsed torrent file with the index's options
+    pub fn fingerprint_contents(&self, contents: &TorrentContents) -> ContentFingerprint {
+        let mut fingerprint = ContentFingerprint::from_files_with_options(&contents.files, &self.fingerprint_options);
+        fingerprint.v2_root = contents.v2_root.clone();
+        fingerprint
+    }
+
+    /// The indexed fingerprint of a torrent on a site
+    pub fn indexed_fingerprint(&self, info_hash: &str, site_id: &str) -> Result<Option<ContentFingerprint>> {
+        let matcher = self.build_matcher()?;
+        Ok(matcher.get(info_hash, site_id).map(|entry| entry.fingerprint.clone()))
+    }
+
+    /// Fingerprint a parsed torrent file and match it against the index
+    ///
+    /// Nothing is stored; this shows what a reseed would see for the torrent.
+    pub fn analyze(&self, contents: &TorrentContents) -> Result<FingerprintAnalysis> {
+        let fingerprint = self.fingerprint_contents(contents);
+        let matcher = self.build_matcher()?;
+
+        let matches = matcher
+            .find_matches_named(&contents.info_hash, Some(&contents.name), &fingerprint)
+            .into_iter()
+            .map(|matched| AnalyzedMatch {
+                reason: matched.reason(&contents.info_hash, &fingerprint),
+                confidence: matched.confidence(),
+                site_id: matched.entry.site_id,
+                torrent_id: matched.entry.torrent_id,
+                info_hash: matched.entry.info_hash,
+                name: matched.entry.name,
+            })
+            .collect();
 
-        let sites = stmt
-            .query_map([], |row| {
-                Ok(SiteIndexCount {
-                    site_id: row.get(0)?,
-                    count: row.get(1)?,
+        Ok(FingerprintAnalysis {
+            info_hash: contents.info_hash.clone(),
+            name: contents.name.clone(),
+            fingerprint,
+            matches,
+        })
+    }
+
+    /// Groups of index entries with the same content under different info
+    /// hashes on the same site, optionally for one site, largest waste first
+    pub fn find_duplicates(&self, site_id: Option<&str>) -> Result<Vec<DuplicateSet>> {
+        let matcher = self.build_matcher()?;
+        Ok(matcher
+            .find_duplicates(site_id)
+            .into_iter()
+            .map(|group| DuplicateSet {
+                total_size: group.entries.first().map_or(0, |e| e.fingerprint.total_size),
+                wasted_size: group.wasted_size(),
+                confidence: group.match_result.confidence(),
+                copies: group.copies,
+                entries: group
+                    .entries
+                    .into_iter()
+                    .map(|e| DuplicateEntry {
+                        info_hash: e.info_hash,
+                        site_id: e.site_id,
+                        torrent_id: e.torrent_id,
+                        name: e.name,
+                        save_path: e.save_path,
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Indexed torrents contained in larger indexed torrents, e.g. an episode
+    /// in a season pack, optionally for one site's torrents
+    ///
+    /// Informational only: a pack holds more data than the torrent, so it
+    /// cannot be reseeded from it. Needs subset matching, and only covers
+    /// entries imported (or re-imported) since it was turned on.
+    pub fn pack_suggestions(&self, site_id: Option<&str>) -> Result<Vec<PackSuggestion>> {
+        if !self.subset_matching {
+            anyhow::bail!("Subset matching is disabled");
+        }
+
+        let matcher = self.build_matcher()?;
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut suggestions: Vec<PackSuggestion> = matcher
+            .entries_with_files()
+            .filter(|(entry, _)| site_id.is_none_or(|site| entry.site_id == site))
+            .filter(|(entry, _)| seen.insert(entry.info_hash.to_lowercase()))
+            .filter_map(|(entry, files)| {
+                let packs = matcher.find_packs(&entry.info_hash, files);
+                (!packs.is_empty()).then(|| PackSuggestion {
+                    info_hash: entry.info_hash.clone(),
+                    site_id: entry.site_id.clone(),
+                    name: entry.name.clone(),
+                    total_size: entry.fingerprint.total_size,
+                    packs: packs
+                        .into_iter()
+                        .map(|p| PackEntry {
+                            info_hash: p.entry.info_hash,
+                            site_id: p.entry.site_id,
+                            torrent_id: p.entry.torrent_id,
+                            name: p.entry.name,
+                            total_size: p.entry.fingerprint.total_size,
+                            file_count: p.pack_file_count,
+                            matched_files: p.matched_files,
+                        })
+                        .collect(),
                 })
-            })?
-            .collect::<std::result::Result<Vec<_>, _>>()?;
+            })
+            .collect();
+
+        suggestions.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.info_hash.cmp(&b.info_hash)));
+        Ok(suggestions)
+    }
 
+    /// Get index statistics
+    pub fn get_stats(&self) -> Result<IndexStats> {
         Ok(IndexStats {
-            total_entries,
-            sites,
+            total_entries: self.store.count_index_entries()?,
+            sites: self.store.count_index_by_site()?,
+            matcher_version: self.matcher_version(),
         })
     }
 
     /// Clear all index entries
     pub fn clear(&self) -> Result<()> {
-        let conn = self.db.conn();
-        conn.execute("DELETE FROM torrent_index", [])?;
-        conn.execute("DELETE FROM content_fingerprints", [])?;
+        self.store.clear_index()?;
+        self.mark_changed();
         Ok(())
     }
 
     /// Clear index entries for a specific site
-    pub fn clear_by_site(&self, site_id: &str) -> Result<()> {
-        let conn = self.db.conn();
-        conn.execute("DELETE FROM torrent_index WHERE site_id = ?1", [site_id])?;
-        Ok(())
+    ///
+    /// The entries are only marked deleted: matching and stats ignore them,
+    /// and `restore_site` brings them back until `purge_deleted` removes
+    /// them. Returns the number of entries cleared.
+    pub fn clear_by_site(&self, site_id: &str) -> Result<usize> {
+        let cleared = self.store.clear_index_site(site_id)?;
+        if cleared > 0 {
+            info!("Cleared {} index entries of site {}", cleared, site_id);
+            self.mark_changed();
+        }
+        Ok(cleared)
+    }
+
+    /// Restore a site's cleared entries, returning how many
+    pub fn restore_site(&self, site_id: &str) -> Result<usize> {
+        let restored = self.store.restore_index_site(site_id)?;
+        if restored > 0 {
+            info!("Restored {} index entries of site {}", restored, site_id);
+            self.mark_changed();
+        }
+        Ok(restored)
+    }
+
+    /// Delete entries cleared before `before`, then orphaned fingerprints
+    ///
+    /// Returns the number of entries removed.
+    pub fn purge_deleted(&self, before: DateTime<Utc>) -> Result<usize> {
+        let removed = self.store.purge_deleted_index_entries(before)?;
+        if removed > 0 {
+            info!("Purged {} index entries cleared before {}", removed, before);
+            self.gc_fingerprints()?;
+        }
+        Ok(removed)
+    }
+
+    /// Number of entries `prune` would delete for this cutoff
+    pub fn count_prunable(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.store.count_index_entries_before(before)
+    }
+
+    /// Delete entries indexed before `before`, then orphaned fingerprints
+    ///
+    /// Returns the number of entries removed.
+    pub fn prune(&self, before: DateTime<Utc>) -> Result<usize> {
+        let removed = self.store.delete_index_entries_before(before)?;
+        if removed > 0 {
+            info!("Pruned {} index entries indexed before {}", removed, before);
+            self.mark_changed();
+            self.gc_fingerprints()?;
+        }
+        Ok(removed)
+    }
+
+    /// Info hashes imported from a client that the client no longer has
+    pub async fn missing_from_client(&self, client: &dyn BitTorrentClient, client_id: &str) -> Result<Vec<String>> {
+        self.throttle().await;
+        let present: HashSet<String> = client
+            .get_torrents()
+            .await
+            .context("Failed to get torrents from client")?
+            .into_iter()
+            .map(|t| t.hash.to_lowercase())
+            .collect();
+
+        let missing = self
+            .store
+            .list_client_index_hashes(client_id)?
+            .into_iter()
+            .filter(|hash| !present.contains(&hash.to_lowercase()))
+            .collect();
+        Ok(missing)
+    }
+
+    /// Delete a client's entries for torrents it no longer has (see
+    /// `missing_from_client`), then orphaned fingerprints
+    pub fn prune_missing(&self, client_id: &str, hashes: &[String]) -> Result<usize> {
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+
+        let removed = self.store.delete_client_index_hashes(client_id, hashes)?;
+        if removed > 0 {
+            info!("Pruned {} index entries no longer in client {}", removed, client_id);
+            self.mark_changed();
+            self.gc_fingerprints()?;
+        }
+        Ok(removed)
+    }
+
+    /// Delete fingerprints no longer referenced by any index entry
+    ///
+    /// Returns the number of fingerprints removed.
+    pub fn gc_fingerprints(&self) -> Result<usize> {
+        let removed = self.store.gc_fingerprints()?;
+
+        if removed > 0 {
+            info!("Removed {} orphaned fingerprints", removed);
+        }
+
+        Ok(removed)
     }
 }
 
+/// Key used in `ImportResult::by_site` for torrents whose site was not identified
+pub const UNRECOGNIZED_SITE: &str = "unrecognized";
+
+/// Key used in `ImportResult::by_site` for torrents without any tracker
+pub const TRACKERLESS_SITE: &str = "trackerless";
+
 /// Result of an import operation
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Serialize, ToSchema)]
 pub struct ImportResult {
     pub total: usize,
     pub imported: usize,
+    /// Already indexed, with a fingerprint that changed since
+    pub updated: usize,
     pub skipped: usize,
+    /// Trackers present, but none belongs to a known site
     pub unrecognized: usize,
+    /// No trackers at all (DHT/PeX only) and not already in the index
+    pub trackerless: usize,
+    /// Number of torrents seen per identified site, plus `unrecognized` and `trackerless`
+    pub by_site: HashMap<String, usize>,
+    /// Left out by the `added_after` filter
+    pub too_old: usize,
+    /// Zero total size, typically still fetching metadata
+    pub empty: usize,
+    /// Imported with a size-only fingerprint because the client did not
+    /// return the file list
+    pub size_only: usize,
+    /// Torrent files that could not be read or parsed (directory imports)
+    pub invalid: usize,
+}
+
+/// Which client torrents an import considers
+#[derive(Debug, Clone, Default)]
+pub struct ImportFilter {
+    /// Only import torrents added to the client after this time
+    pub added_after: Option<DateTime<Utc>>,
+    /// Leave out torrents whose client does not report when they were added
+    pub exclude_undated: bool,
+    /// Category and tag filter, applied by the client where it supports it
+    pub torrents: TorrentFilter,
+}
+
+impl ImportFilter {
+    pub fn accepts(&self, added_on: Option<DateTime<Utc>>) -> bool {
+        match (self.added_after, added_on) {
+            (None, _) => true,
+            (Some(after), Some(added)) => added > after,
+            (Some(_), None) => !self.exclude_undated,
+        }
+    }
+}
+
+impl ImportResult {
+    /// Per-site counts formatted as "site: n", largest first
+    pub fn site_summary(&self) -> String {
+        let mut counts: Vec<_> = self.by_site.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        counts
+            .iter()
+            .map(|(site, count)| format!("{}: {}", site, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A single index entry in portable form, used by index export/import
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct IndexExportEntry {
+    pub info_hash: String,
+    pub site_id: String,
+    pub torrent_id: Option<String>,
+    pub name: Option<String>,
+    pub save_path: Option<String>,
+    pub source_client: Option<String>,
+    pub total_size: u64,
+    pub file_count: usize,
+    pub largest_file_size: u64,
+    pub files_hash: Option<String>,
+    #[serde(default)]
+    pub profile_hash: Option<String>,
+    #[serde(default)]
+    pub media_type: Option<MediaType>,
+    #[serde(default)]
+    pub v2_root: Option<String>,
+    #[serde(default)]
+    pub size_only: bool,
+    #[serde(default)]
+    pub padding_excluded: bool,
+}
+
+impl IndexExportEntry {
+    /// The entry's content fingerprint
+    pub fn fingerprint(&self) -> ContentFingerprint {
+        ContentFingerprint {
+            total_size: self.total_size,
+            file_count: self.file_count,
+            largest_file_size: self.largest_file_size,
+            files_hash: self.files_hash.clone(),
+            profile_hash: self.profile_hash.clone(),
+            media_type: self.media_type,
+            v2_root: self.v2_root.clone(),
+            size_only: self.size_only,
+            padding_excluded: self.padding_excluded,
+        }
+    }
+
+    /// An entry with only a fingerprint, site and info hash, for tests
+    #[cfg(test)]
+    pub fn from_fingerprint(info_hash: &str, site_id: &str, fingerprint: &ContentFingerprint) -> Self {
+        Self {
+            info_hash: info_hash.to_string(),
+            site_id: site_id.to_string(),
+            torrent_id: None,
+            name: None,
+            save_path: None,
+            source_client: None,
+            total_size: fingerprint.total_size,
+            file_count: fingerprint.file_count,
+            largest_file_size: fingerprint.largest_file_size,
+            files_hash: fingerprint.files_hash.clone(),
+            profile_hash: fingerprint.profile_hash.clone(),
+            media_type: fingerprint.media_type,
+            v2_root: fingerprint.v2_root.clone(),
+            size_only: fingerprint.size_only,
+            padding_excluded: fingerprint.padding_excluded,
+        }
+    }
+
+    /// Drop the torrent name, save path and source client, keeping only what
+    /// is needed to match: the fingerprint, site and torrent id
+    pub fn scrub(&mut self) {
+        self.name = None;
+        self.save_path = None;
+        self.source_client = None;
+    }
 }
 
 /// Index statistics
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct IndexStats {
     pub total_entries: i64,
     pub sites: Vec<SiteIndexCount>,
+    /// Changes whenever the index changes
+    pub matcher_version: u64,
+}
+
+/// A torrent's fingerprint and the index entries it matches
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FingerprintAnalysis {
+    pub info_hash: String,
+    pub name: String,
+    pub fingerprint: ContentFingerprint,
+    /// Highest confidence first
+    pub matches: Vec<AnalyzedMatch>,
+}
+
+/// An index entry matched by `IndexService::analyze`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnalyzedMatch {
+    pub site_id: String,
+    pub torrent_id: Option<String>,
+    pub info_hash: String,
+    pub name: Option<String>,
+    pub confidence: f64,
+    pub reason: String,
+}
+
+/// Index entries sharing content, as reported by `IndexService::find_duplicates`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DuplicateSet {
+    pub total_size: u64,
+    /// Bytes taken by all copies but one
+    pub wasted_size: u64,
+    /// 1.0 for identical file lists, lower for a size and structure match
+    pub confidence: f64,
+    /// Number of distinct info hashes
+    pub copies: usize,
+    pub entries: Vec<DuplicateEntry>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DuplicateEntry {
+    pub info_hash: String,
+    pub site_id: String,
+    pub torrent_id: Option<String>,
+    pub name: Option<String>,
+    pub save_path: Option<String>,
+}
+
+/// An indexed torrent and the indexed packs containing it, as reported by
+/// `IndexService::pack_suggestions`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PackSuggestion {
+    pub info_hash: String,
+    pub site_id: String,
+    pub name: Option<String>,
+    pub total_size: u64,
+    /// Smallest first
+    pub packs: Vec<PackEntry>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PackEntry {
+    pub info_hash: String,
+    pub site_id: String,
+    pub torrent_id: Option<String>,
+    pub name: Option<String>,
+    pub total_size: u64,
+    pub file_count: usize,
+    /// Files of the suggested-for torrent found in the pack (all of them)
+    pub matched_files: usize,
 }
 
 /// Count of index entries per site
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SiteIndexCount {
     pub site_id: String,
     pub count: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Database, SqliteStore};
+
+    fn setup() -> IndexService {
+        setup_with_db().1
+    }
+
+    fn setup_with_db() -> (Database, IndexService) {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        for site in ["hdsky", "ourbits"] {
+            db.conn()
+                .execute(
+                    "INSERT INTO sites (id, name, base_url) VALUES (?1, ?1, 'https://example.com')",
+                    [site],
+                )
+                .unwrap();
+        }
+        let service = IndexService::new(Arc::new(SqliteStore::new(db.clone())));
+        (db, service)
+    }
+
+    fn fingerprint_count(db: &Database) -> i64 {
+        db.conn()
+            .query_row("SELECT COUNT(*) FROM content_fingerprints", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_import_filter_added_after() {
+        let after = "2024-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let older = Some("2024-05-01T00:00:00Z".parse().unwrap());
+        let newer = Some("2024-07-01T00:00:00Z".parse().unwrap());
+
+        let filter = ImportFilter::default();
+        assert!(filter.accepts(older));
+        assert!(filter.accepts(None));
+
+        let mut filter = ImportFilter {
+            added_after: Some(after),
+            ..Default::default()
+        };
+        assert!(!filter.accepts(older));
+        assert!(filter.accepts(newer));
+        assert!(filter.accepts(None));
+
+        filter.exclude_undated = true;
+        assert!(!filter.accepts(None));
+    }
+
+    #[test]
+    fn test_purge_removes_orphaned_fingerprints() {
+        let (db, service) = setup_with_db();
+        let shared = ContentFingerprint::from_size(1000, 1, 1000);
+        let only_hdsky = ContentFingerprint::from_size(2000, 1, 2000);
+
+        service.insert_entry("a", "hdsky", None, &shared, None, None, None).unwrap();
+        service.insert_entry("b", "ourbits", None, &shared, None, None, None).unwrap();
+        service.insert_entry("c", "hdsky", None, &only_hdsky, None, None, None).unwrap();
+        assert_eq!(fingerprint_count(&db), 2);
+
+        assert_eq!(service.clear_by_site("hdsky").unwrap(), 2);
+        // Kept for restoring until purged
+        assert_eq!(fingerprint_count(&db), 2);
+
+        service.purge_deleted(Utc::now() + chrono::Duration::seconds(1)).unwrap();
+
+        // The fingerprint still used by ourbits survives
+        assert_eq!(fingerprint_count(&db), 1);
+        assert_eq!(service.gc_fingerprints().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_import_entries_counts_by_site() {
+        let service = setup();
+        let fingerprint = ContentFingerprint::from_size(1000, 1, 1000);
+        let entry = |hash: &str, site: &str| IndexExportEntry::from_fingerprint(hash, site, &fingerprint);
+
+        let result = service
+            .import_entries(&[entry("a", "hdsky"), entry("b", "hdsky"), entry("c", "unknown")])
+            .unwrap();
+
+        assert_eq!(result.by_site.get("hdsky"), Some(&2));
+        assert_eq!(result.by_site.get(UNRECOGNIZED_SITE), Some(&1));
+        assert_eq!(result.site_summary(), "hdsky: 2, unrecognized: 1");
+    }
+
+    #[test]
+    fn test_export_batch_scrub() {
+        let service = setup();
+        let fingerprint = ContentFingerprint::from_size(1000, 1, 1000);
+        service
+            .insert_entry("abcdef", "hdsky", Some("42"), &fingerprint, Some("Movie"), Some("/data"), Some("qb"))
+            .unwrap();
+
+        let (entries, _) = service.export_batch(0, 10, false).unwrap();
+        assert_eq!(entries[0].save_path.as_deref(), Some("/data"));
+
+        let (entries, _) = service.export_batch(0, 10, true).unwrap();
+        let entry = &entries[0];
+        assert!(entry.name.is_none() && entry.save_path.is_none() && entry.source_client.is_none());
+        assert_eq!(entry.torrent_id.as_deref(), Some("42"));
+        assert_eq!(entry.total_size, 1000);
+    }
+
+    #[test]
+    fn test_find_indexed_site() {
+        let service = setup();
+        let fingerprint = ContentFingerprint::from_size(1000, 1, 1000);
+        service
+            .insert_entry("abcdef", "hdsky", Some("42"), &fingerprint, None, None, None)
+            .unwrap();
+
+        let site = service.find_indexed_site("ABCDEF").unwrap().unwrap();
+        assert_eq!(site.site_id, "hdsky");
+        assert_eq!(site.torrent_id.as_deref(), Some("42"));
+        assert!(service.find_indexed_site("123456").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_matcher_cache_invalidated_on_change() {
+        let service = setup();
+        let fingerprint = ContentFingerprint::from_size(1000, 1, 1000);
+        service.insert_entry("a", "hdsky", None, &fingerprint, None, None, None).unwrap();
+
+        let first = service.build_matcher().unwrap();
+        let second = service.build_matcher().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.len(), 1);
+
+        service.insert_entry("b", "ourbits", None, &fingerprint, None, None, None).unwrap();
+        let rebuilt = service.build_matcher().unwrap();
+        assert!(!Arc::ptr_eq(&first, &rebuilt));
+        assert_eq!(rebuilt.len(), 2);
+    }
+
+    #[test]
+    fn test_refresh_fingerprint() {
+        let (db, service) = setup_with_db();
+        service
+            .insert_entry("a", "hdsky", None, &ContentFingerprint::size_only(1000), None, None, None)
+            .unwrap();
+
+        let files = vec![
+            TorrentFile { name: "Movie/movie.mkv".to_string(), size: 900, progress: 0.0 },
+            TorrentFile { name: "Movie/movie.nfo".to_string(), size: 100, progress: 0.0 },
+        ];
+        let fingerprint = ContentFingerprint::from_files_with_options(&files, &FingerprintOptions::default());
+        let version = service.matcher_version();
+        assert!(service.refresh_fingerprint("a", "hdsky", &fingerprint).unwrap());
+        assert!(service.matcher_version() > version);
+        assert!(!service.refresh_fingerprint("a", "hdsky", &fingerprint).unwrap());
+
+        // A file list that went missing again leaves the entry alone
+        assert!(!service.refresh_fingerprint("a", "hdsky", &ContentFingerprint::size_only(1000)).unwrap());
+
+        let (_, stored) = crate::db::repository::list_index_entries(&db.conn(), 0, 10).unwrap().remove(0);
+        assert_eq!(stored.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_import_torrent_list() {
+        let (db, service) = setup_with_db();
+        let json = br#"[
+            {"hash": "aaa", "name": "Movie", "size": 1000, "progress": 1.0, "state": "uploading",
+             "save_path": "/data", "tracker": "https://hdsky.me/announce.php?passkey=x",
+             "files": [{"name": "Movie/movie.mkv", "size": 1000, "progress": 1.0}]},
+            {"hash": "bbb", "name": "Show", "size": 2000, "progress": 1.0, "state": "uploading",
+             "save_path": "/data", "tracker": "https://hdsky.me/announce.php?passkey=x"},
+            {"hash": "ccc", "name": "Other", "size": 3000, "progress": 1.0, "state": "uploading",
+             "save_path": "/data", "tracker": "https://unknown.example/announce"}
+        ]"#;
+        let torrents = crate::client::parse_torrent_export(json).unwrap();
+
+        let result = service.import_torrent_list(&torrents, Some("qb")).unwrap();
+        assert_eq!(result.total, 3);
+        assert_eq!(result.imported, 2);
+        assert_eq!(result.size_only, 1);
+        assert_eq!(result.unrecognized, 1);
+
+        let result = service.import_torrent_list(&torrents, Some("qb")).unwrap();
+        assert_eq!(result.skipped, 2);
+
+        // Domains stored for a site identify its torrents too
+        crate::db::repository::add_tracker_domains(&db.conn(), "ourbits", &["unknown.example".to_string()]).unwrap();
+        let result = service.import_torrent_list(&torrents, Some("qb")).unwrap();
+        assert_eq!(result.imported, 1);
+        assert_eq!(result.by_site.get("ourbits"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_import_from_dir() {
+        let service = setup();
+        let dir = std::env::temp_dir().join(format!("graft-test-torrents-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let torrent = |announce: &str, name: &str| {
+            let mut bytes = format!(
+                "d8:announce{}:{}4:infod6:lengthi1000e4:name{}:{}12:piece lengthi16384e6:pieces20:",
+                announce.len(), announce, name.len(), name
+            )
+            .into_bytes();
+            bytes.extend([0xffu8; 20]);
+            bytes.extend(b"ee");
+            bytes
+        };
+        std::fs::write(dir.join("a.torrent"), torrent("https://hdsky.me/announce.php?passkey=x", "a.mkv")).unwrap();
+        std::fs::write(dir.join("b.TORRENT"), torrent("https://unknown.example/announce", "b.mkv")).unwrap();
+        std::fs::write(dir.join("c.torrent"), b"not a torrent").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+
+        let result = service.import_from_dir(&dir).await.unwrap();
+        assert_eq!(result.total, 3);
+        assert_eq!(result.imported, 1);
+        assert_eq!(result.unrecognized, 1);
+        assert_eq!(result.invalid, 1);
+        assert_eq!(result.by_site["hdsky"], 1);
+
+        // Already indexed on a second run
+        let result = service.import_from_dir(&dir).await.unwrap();
+        assert_eq!(result.skipped, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_analyze() {
+        let service = setup();
+        let files = vec![
+            TorrentFile { name: "Movie/movie.mkv".to_string(), size: 900, progress: 0.0 },
+            TorrentFile { name: "Movie/movie.nfo".to_string(), size: 100, progress: 0.0 },
+        ];
+        let fingerprint = ContentFingerprint::from_files_with_options(&files, &FingerprintOptions::default());
+        service
+            .insert_entry("a", "ourbits", Some("7"), &fingerprint, Some("Movie"), None, None)
+            .unwrap();
+
+        let contents = TorrentContents {
+            info_hash: "b".to_string(),
+            name: "Movie".to_string(),
+            files,
+            v2_root: None,
+            trackers: Vec::new(),
+        };
+        let analysis = service.analyze(&contents).unwrap();
+        assert_eq!(analysis.fingerprint, fingerprint);
+        assert_eq!(analysis.matches.len(), 1);
+        assert_eq!(analysis.matches[0].site_id, "ourbits");
+        assert_eq!(analysis.matches[0].reason, "exact files_hash");
+
+        // v2 roots are stored and matched on their own
+        let v2_fingerprint = ContentFingerprint {
+            v2_root: Some("root".to_string()),
+            ..fingerprint
+        };
+        service
+            .insert_entry("c", "hdsky", Some("8"), &v2_fingerprint, Some("Movie"), None, None)
+            .unwrap();
+        let contents = TorrentContents {
+            v2_root: Some("root".to_string()),
+            ..contents
+        };
+        let analysis = service.analyze(&contents).unwrap();
+        let reasons: HashMap<_, _> = analysis
+            .matches
+            .iter()
+            .map(|m| (m.site_id.as_str(), m.reason.as_str()))
+            .collect();
+        assert_eq!(reasons["hdsky"], "identical v2 file tree");
+        // The v1 entry still matches on its file list
+        assert_eq!(reasons["ourbits"], "exact files_hash");
+    }
+}
@@ -1,9 +1,15 @@
 //! Business logic services
 
+mod events;
 mod fingerprint;
 mod index;
+mod rate_limit;
 mod reseed;
+mod scheduler;
 
-pub use fingerprint::{ContentFingerprint, FingerprintMatcher};
-pub use index::{IndexService, ImportResult, IndexStats};
-pub use reseed::{ReseedService, ReseedRequest, ReseedResult, PreviewResult};
+pub use events::{Event, EventBus};
+pub use fingerprint::{ContentFingerprint, FingerprintOptions, ScoringWeights};
+pub use index::{IndexService, DuplicateSet, FingerprintAnalysis, ImportFilter, ImportResult, IndexExportEntry, IndexStats, PackSuggestion, SiteIndexCount};
+pub use reseed::{ReseedService, ReseedRequest, ReseedResult, ReseedMatch, PreviewResult, ReseedInProgress, UserAgentRotation};
+pub use rate_limit::RateLimiter;
+pub use scheduler::TaskScheduler;
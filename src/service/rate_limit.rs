@@ -0,0 +1,57 @@
+//! Global cap on outbound requests
+//!
+//! One limiter is shared by the index and reseed services, so requests to
+//! sites and clients together never exceed the configured rate. Per-site
+//! pacing still applies on top; whichever is stricter wins.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Spaces requests evenly at a fixed number per minute
+pub struct RateLimiter {
+    interval: Duration,
+    /// Earliest time the next request may start
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Limiter allowing `rpm` requests per minute (at least one)
+    pub fn per_minute(rpm: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / rpm.max(1),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for a free slot
+    ///
+    /// Slots are handed out in call order, so waiting callers are not starved.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_requests_are_spaced() {
+        let limiter = RateLimiter::per_minute(6000);
+        let started = Instant::now();
+
+        // The first request goes out at once, the rest 10ms apart
+        limiter.acquire().await;
+        assert!(started.elapsed() < Duration::from_millis(10));
+        futures::future::join_all((0..4).map(|_| limiter.acquire())).await;
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+}
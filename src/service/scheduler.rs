@@ -0,0 +1,274 @@
+//! Scheduled reseed tasks
+//!
+//! Runs the reseed tasks stored in `reseed_tasks` on their cron schedules.
+//! Scheduled runs are unattended, so matches below the stricter
+//! `schedule.auto_min_confidence` floor are queued for manual approval
+//! instead of added. A daily job also purges index entries cleared more
+//! than `schedule.index_purge_days` ago.
+
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+
+use crate::client::{BitTorrentClient, ClientConfig};
+use crate::config::ScheduleSettings;
+use crate::db::{repository, Database};
+use crate::service::index::IndexService;
+use crate::service::reseed::{ReseedRequest, ReseedService};
+use crate::site::SiteConfig;
+
+/// When cleared index entries are purged: daily at 04:30
+const PURGE_CRON: &str = "0 30 4 * * *";
+
+/// A reseed task row
+#[derive(Debug, Clone)]
+pub struct ReseedTask {
+    pub id: String,
+    pub name: String,
+    pub source_client: String,
+    pub target_client: String,
+    pub target_sites: Vec<String>,
+    pub cron_expression: String,
+    pub add_paused: bool,
+}
+
+/// Cron scheduler for reseed tasks
+pub struct TaskScheduler {
+    scheduler: JobScheduler,
+    db: Database,
+    reseed_service: Arc<ReseedService>,
+    index_service: Arc<IndexService>,
+    settings: ScheduleSettings,
+}
+
+impl TaskScheduler {
+    pub async fn new(
+        db: Database,
+        reseed_service: Arc<ReseedService>,
+        index_service: Arc<IndexService>,
+        settings: ScheduleSettings,
+    ) -> Result<Self> {
+        let scheduler = JobScheduler::new().await?;
+
+        Ok(Self {
+            scheduler,
+            db,
+            reseed_service,
+            index_service,
+            settings,
+        })
+    }
+
+    /// Register all enabled tasks that have a cron expression
+    ///
+    /// Returns the number of tasks scheduled. Tasks with an invalid cron
+    /// expression are logged and skipped.
+    pub async fn load_tasks(&self) -> Result<usize> {
+        let tasks = load_enabled_tasks(&self.db)?;
+        let mut scheduled = 0;
+
+        for task in tasks {
+            match self.add_reseed_job(task.clone()).await {
+                Ok(()) => scheduled += 1,
+                Err(e) => warn!("Failed to schedule task {} ({}): {}", task.name, task.id, e),
+            }
+        }
+
+        Ok(scheduled)
+    }
+
+    async fn add_reseed_job(&self, task: ReseedTask) -> Result<()> {
+        let db = self.db.clone();
+        let reseed_service = self.reseed_service.clone();
+        let min_confidence = self.settings.auto_min_confidence;
+        let cron = task.cron_expression.clone();
+
+        let job = Job::new_async(cron.as_str(), move |_uuid, _lock| {
+            let db = db.clone();
+            let reseed_service = reseed_service.clone();
+            let task = task.clone();
+            Box::pin(async move {
+                info!("Running scheduled reseed task: {}", task.name);
+                if let Err(e) = run_task(&db, &reseed_service, &task, min_confidence).await {
+                    error!("Scheduled reseed task {} failed: {:#}", task.name, e);
+                }
+            })
+        })
+        .with_context(|| format!("Invalid cron expression: {}", cron))?;
+
+        self.scheduler.add(job).await?;
+        Ok(())
+    }
+
+    /// Register the daily purge of cleared index entries
+    ///
+    /// Returns false when purging is disabled (`index_purge_days = 0`).
+    pub async fn add_purge_job(&self) -> Result<bool> {
+        let days = self.settings.index_purge_days;
+        if days == 0 {
+            return Ok(false);
+        }
+
+        let index_service = self.index_service.clone();
+        let job = Job::new_async(PURGE_CRON, move |_uuid, _lock| {
+            let index_service = index_service.clone();
+            Box::pin(async move {
+                let before = Utc::now() - Duration::days(days.into());
+                if let Err(e) = index_service.purge_deleted(before) {
+                    error!("Purging cleared index entries failed: {:#}", e);
+                }
+            })
+        })?;
+
+        self.scheduler.add(job).await?;
+        Ok(true)
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        self.scheduler.start().await?;
+        Ok(())
+    }
+
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.scheduler.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// Execute a single scheduled task
+async fn run_task(
+    db: &Database,
+    reseed_service: &ReseedService,
+    task: &ReseedTask,
+    min_confidence: f64,
+) -> Result<()> {
+    let source_client = load_client_config(db, &task.source_client)?.create_client();
+    let target_client = load_client_config(db, &task.target_client)?.create_client();
+    let sites = load_site_configs(db, &task.target_sites)?;
+
+    let request = ReseedRequest {
+        task_id: Some(task.id.clone()),
+        source_client_ids: vec![task.source_client.clone()],
+        target_client_id: task.target_client.clone(),
+        target_site_ids: task.target_sites.clone(),
+        add_paused: task.add_paused,
+        // Low-confidence matches wait for manual approval
+        queue_below_confidence: Some(min_confidence),
+        ..Default::default()
+    };
+
+    let source_refs: Vec<&dyn BitTorrentClient> = vec![source_client.as_ref()];
+    reseed_service
+        .execute(request, &source_refs, target_client.as_ref(), &sites)
+        .await?;
+
+    db.conn().execute(
+        "UPDATE reseed_tasks SET last_run_at = datetime('now') WHERE id = ?1",
+        [&task.id],
+    )?;
+
+    Ok(())
+}
+
+fn load_enabled_tasks(db: &Database) -> Result<Vec<ReseedTask>> {
+    let conn = db.conn();
+    let mut stmt = conn.prepare(
+        "SELECT id, name, source_client, target_client, target_sites, cron_expression, add_paused
+         FROM reseed_tasks
+         WHERE enabled = 1 AND cron_expression IS NOT NULL AND cron_expression != ''",
+    )?;
+
+    let tasks = stmt
+        .query_map([], |row| {
+            let target_sites: String = row.get(4)?;
+            Ok(ReseedTask {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                source_client: row.get(2)?,
+                target_client: row.get(3)?,
+                target_sites: serde_json::from_str(&target_sites).unwrap_or_default(),
+                cron_expression: row.get(5)?,
+                add_paused: row.get::<_, i32>(6)? != 0,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(tasks)
+}
+
+fn load_client_config(db: &Database, id: &str) -> Result<ClientConfig> {
+    let config = repository::get_client(&db.conn(), id)?
+        .with_context(|| format!("Client not found: {}", id))?;
+
+    if !config.enabled {
+        anyhow::bail!("Client is disabled: {}", config.name);
+    }
+
+    Ok(config)
+}
+
+fn load_site_configs(db: &Database, site_ids: &[String]) -> Result<Vec<SiteConfig>> {
+    let conn = db.conn();
+    let mut sites = Vec::new();
+
+    for site_id in site_ids {
+        if let Some(site) = repository::get_enabled_site(&conn, site_id)? {
+            if site.has_credentials() {
+                sites.push(site);
+            } else {
+                warn!("Skipping site {}: no passkey or cookie configured", site.id);
+            }
+        }
+    }
+
+    Ok(sites)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_enabled_tasks() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        {
+            let conn = db.conn();
+            conn.execute(
+                "INSERT INTO clients (id, name, client_type, host, port) VALUES ('qb', 'qb', 'qbittorrent', 'localhost', 8080)",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO reseed_tasks (id, name, source_client, target_client, target_sites, cron_expression, enabled)
+                 VALUES ('t1', 'nightly', 'qb', 'qb', '[\"hdsky\",\"ourbits\"]', '0 0 3 * * *', 1),
+                        ('t2', 'manual', 'qb', 'qb', '[]', NULL, 1),
+                        ('t3', 'off', 'qb', 'qb', '[]', '0 0 4 * * *', 0)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let tasks = load_enabled_tasks(&db).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].id, "t1");
+        assert_eq!(tasks[0].target_sites, vec!["hdsky".to_string(), "ourbits".to_string()]);
+    }
+
+    #[test]
+    fn test_disabled_client_is_refused() {
+        let db = Database::in_memory().unwrap();
+        db.migrate().unwrap();
+        db.conn()
+            .execute(
+                "INSERT INTO clients (id, name, client_type, host, port, enabled) VALUES ('qb', 'qb', 'qbittorrent', 'localhost', 8080, 0)",
+                [],
+            )
+            .unwrap();
+
+        let err = load_client_config(&db, "qb").unwrap_err();
+        assert!(err.to_string().contains("disabled"));
+    }
+}
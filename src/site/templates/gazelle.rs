@@ -4,7 +4,10 @@
 
 use async_trait::async_trait;
 
-use super::{Result, SiteTemplate, TemplateError, TemplateType};
+use super::{
+    check_download_url, check_rate_limit, check_session, decompress_torrent, json_error_message, with_download_headers,
+    AuthCheck, Result, SiteTemplate, TemplateError, TemplateType,
+};
 use crate::site::SiteConfig;
 
 pub struct GazelleTemplate {
@@ -19,19 +22,10 @@ impl GazelleTemplate {
             authkey: None,
         }
     }
-
-    pub fn with_authkey(mut self, authkey: String) -> Self {
-        self.authkey = Some(authkey);
-        self
-    }
 }
 
 #[async_trait]
 impl SiteTemplate for GazelleTemplate {
-    fn config(&self) -> &SiteConfig {
-        &self.config
-    }
-
     fn template_type(&self) -> TemplateType {
         TemplateType::Gazelle
     }
@@ -59,17 +53,20 @@ impl SiteTemplate for GazelleTemplate {
     ) -> Result<Vec<u8>> {
         let url = self.build_download_url(torrent_id)?;
 
-        let mut request = http_client.get(&url);
+        let mut request = with_download_headers(&self.config, http_client.get(&url));
 
         // Gazelle sites typically require cookie authentication
         if let Some(ref cookie) = self.config.cookie {
             request = request.header("Cookie", cookie);
         }
 
-        let response = request
-            .header("User-Agent", "Graft/1.0")
-            .send()
-            .await?;
+        let response = request.send().await?;
+        check_download_url(&self.config, response.url())?;
+        check_rate_limit(&response)?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(TemplateError::AuthenticationFailed);
+        }
 
         if !response.status().is_success() {
             return Err(TemplateError::DownloadFailed(format!(
@@ -79,21 +76,30 @@ impl SiteTemplate for GazelleTemplate {
             )));
         }
 
-        let bytes = response.bytes().await?;
+        let bytes = decompress_torrent(&response.bytes().await?)?;
+        self.validate_download(&bytes)?;
 
-        // Verify it's a valid torrent file
-        if bytes.first() != Some(&b'd') {
-            // Check if it's a JSON error response
-            if let Ok(text) = std::str::from_utf8(&bytes) {
-                if text.contains("error") || text.contains("failure") {
-                    return Err(TemplateError::InvalidResponse(text.to_string()));
-                }
+        Ok(bytes.to_vec())
+    }
+
+    fn validate_download(&self, bytes: &[u8]) -> Result<()> {
+        if bytes.first() == Some(&b'd') {
+            return Ok(());
+        }
+        // Check if it's a JSON error response
+        if let Some(message) = json_error_message(bytes) {
+            return Err(TemplateError::SiteError(message));
+        }
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            if text.contains("error") || text.contains("failure") {
+                return Err(TemplateError::InvalidResponse(text.to_string()));
             }
-            return Err(TemplateError::InvalidResponse(
-                "Invalid torrent file format".to_string()
-            ));
         }
+        Err(TemplateError::InvalidResponse("Invalid torrent file format".to_string()))
+    }
 
-        Ok(bytes.to_vec())
+    async fn check_auth(&self, http_client: &reqwest::Client) -> Result<AuthCheck> {
+        // Gazelle downloads need a logged-in session
+        check_session(&self.config, http_client, "/index.php", true).await
     }
 }
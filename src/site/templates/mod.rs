@@ -12,12 +12,17 @@ pub use unit3d::Unit3DTemplate;
 pub use gazelle::GazelleTemplate;
 
 use async_trait::async_trait;
+use flate2::read::{GzDecoder, ZlibDecoder};
 use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::time::Duration;
+use utoipa::ToSchema;
 
 use super::SiteConfig;
+use crate::utils::torrent::{read_contents, TorrentContents};
 
 /// Template type enum
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum TemplateType {
     NexusPHP,
@@ -25,6 +30,17 @@ pub enum TemplateType {
     Gazelle,
 }
 
+impl TemplateType {
+    /// Download URL pattern used when a site doesn't define its own
+    pub fn default_download_pattern(&self) -> &'static str {
+        match self {
+            TemplateType::NexusPHP => "/download.php?id={id}&passkey={passkey}",
+            TemplateType::Unit3D => "/torrent/download/{id}.{rsskey}",
+            TemplateType::Gazelle => "/torrents.php?action=download&id={id}&authkey={authkey}&torrent_pass={passkey}",
+        }
+    }
+}
+
 impl std::fmt::Display for TemplateType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -57,26 +73,193 @@ pub enum TemplateError {
     #[error("Missing cookie")]
     MissingCookie,
 
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+
     #[error("Download failed: {0}")]
     DownloadFailed(String),
 
+    /// HTTP 429, with the wait the site asked for in `Retry-After`
+    #[error("Rate limited by site{}", retry_after.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+
     #[error("HTTP error: {0}")]
     HttpError(#[from] reqwest::Error),
 
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
+
+    /// The site answered with an error message instead of a torrent file
+    #[error("Site refused the download: {0}")]
+    SiteError(String),
+
+    #[error("Login is not supported for {0} sites")]
+    LoginNotSupported(TemplateType),
+}
+
+impl TemplateError {
+    /// Whether this error means the site rejected our credentials
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self, TemplateError::MissingCookie | TemplateError::AuthenticationFailed)
+    }
 }
 
 pub type Result<T> = std::result::Result<T, TemplateError>;
 
+/// Validate a downloaded torrent file
+///
+/// Decodes the bencoded metainfo (v1, hybrid or v2-only), catching sites
+/// that answer with an error page instead of a torrent file.
+pub fn validate_torrent(bytes: &[u8]) -> Result<TorrentContents> {
+    read_contents(bytes).map_err(|e| TemplateError::InvalidResponse(format!("{:#}", e)))
+}
+
+/// Undo gzip or zlib compression of a downloaded torrent file
+///
+/// The HTTP client already decodes `Content-Encoding`, but some sites serve
+/// compressed bytes without that header. Uncompressed input is returned as is.
+pub(crate) fn decompress_torrent(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let decoded = match bytes {
+        [0x1f, 0x8b, ..] => GzDecoder::new(bytes).read_to_end(&mut out),
+        [0x78, 0x01 | 0x5e | 0x9c | 0xda, ..] => ZlibDecoder::new(bytes).read_to_end(&mut out),
+        _ => return Ok(bytes.to_vec()),
+    };
+
+    decoded.map_err(|e| {
+        TemplateError::InvalidResponse(format!("Failed to decompress torrent file: {}", e))
+    })?;
+    Ok(out)
+}
+
+/// User-Agent sent to sites that don't set their own
+pub const DEFAULT_USER_AGENT: &str = "Graft/1.0";
+
+/// Add the site's download headers to a request
+///
+/// Sends `Referer: <base_url>` unless the site overrides it, since some
+/// NexusPHP sites reject downloads without one, and `DEFAULT_USER_AGENT`
+/// unless the site sets a User-Agent.
+pub(crate) fn with_download_headers(
+    config: &SiteConfig,
+    mut request: reqwest::RequestBuilder,
+) -> reqwest::RequestBuilder {
+    if !config.has_extra_header("referer") {
+        request = request.header(reqwest::header::REFERER, &config.base_url);
+    }
+    if !config.has_extra_header("user-agent") {
+        request = request.header(reqwest::header::USER_AGENT, DEFAULT_USER_AGENT);
+    }
+
+    for (name, value) in &config.extra_headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+
+    request
+}
+
+/// What a credentials check could confirm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthCheck {
+    /// The site accepted the session cookie
+    Verified,
+    /// A passkey is set but there is no cookie to check; the passkey is only
+    /// known to work once a download succeeds
+    Unverified,
+}
+
+/// Check that a site accepts our credentials by loading one of its pages
+///
+/// Without a cookie nothing is sent and the result is `Unverified`, unless
+/// `require_cookie` is set. A redirect to a login page counts as rejected
+/// credentials.
+pub(crate) async fn check_session(
+    config: &SiteConfig,
+    http_client: &reqwest::Client,
+    path: &str,
+    require_cookie: bool,
+) -> Result<AuthCheck> {
+    if config.download_key().is_none() {
+        return Err(TemplateError::MissingPasskey);
+    }
+
+    let cookie = match config.cookie {
+        Some(ref cookie) => cookie,
+        None if require_cookie => return Err(TemplateError::MissingCookie),
+        None => return Ok(AuthCheck::Unverified),
+    };
+
+    let url = format!("{}{}", config.base_url, path);
+    let response = with_download_headers(config, http_client.get(&url))
+        .header("Cookie", cookie)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(TemplateError::AuthenticationFailed);
+    }
+    if !status.is_success() {
+        return Err(TemplateError::InvalidResponse(format!("HTTP {}", status)));
+    }
+    if is_login_url(response.url()) {
+        return Err(TemplateError::AuthenticationFailed);
+    }
+
+    Ok(AuthCheck::Verified)
+}
+
+/// Whether a URL points at a login page (where sites redirect expired sessions)
+fn is_login_url(url: &reqwest::Url) -> bool {
+    let path = url.path().to_lowercase();
+    path.contains("login") || path.contains("signin") || path == "/auth" || path.starts_with("/auth/")
+}
+
+/// Fail a download the site refused with HTTP 429
+pub(crate) fn check_rate_limit(response: &reqwest::Response) -> Result<()> {
+    if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_retry_after(v, chrono::Utc::now()));
+    Err(TemplateError::RateLimited { retry_after })
+}
+
+/// Parse a `Retry-After` value: delay seconds or an HTTP date
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Fail a download that was redirected to a login page
+///
+/// Redirects are followed, so a missing or expired session ends at the
+/// site's login page instead of an error status. `final_url` is the URL of
+/// the response after redirects; redirects elsewhere (e.g. a CDN) pass.
+pub(crate) fn check_download_url(config: &SiteConfig, final_url: &reqwest::Url) -> Result<()> {
+    if !is_login_url(final_url) {
+        return Ok(());
+    }
+    match config.cookie {
+        None => Err(TemplateError::MissingCookie),
+        Some(_) => Err(TemplateError::AuthenticationFailed),
+    }
+}
+
 /// Site template trait
 ///
 /// Defines the interface for interacting with PT sites
 #[async_trait]
 pub trait SiteTemplate: Send + Sync {
-    /// Get site configuration
-    fn config(&self) -> &SiteConfig;
-
     /// Get template type
     fn template_type(&self) -> TemplateType;
 
@@ -89,4 +272,234 @@ pub trait SiteTemplate: Send + Sync {
         http_client: &reqwest::Client,
         torrent_id: &str,
     ) -> Result<Vec<u8>>;
+
+    /// Check that a downloaded body is a torrent file
+    ///
+    /// Templates for sites that report errors in the body (e.g. JSON with
+    /// HTTP 200) override this to surface the site's message.
+    fn validate_download(&self, bytes: &[u8]) -> Result<()> {
+        if bytes.first() != Some(&b'd') {
+            return Err(TemplateError::InvalidResponse("Invalid torrent file format".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check that the site accepts the configured credentials
+    async fn check_auth(&self, http_client: &reqwest::Client) -> Result<AuthCheck>;
+
+    /// Log in with the site username and password, returning the session
+    /// cookie to send with later requests
+    async fn login(&self) -> Result<String> {
+        Err(TemplateError::LoginNotSupported(self.template_type()))
+    }
+}
+
+/// The error message of a JSON error body, e.g. `{"message": "..."}` or
+/// `{"status": "failure", "error": "..."}`; `None` for anything else
+pub(crate) fn json_error_message(bytes: &[u8]) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_slice(bytes).ok()?;
+    let body = body.as_object()?;
+
+    for key in ["error", "message", "errors"] {
+        let message = match body.get(key) {
+            Some(serde_json::Value::String(s)) => s.trim().to_string(),
+            // Validation errors: a list, or lists of messages per field
+            Some(serde_json::Value::Array(items)) => join_strings(items.iter()),
+            Some(serde_json::Value::Object(fields)) => join_strings(
+                fields.values().flat_map(|v| v.as_array().map_or(std::slice::from_ref(v), |a| a.as_slice())),
+            ),
+            _ => continue,
+        };
+        if !message.is_empty() {
+            return Some(message);
+        }
+    }
+    None
+}
+
+fn join_strings<'a>(values: impl Iterator<Item = &'a serde_json::Value>) -> String {
+    values.filter_map(|v| v.as_str()).map(str::trim).collect::<Vec<_>>().join("; ")
+}
+
+/// Timeout for a login request
+pub(crate) const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The `name=value` pairs set by a response's `Set-Cookie` headers, as a
+/// `Cookie` header value; `None` when it sets none
+///
+/// Cookies being cleared (empty or `deleted` values) are left out.
+pub(crate) fn session_cookie(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let pairs: Vec<&str> = headers
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next())
+        .map(str::trim)
+        .filter(|pair| matches!(pair.split_once('='), Some((name, value)) if !name.is_empty() && !value.is_empty() && value != "deleted"))
+        .collect();
+
+    (!pairs.is_empty()).then(|| pairs.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_error_message() {
+        assert_eq!(json_error_message(br#"{"message": "Torrent not found"}"#).as_deref(), Some("Torrent not found"));
+        assert_eq!(
+            json_error_message(br#"{"status": "failure", "error": "bad parameters"}"#).as_deref(),
+            Some("bad parameters")
+        );
+        assert_eq!(
+            json_error_message(br#"{"errors": {"rsskey": ["Invalid rsskey"]}}"#).as_deref(),
+            Some("Invalid rsskey")
+        );
+        assert_eq!(json_error_message(br#"{"message": ""}"#), None);
+        assert_eq!(json_error_message(b"<html>error</html>"), None);
+        assert_eq!(json_error_message(b"d4:infod4:name1:aee"), None);
+    }
+
+    fn single_file_torrent(length: u64) -> Vec<u8> {
+        let mut bytes = format!(
+            "d4:infod6:lengthi{}e4:name8:file.mkv12:piece lengthi16384e6:pieces20:",
+            length
+        )
+        .into_bytes();
+        // Piece hashes are raw bytes, not UTF-8
+        bytes.extend([0xffu8; 20]);
+        bytes.extend(b"ee");
+        bytes
+    }
+
+    #[test]
+    fn test_validate_torrent() {
+        let contents = validate_torrent(&single_file_torrent(1000)).unwrap();
+        assert_eq!(contents.files[0].size, 1000);
+    }
+
+    #[test]
+    fn test_decompress_gzipped_torrent() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let torrent = single_file_torrent(1000);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&torrent).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let decoded = decompress_torrent(&gzipped).unwrap();
+        assert_eq!(decoded, torrent);
+        validate_torrent(&decoded).unwrap();
+
+        // Plain torrents pass through untouched
+        assert_eq!(decompress_torrent(&torrent).unwrap(), torrent);
+    }
+
+    #[test]
+    fn test_validate_torrent_garbage() {
+        let err = validate_torrent(b"d8:announce").unwrap_err();
+        assert!(matches!(err, TemplateError::InvalidResponse(_)));
+    }
+
+    #[test]
+    fn test_validate_v2_only_torrent() {
+        let mut bytes = b"d4:infod9:file treed8:file.mkvd0:d6:lengthi1000e11:pieces root32:".to_vec();
+        bytes.extend([0x11u8; 32]);
+        bytes.extend(b"eee12:meta versioni2e4:name8:file.mkv12:piece lengthi16384eee");
+
+        let contents = validate_torrent(&bytes).unwrap();
+        assert_eq!(contents.name, "file.mkv");
+        assert!(contents.v2_root.is_some());
+    }
+
+    #[test]
+    fn test_is_login_url() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(is_login_url(&url("https://hdsky.me/login.php?returnto=index.php")));
+        assert!(is_login_url(&url("https://blutopia.cc/Login")));
+        assert!(is_login_url(&url("https://example.com/auth/signin?next=/download")));
+        assert!(!is_login_url(&url("https://hdsky.me/index.php")));
+        assert!(!is_login_url(&url("https://cdn.example.com/authors/t.torrent")));
+    }
+
+    #[test]
+    fn test_session_cookie() {
+        use reqwest::header::{HeaderMap, HeaderValue, SET_COOKIE};
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_cookie(&headers), None);
+
+        headers.append(SET_COOKIE, HeaderValue::from_static("c_secure_uid=MTIz; path=/; HttpOnly"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("c_secure_pass=abc; expires=Fri, 01 Jan 2100 00:00:00 GMT"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("c_secure_login=deleted; path=/"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("empty=; path=/"));
+        assert_eq!(session_cookie(&headers).as_deref(), Some("c_secure_uid=MTIz; c_secure_pass=abc"));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z").unwrap().to_utc();
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now), Some(Duration::from_secs(90)));
+        // A date in the past means retry now
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_check_download_url() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        let mut config = crate::site::builtin_sites().remove(0);
+
+        // A redirect to a CDN is fine
+        check_download_url(&config, &url("https://cdn.example.com/t/42.torrent")).unwrap();
+
+        let login = url("https://kp.m-team.cc/login.php?returnto=download.php");
+        assert!(matches!(check_download_url(&config, &login), Err(TemplateError::MissingCookie)));
+        config.cookie = Some("uid=1".to_string());
+        assert!(matches!(check_download_url(&config, &login), Err(TemplateError::AuthenticationFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_check_session_without_cookie() {
+        let client = reqwest::Client::new();
+        let mut config = crate::site::builtin_sites().remove(0);
+        config.base_url = "http://127.0.0.1:9".to_string();
+        config.passkey = Some("key".to_string());
+
+        // Nothing is sent, so nothing is confirmed
+        let checked = check_session(&config, &client, "/index.php", false).await.unwrap();
+        assert_eq!(checked, AuthCheck::Unverified);
+        assert!(matches!(
+            check_session(&config, &client, "/index.php", true).await,
+            Err(TemplateError::MissingCookie)
+        ));
+    }
+
+    #[test]
+    fn test_download_headers() {
+        let client = reqwest::Client::new();
+        let mut config = crate::site::builtin_sites().remove(0);
+
+        let request = with_download_headers(&config, client.get("https://example.com/t"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["referer"], config.base_url.as_str());
+        assert_eq!(request.headers()["user-agent"], DEFAULT_USER_AGENT);
+
+        config.extra_headers.insert("Referer".to_string(), "https://example.com/details".to_string());
+        config.extra_headers.insert("X-Requested-With".to_string(), "XMLHttpRequest".to_string());
+        config.extra_headers.insert("user-agent".to_string(), "Mozilla/5.0".to_string());
+        let request = with_download_headers(&config, client.get("https://example.com/t"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers().get_all("referer").iter().count(), 1);
+        assert_eq!(request.headers()["referer"], "https://example.com/details");
+        assert_eq!(request.headers().get_all("user-agent").iter().count(), 1);
+        assert_eq!(request.headers()["user-agent"], "Mozilla/5.0");
+        assert_eq!(request.headers()["x-requested-with"], "XMLHttpRequest");
+    }
 }
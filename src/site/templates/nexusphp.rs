@@ -4,7 +4,10 @@
 
 use async_trait::async_trait;
 
-use super::{Result, SiteTemplate, TemplateError, TemplateType};
+use super::{
+    check_download_url, check_rate_limit, check_session, decompress_torrent, session_cookie, with_download_headers,
+    AuthCheck, Result, SiteTemplate, TemplateError, TemplateType, LOGIN_TIMEOUT,
+};
 use crate::site::SiteConfig;
 
 pub struct NexusPHPTemplate {
@@ -19,10 +22,6 @@ impl NexusPHPTemplate {
 
 #[async_trait]
 impl SiteTemplate for NexusPHPTemplate {
-    fn config(&self) -> &SiteConfig {
-        &self.config
-    }
-
     fn template_type(&self) -> TemplateType {
         TemplateType::NexusPHP
     }
@@ -45,7 +44,7 @@ impl SiteTemplate for NexusPHPTemplate {
     ) -> Result<Vec<u8>> {
         let url = self.build_download_url(torrent_id)?;
 
-        let mut request = http_client.get(&url);
+        let mut request = with_download_headers(&self.config, http_client.get(&url));
 
         // Add cookie if available
         if let Some(ref cookie) = self.config.cookie {
@@ -55,6 +54,12 @@ impl SiteTemplate for NexusPHPTemplate {
         let response = request
             .send()
             .await?;
+        check_download_url(&self.config, response.url())?;
+        check_rate_limit(&response)?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(TemplateError::AuthenticationFailed);
+        }
 
         if !response.status().is_success() {
             return Err(TemplateError::DownloadFailed(format!(
@@ -82,15 +87,44 @@ impl SiteTemplate for NexusPHPTemplate {
             ));
         }
 
-        let bytes = response.bytes().await?;
+        let bytes = decompress_torrent(&response.bytes().await?)?;
+        self.validate_download(&bytes)?;
 
-        // Verify it's a valid torrent file (starts with "d")
-        if bytes.first() != Some(&b'd') {
-            return Err(TemplateError::InvalidResponse(
-                "Invalid torrent file format".to_string()
-            ));
+        Ok(bytes.to_vec())
+    }
+
+    async fn check_auth(&self, http_client: &reqwest::Client) -> Result<AuthCheck> {
+        // Passkey-only setups are fine for downloads; the cookie is checked when set
+        check_session(&self.config, http_client, "/index.php", false).await
+    }
+
+    async fn login(&self) -> Result<String> {
+        let (Some(username), Some(password)) = (&self.config.site_username, &self.config.site_password) else {
+            return Err(TemplateError::MissingCookie);
+        };
+
+        // The session cookie is set on the redirect after a successful login
+        let http_client = self
+            .config
+            .http_client_builder(LOGIN_TIMEOUT)?
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        let response = with_download_headers(&self.config, http_client.post(format!("{}/takelogin.php", self.config.base_url)))
+            .form(&[("username", username.as_str()), ("password", password.as_str())])
+            .send()
+            .await?;
+        check_rate_limit(&response)?;
+
+        // A failed login shows the form again or redirects back to it
+        let redirected_to_login = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|location| location.to_lowercase().contains("login"));
+        if !response.status().is_redirection() || redirected_to_login {
+            return Err(TemplateError::AuthenticationFailed);
         }
 
-        Ok(bytes.to_vec())
+        session_cookie(response.headers()).ok_or(TemplateError::AuthenticationFailed)
     }
 }
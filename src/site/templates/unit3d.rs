@@ -4,7 +4,10 @@
 
 use async_trait::async_trait;
 
-use super::{Result, SiteTemplate, TemplateError, TemplateType};
+use super::{
+    check_download_url, check_rate_limit, check_session, decompress_torrent, json_error_message, with_download_headers,
+    AuthCheck, Result, SiteTemplate, TemplateError, TemplateType,
+};
 use crate::site::SiteConfig;
 
 pub struct Unit3DTemplate {
@@ -19,22 +22,20 @@ impl Unit3DTemplate {
 
 #[async_trait]
 impl SiteTemplate for Unit3DTemplate {
-    fn config(&self) -> &SiteConfig {
-        &self.config
-    }
-
     fn template_type(&self) -> TemplateType {
         TemplateType::Unit3D
     }
 
     fn build_download_url(&self, torrent_id: &str) -> Result<String> {
-        let passkey = self.config.passkey.as_ref()
+        // Downloads take the RSS key, not the API passkey
+        let rsskey = self.config.download_key()
             .ok_or(TemplateError::MissingPasskey)?;
 
         // Unit3D typically uses format: /torrent/download/{id}.{rsskey}
         let url = self.config.download_pattern
             .replace("{id}", torrent_id)
-            .replace("{passkey}", passkey);
+            .replace("{rsskey}", rsskey)
+            .replace("{passkey}", rsskey);
 
         Ok(format!("{}{}", self.config.base_url, url))
     }
@@ -46,17 +47,20 @@ impl SiteTemplate for Unit3DTemplate {
     ) -> Result<Vec<u8>> {
         let url = self.build_download_url(torrent_id)?;
 
-        let mut request = http_client.get(&url);
+        let mut request = with_download_headers(&self.config, http_client.get(&url));
 
         // Add cookie if available
         if let Some(ref cookie) = self.config.cookie {
             request = request.header("Cookie", cookie);
         }
 
-        let response = request
-            .header("User-Agent", "Graft/1.0")
-            .send()
-            .await?;
+        let response = request.send().await?;
+        check_download_url(&self.config, response.url())?;
+        check_rate_limit(&response)?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(TemplateError::AuthenticationFailed);
+        }
 
         if !response.status().is_success() {
             return Err(TemplateError::DownloadFailed(format!(
@@ -66,15 +70,25 @@ impl SiteTemplate for Unit3DTemplate {
             )));
         }
 
-        let bytes = response.bytes().await?;
+        let bytes = decompress_torrent(&response.bytes().await?)?;
+        self.validate_download(&bytes)?;
+
+        Ok(bytes.to_vec())
+    }
 
-        // Verify it's a valid torrent file
-        if bytes.first() != Some(&b'd') {
-            return Err(TemplateError::InvalidResponse(
-                "Invalid torrent file format".to_string()
-            ));
+    fn validate_download(&self, bytes: &[u8]) -> Result<()> {
+        if bytes.first() == Some(&b'd') {
+            return Ok(());
+        }
+        // Unit3D reports a bad torrent id or RSS key as JSON with HTTP 200
+        if let Some(message) = json_error_message(bytes) {
+            return Err(TemplateError::SiteError(message));
         }
+        Err(TemplateError::InvalidResponse("Invalid torrent file format".to_string()))
+    }
 
-        Ok(bytes.to_vec())
+    async fn check_auth(&self, http_client: &reqwest::Client) -> Result<AuthCheck> {
+        // Downloads use the RSS key; the cookie is checked when set
+        check_session(&self.config, http_client, "/", false).await
     }
 }
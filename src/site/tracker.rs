@@ -27,6 +27,13 @@ impl TrackerIdentifier {
         identifier
     }
 
+    /// Add (domain, site id) mappings, e.g. those stored for configured
+    /// sites, over the builtin ones
+    pub fn with_domains(mut self, domains: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.domain_map.extend(domains);
+        self
+    }
+
     fn register_builtin_sites(&mut self) {
         let mappings = [
             // M-Team
@@ -110,6 +117,27 @@ impl TrackerIdentifier {
         None
     }
 
+    /// Identify every site a torrent is registered on
+    ///
+    /// Returns one identification per site, in tracker order. A site listed
+    /// by several trackers keeps the first torrent ID found for it.
+    pub fn identify_all(&self, trackers: &[String]) -> Vec<SiteIdentification> {
+        let mut sites: Vec<SiteIdentification> = Vec::new();
+
+        for result in trackers.iter().filter_map(|t| self.identify(t)) {
+            match sites.iter_mut().find(|s| s.site_id == result.site_id) {
+                Some(existing) => {
+                    if existing.torrent_id.is_none() {
+                        existing.torrent_id = result.torrent_id;
+                    }
+                }
+                None => sites.push(result),
+            }
+        }
+
+        sites
+    }
+
     fn find_site_by_host(&self, host: &str) -> Option<String> {
         // Direct match
         if let Some(site_id) = self.domain_map.get(host) {
@@ -156,23 +184,13 @@ impl TrackerIdentifier {
         let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
 
         for segment in segments.iter().rev() {
-            if segment.chars().all(|c| c.is_ascii_digit()) && segment.len() > 0 {
+            if segment.chars().all(|c| c.is_ascii_digit()) && !segment.is_empty() {
                 return Some(segment.to_string());
             }
         }
 
         None
     }
-
-    /// Register a custom site domain mapping
-    pub fn register_site(&mut self, domain: &str, site_id: &str) {
-        self.domain_map.insert(domain.to_string(), site_id.to_string());
-    }
-
-    /// Get all registered domains
-    pub fn get_domains(&self) -> Vec<(&String, &String)> {
-        self.domain_map.iter().collect()
-    }
 }
 
 impl Default for TrackerIdentifier {
@@ -206,6 +224,16 @@ mod tests {
         assert_eq!(result.torrent_id, Some("12345".to_string()));
     }
 
+    #[test]
+    fn test_identify_stored_domain() {
+        let identifier = TrackerIdentifier::new()
+            .with_domains([("tracker.example.org".to_string(), "custom".to_string())]);
+
+        let result = identifier.identify("https://tracker.example.org/announce?passkey=abc").unwrap();
+        assert_eq!(result.site_id, "custom");
+        assert_eq!(identifier.identify("https://hdsky.me/announce.php").unwrap().site_id, "hdsky");
+    }
+
     #[test]
     fn test_unknown_site() {
         let identifier = TrackerIdentifier::new();
@@ -213,4 +241,21 @@ mod tests {
         let result = identifier.identify("https://unknown-site.com/announce");
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_identify_all() {
+        let identifier = TrackerIdentifier::new();
+        let trackers = vec![
+            "https://hdsky.me/announce.php?passkey=abc".to_string(),
+            "https://unknown-site.com/announce".to_string(),
+            "https://kp.m-team.cc/announce.php?passkey=def".to_string(),
+            "https://hdsky.me/announce.php?passkey=abc&torrent_id=12345".to_string(),
+        ];
+
+        let sites = identifier.identify_all(&trackers);
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0].site_id, "hdsky");
+        assert_eq!(sites[0].torrent_id, Some("12345".to_string()));
+        assert_eq!(sites[1].site_id, "mteam");
+    }
 }
@@ -1,39 +1,105 @@
 //! Utility functions
 
+pub mod torrent;
+
+use anyhow::{bail, Context, Result};
 use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
 
-/// Simple encryption for storing passwords (not cryptographically secure, just obfuscation)
-/// In production, use a proper secrets manager or encryption library
-pub fn encrypt_password(password: &str) -> String {
-    base64::engine::general_purpose::STANDARD.encode(password.as_bytes())
+/// Key for stored passwords, set by `init_secret_key`
+static SECRET_KEY: OnceLock<LessSafeKey> = OnceLock::new();
+
+/// Marks values written by `encrypt_password`
+const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Load the key for stored passwords from `path`, creating it on first run
+///
+/// The key is kept out of the database, so copies and backups of the
+/// database don't reveal the passwords in it. Restoring a backup elsewhere
+/// needs the key file too.
+pub fn init_secret_key(path: &Path) -> Result<()> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut bytes = vec![0u8; CHACHA20_POLY1305.key_len()];
+            SystemRandom::new()
+                .fill(&mut bytes)
+                .map_err(|_| anyhow::anyhow!("Failed to generate a secret key"))?;
+
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            options
+                .open(path)
+                .and_then(|mut file| file.write_all(&bytes))
+                .with_context(|| format!("Failed to write secret key to {:?}", path))?;
+            bytes
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read secret key from {:?}", path)),
+    };
+
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+        .map_err(|_| anyhow::anyhow!("Invalid secret key in {:?}", path))?;
+    if SECRET_KEY.set(LessSafeKey::new(key)).is_err() {
+        bail!("Secret key is already loaded");
+    }
+    Ok(())
 }
 
-/// Decrypt password
-pub fn decrypt_password(encrypted: &str) -> Option<String> {
-    base64::engine::general_purpose::STANDARD
-        .decode(encrypted)
-        .ok()
-        .and_then(|bytes| String::from_utf8(bytes).ok())
+fn secret_key() -> &'static LessSafeKey {
+    // Without `init_secret_key` (tests), a key that lives as long as the process
+    SECRET_KEY.get_or_init(|| {
+        let mut bytes = vec![0u8; CHACHA20_POLY1305.key_len()];
+        SystemRandom::new().fill(&mut bytes).expect("Failed to generate a secret key");
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &bytes).expect("Invalid key length"))
+    })
 }
 
-/// Format file size in human-readable format
-pub fn format_size(size: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-    const TB: u64 = GB * 1024;
-
-    if size >= TB {
-        format!("{:.2} TB", size as f64 / TB as f64)
-    } else if size >= GB {
-        format!("{:.2} GB", size as f64 / GB as f64)
-    } else if size >= MB {
-        format!("{:.2} MB", size as f64 / MB as f64)
-    } else if size >= KB {
-        format!("{:.2} KB", size as f64 / KB as f64)
-    } else {
-        format!("{} B", size)
+/// Encrypt a password for storage (ChaCha20-Poly1305 under the secret key)
+pub fn encrypt_password(password: &str) -> String {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).expect("Failed to generate a nonce");
+
+    let mut sealed = password.as_bytes().to_vec();
+    secret_key()
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+        .expect("Password too long to encrypt");
+
+    let mut bytes = nonce.to_vec();
+    bytes.extend(sealed);
+    format!("{}{}", ENCRYPTED_PREFIX, base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Decrypt a password stored by `encrypt_password`, `None` when it was
+/// encrypted under another key
+///
+/// Values stored before encryption are returned as they are.
+pub fn decrypt_password(stored: &str) -> Option<String> {
+    let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Some(stored.to_string());
+    };
+
+    let mut bytes = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+    if bytes.len() < NONCE_LEN {
+        return None;
     }
+    let mut sealed = bytes.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&bytes).ok()?;
+    let plain = secret_key().open_in_place(nonce, Aad::empty(), &mut sealed).ok()?;
+    String::from_utf8(plain.to_vec()).ok()
+}
+
+/// Whether a stored value was written by `encrypt_password`
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
 }
 
 #[cfg(test)]
@@ -44,16 +110,21 @@ mod tests {
     fn test_password_encryption() {
         let password = "my_secret_password";
         let encrypted = encrypt_password(password);
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains(password));
+        // Fresh nonce every time
+        assert_ne!(encrypt_password(password), encrypted);
+
         let decrypted = decrypt_password(&encrypted);
         assert_eq!(decrypted, Some(password.to_string()));
-    }
 
-    #[test]
-    fn test_format_size() {
-        assert_eq!(format_size(500), "500 B");
-        assert_eq!(format_size(1024), "1.00 KB");
-        assert_eq!(format_size(1024 * 1024), "1.00 MB");
-        assert_eq!(format_size(1024 * 1024 * 1024), "1.00 GB");
-        assert_eq!(format_size(1024 * 1024 * 1024 * 1024), "1.00 TB");
+        // Tampered or foreign values don't decrypt
+        let mut tampered = encrypted.clone();
+        tampered.pop();
+        tampered.push(if encrypted.ends_with('A') { 'B' } else { 'A' });
+        assert_eq!(decrypt_password(&tampered), None);
+
+        // Passwords stored before encryption
+        assert_eq!(decrypt_password("plain"), Some("plain".to_string()));
     }
 }
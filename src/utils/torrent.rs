@@ -0,0 +1,558 @@
+//! Torrent file manipulation
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::client::TorrentFile;
+
+/// Lowercase hex info hash of a torrent file, as download clients report
+/// it (truncated to 20 bytes for v2-only torrents)
+pub fn info_hash(bytes: &[u8]) -> Result<String> {
+    Ok(read_contents(bytes)?.info_hash)
+}
+
+/// Name, info hash and file list read from a torrent file
+#[derive(Debug, Clone)]
+pub struct TorrentContents {
+    /// v1 info hash, or the truncated v2 info hash of a v2-only torrent (as
+    /// download clients report it)
+    pub info_hash: String,
+    pub name: String,
+    /// Files as a download client lists them: multi-file torrents are
+    /// prefixed with the torrent name as their root directory
+    pub files: Vec<TorrentFile>,
+    /// Root of the BitTorrent v2 file tree (see `v2_file_tree_root`), for
+    /// v2 and hybrid torrents
+    pub v2_root: Option<String>,
+    /// `announce` followed by the `announce-list` tiers, without duplicates
+    pub trackers: Vec<String>,
+}
+
+/// Parse a torrent file's name and file list
+///
+/// Accepts v1, hybrid and v2-only torrents.
+pub fn read_contents(bytes: &[u8]) -> Result<TorrentContents> {
+    // Anything the v2 reader rejects is left to the v1 parser to report
+    let v2 = read_v2_info(bytes).ok().flatten();
+    let trackers = read_trackers(bytes).unwrap_or_default();
+
+    let torrent = match lava_torrent::torrent::v1::Torrent::read_from_bytes(bytes) {
+        Ok(torrent) => torrent,
+        Err(e) => match v2 {
+            // v2-only torrents have no v1 piece hashes or file list
+            Some(v2) => return Ok(v2.into_contents(trackers)),
+            None => return Err(anyhow::Error::new(e).context("Invalid torrent file")),
+        },
+    };
+
+    let files = match &torrent.files {
+        Some(files) => files
+            .iter()
+            .map(|file| TorrentFile {
+                name: format!("{}/{}", torrent.name, file.path.to_string_lossy().replace('\\', "/")),
+                size: file.length.max(0) as u64,
+                progress: 0.0,
+            })
+            // Hybrid torrents list padding files in their v1 file list
+            .filter(|file| !file.name.contains("/.pad/"))
+            .collect(),
+        None => vec![TorrentFile {
+            name: torrent.name.clone(),
+            size: torrent.length.max(0) as u64,
+            progress: 0.0,
+        }],
+    };
+
+    Ok(TorrentContents {
+        info_hash: torrent.info_hash(),
+        name: torrent.name,
+        files,
+        v2_root: v2.map(|v2| v2.root),
+        trackers,
+    })
+}
+
+/// Tracker URLs from `announce` and `announce-list`, in order
+fn read_trackers(bytes: &[u8]) -> Result<Vec<String>> {
+    let mut trackers: Vec<String> = Vec::new();
+    let mut add = |url: &str| {
+        if !url.is_empty() && !trackers.iter().any(|t| t == url) {
+            trackers.push(url.to_string());
+        }
+    };
+
+    for (key, value) in parse_top_level(bytes)? {
+        match key {
+            b"announce" => add(read_whole_string(value)?),
+            b"announce-list" => {
+                for tier in read_list(value)? {
+                    for url in read_list(tier)? {
+                        add(read_whole_string(url)?);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(trackers)
+}
+
+/// The v2 parts of a torrent's info dictionary
+struct V2Info {
+    /// Hex SHA-256 of the info dictionary
+    info_hash: String,
+    name: String,
+    /// (path within the file tree, length, pieces root) per file
+    files: Vec<(String, u64, Vec<u8>)>,
+    root: String,
+}
+
+impl V2Info {
+    fn into_contents(self, trackers: Vec<String>) -> TorrentContents {
+        // A single file at the top of the tree is the whole torrent;
+        // otherwise the name is the root directory
+        let single_file = self.files.len() == 1 && !self.files[0].0.contains('/');
+        let files: Vec<TorrentFile> = self
+            .files
+            .into_iter()
+            .map(|(path, size, _)| TorrentFile {
+                name: if single_file { path } else { format!("{}/{}", self.name, path) },
+                size,
+                progress: 0.0,
+            })
+            .collect();
+
+        TorrentContents {
+            // Clients and trackers use the v2 hash truncated to 20 bytes
+            info_hash: self.info_hash[..40].to_string(),
+            name: self.name,
+            files,
+            v2_root: Some(self.root),
+            trackers,
+        }
+    }
+}
+
+/// Read the v2 file tree of a torrent, `None` for v1-only torrents
+fn read_v2_info(bytes: &[u8]) -> Result<Option<V2Info>> {
+    let info = parse_top_level(bytes)?
+        .into_iter()
+        .find(|(key, _)| *key == b"info")
+        .map(|(_, value)| value)
+        .context("Torrent has no info dictionary")?;
+
+    let fields = parse_top_level(info)?;
+    let field = |name: &[u8]| fields.iter().find(|(key, _)| *key == name).map(|(_, value)| *value);
+
+    let meta_version = field(b"meta version").map(read_int).transpose()?;
+    let (Some(2), Some(file_tree)) = (meta_version, field(b"file tree")) else {
+        return Ok(None);
+    };
+
+    let name = read_whole_string(field(b"name").context("Torrent has no name")?)?.to_string();
+    let mut files = Vec::new();
+    read_file_tree(file_tree, "", &mut files)?;
+    if files.is_empty() {
+        bail!("Torrent file tree is empty");
+    }
+
+    Ok(Some(V2Info {
+        info_hash: hex(&Sha256::digest(info)),
+        name,
+        root: v2_file_tree_root(&files),
+        files,
+    }))
+}
+
+/// Collect the files under a v2 file tree node, depth first
+fn read_file_tree(node: &[u8], prefix: &str, files: &mut Vec<(String, u64, Vec<u8>)>) -> Result<()> {
+    for (key, value) in parse_top_level(node)? {
+        let name = std::str::from_utf8(key).context("File name is not valid UTF-8")?;
+
+        // A file is a node with a single "" key holding its properties
+        if name.is_empty() {
+            let properties = parse_top_level(value)?;
+            let property = |name: &[u8]| properties.iter().find(|(key, _)| *key == name).map(|(_, value)| *value);
+            let length = read_int(property(b"length").context("File has no length")?)?;
+            // Empty files have no pieces root
+            let pieces_root = match property(b"pieces root") {
+                Some(root) => read_string(root, 0)?.0.to_vec(),
+                None => Vec::new(),
+            };
+            files.push((prefix.to_string(), length.max(0) as u64, pieces_root));
+            continue;
+        }
+
+        let path = if prefix.is_empty() { name.to_string() } else { format!("{}/{}", prefix, name) };
+        read_file_tree(value, &path, files)?;
+    }
+    Ok(())
+}
+
+/// Hex SHA-256 over a v2 file tree's paths, lengths and pieces roots
+///
+/// Pieces roots are merkle roots of the file data alone, so two v2 torrents
+/// with the same root hold identical files at identical paths whatever their
+/// name, piece length or tracker-specific fields.
+fn v2_file_tree_root(files: &[(String, u64, Vec<u8>)]) -> String {
+    let mut sorted: Vec<_> = files.iter().collect();
+    sorted.sort();
+
+    let mut hasher = Sha256::new();
+    for (path, length, pieces_root) in sorted {
+        hasher.update(path.as_bytes());
+        hasher.update(length.to_le_bytes());
+        hasher.update(pieces_root);
+    }
+    hex(&hasher.finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Build a torrent for another tracker from an existing torrent file
+///
+/// Copies every top-level key of `source_bytes` byte for byte, replaces
+/// `announce` with `announce_url` and drops `announce-list`. The `info`
+/// dictionary is untouched, so the result has the same info hash as the
+/// source. Only useful when the target site's torrent has that same hash.
+pub fn build_cross_seed_torrent(source_bytes: &[u8], announce_url: &str) -> Result<Vec<u8>> {
+    let entries = parse_top_level(source_bytes)?;
+    if !entries.iter().any(|(key, _)| *key == b"info") {
+        bail!("Torrent has no info dictionary");
+    }
+
+    let mut out = Vec::with_capacity(source_bytes.len() + announce_url.len());
+    out.push(b'd');
+
+    // Bencode keys must stay sorted, so `announce` goes before the first
+    // key that sorts after it
+    let mut announce_written = false;
+    for (key, value) in &entries {
+        if *key == b"announce" || *key == b"announce-list" {
+            continue;
+        }
+        if !announce_written && *key > b"announce".as_slice() {
+            write_announce(&mut out, announce_url);
+            announce_written = true;
+        }
+        write_bytes(&mut out, key);
+        out.extend_from_slice(value);
+    }
+    if !announce_written {
+        write_announce(&mut out, announce_url);
+    }
+
+    out.push(b'e');
+    Ok(out)
+}
+
+/// Point a torrent's trackers at different hosts
+///
+/// Each `(from, to)` pair replaces the host `from` (case-insensitive) with
+/// `to` in `announce` and every `announce-list` entry. Only those two keys
+/// are re-encoded; everything else, including the `info` dictionary, is
+/// copied byte for byte so the info hash stays the same. Fails instead of
+/// guessing when the announce keys are malformed or a new host is invalid.
+pub fn rewrite_announce_hosts(bytes: &[u8], rewrites: &[(String, String)]) -> Result<Vec<u8>> {
+    let entries = parse_top_level(bytes)?;
+    if !entries.iter().any(|(key, _)| *key == b"info") {
+        bail!("Torrent has no info dictionary");
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.push(b'd');
+    for (key, value) in &entries {
+        write_bytes(&mut out, key);
+        match *key {
+            b"announce" => {
+                let url = read_whole_string(value)?;
+                write_bytes(&mut out, rewrite_host(url, rewrites)?.as_bytes());
+            }
+            b"announce-list" => {
+                out.push(b'l');
+                for tier in read_list(value)? {
+                    out.push(b'l');
+                    for url in read_list(tier)? {
+                        let url = read_whole_string(url)?;
+                        write_bytes(&mut out, rewrite_host(url, rewrites)?.as_bytes());
+                    }
+                    out.push(b'e');
+                }
+                out.push(b'e');
+            }
+            _ => out.extend_from_slice(value),
+        }
+    }
+    out.push(b'e');
+
+    if info_hash(&out)? != info_hash(bytes)? {
+        bail!("Rewriting the announce URLs changed the info hash");
+    }
+    Ok(out)
+}
+
+fn rewrite_host(url: &str, rewrites: &[(String, String)]) -> Result<String> {
+    // Entries that are not URLs have no host to match, so they stay as they are
+    let Ok(mut parsed) = Url::parse(url) else {
+        return Ok(url.to_string());
+    };
+    let Some(host) = parsed.host_str() else {
+        return Ok(url.to_string());
+    };
+
+    match rewrites.iter().find(|(from, _)| from.eq_ignore_ascii_case(host)) {
+        Some((_, to)) => {
+            parsed
+                .set_host(Some(to))
+                .with_context(|| format!("Invalid replacement host: {}", to))?;
+            Ok(parsed.to_string())
+        }
+        None => Ok(url.to_string()),
+    }
+}
+
+fn write_announce(out: &mut Vec<u8>, announce_url: &str) {
+    write_bytes(out, b"announce");
+    write_bytes(out, announce_url.as_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+/// Split a bencoded dictionary into its keys and raw encoded values
+fn parse_top_level(bytes: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+    if bytes.first() != Some(&b'd') {
+        bail!("Torrent is not a bencoded dictionary");
+    }
+
+    let mut entries = Vec::new();
+    let mut pos = 1;
+    while bytes.get(pos) != Some(&b'e') {
+        let (key, after_key) = read_string(bytes, pos)?;
+        let after_value = skip_value(bytes, after_key)?;
+        entries.push((key, &bytes[after_key..after_value]));
+        pos = after_value;
+    }
+
+    if pos + 1 != bytes.len() {
+        bail!("Trailing data after torrent dictionary");
+    }
+    Ok(entries)
+}
+
+/// Read a byte string at `pos`, returning it and the position after it
+fn read_string(bytes: &[u8], pos: usize) -> Result<(&[u8], usize)> {
+    let colon = bytes[pos..]
+        .iter()
+        .position(|&b| b == b':')
+        .map(|i| pos + i)
+        .context("Unterminated string length")?;
+    let len: usize = std::str::from_utf8(&bytes[pos..colon])
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .context("Invalid string length")?;
+    let end = colon + 1 + len;
+    if end > bytes.len() {
+        bail!("String runs past end of torrent");
+    }
+    Ok((&bytes[colon + 1..end], end))
+}
+
+/// Decode an encoded value that must be exactly one UTF-8 string
+fn read_whole_string(value: &[u8]) -> Result<&str> {
+    if !value.first().is_some_and(u8::is_ascii_digit) {
+        bail!("Expected a string");
+    }
+    let (string, end) = read_string(value, 0)?;
+    if end != value.len() {
+        bail!("Trailing data after string");
+    }
+    std::str::from_utf8(string).context("String is not valid UTF-8")
+}
+
+/// Decode an encoded integer
+fn read_int(value: &[u8]) -> Result<i64> {
+    value
+        .strip_prefix(b"i")
+        .and_then(|rest| rest.strip_suffix(b"e"))
+        .and_then(|digits| std::str::from_utf8(digits).ok())
+        .and_then(|digits| digits.parse().ok())
+        .context("Expected an integer")
+}
+
+/// Split an encoded list into its raw encoded items
+fn read_list(value: &[u8]) -> Result<Vec<&[u8]>> {
+    if value.first() != Some(&b'l') {
+        bail!("Expected a list");
+    }
+
+    let mut items = Vec::new();
+    let mut pos = 1;
+    while value.get(pos) != Some(&b'e') {
+        if pos >= value.len() {
+            bail!("Unterminated list");
+        }
+        let end = skip_value(value, pos)?;
+        items.push(&value[pos..end]);
+        pos = end;
+    }
+    Ok(items)
+}
+
+/// Return the position just after the value starting at `pos`
+fn skip_value(bytes: &[u8], pos: usize) -> Result<usize> {
+    match bytes.get(pos) {
+        Some(b'i') => bytes[pos..]
+            .iter()
+            .position(|&b| b == b'e')
+            .map(|i| pos + i + 1)
+            .context("Unterminated integer"),
+        Some(b'l') | Some(b'd') => {
+            let is_dict = bytes[pos] == b'd';
+            let mut pos = pos + 1;
+            while bytes.get(pos) != Some(&b'e') {
+                if pos >= bytes.len() {
+                    bail!("Unterminated list or dictionary");
+                }
+                if is_dict {
+                    pos = read_string(bytes, pos)?.1;
+                }
+                pos = skip_value(bytes, pos)?;
+            }
+            Ok(pos + 1)
+        }
+        Some(b'0'..=b'9') => Ok(read_string(bytes, pos)?.1),
+        _ => bail!("Invalid bencode value at offset {}", pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lava_torrent::torrent::v1::Torrent;
+
+    fn source_torrent() -> Vec<u8> {
+        let mut bytes = b"d8:announce30:https://a.example/announce?p=113:announce-listll30:https://a.example/announce?p=1ee7:comment4:test4:infod6:lengthi1000e4:name8:file.mkv12:piece lengthi16384e6:pieces20:".to_vec();
+        bytes.extend([0xffu8; 20]);
+        bytes.extend(b"7:privatei1eee");
+        bytes
+    }
+
+    #[test]
+    fn test_cross_seed_keeps_info_hash() {
+        let source = source_torrent();
+        let built = build_cross_seed_torrent(&source, "https://b.example/announce.php?passkey=xyz").unwrap();
+
+        let before = Torrent::read_from_bytes(&source).unwrap();
+        let after = Torrent::read_from_bytes(&built).unwrap();
+        assert_eq!(before.info_hash(), after.info_hash());
+        assert_eq!(after.announce.as_deref(), Some("https://b.example/announce.php?passkey=xyz"));
+        assert!(after.announce_list.is_none());
+    }
+
+    #[test]
+    fn test_info_hash() {
+        let source = source_torrent();
+        let hash = info_hash(&source).unwrap();
+        assert_eq!(hash.len(), 40);
+        assert_eq!(hash, Torrent::read_from_bytes(&source).unwrap().info_hash());
+        assert!(info_hash(b"not a torrent").is_err());
+    }
+
+    #[test]
+    fn test_read_contents() {
+        let single = read_contents(&source_torrent()).unwrap();
+        assert_eq!(single.name, "file.mkv");
+        assert_eq!(single.trackers, vec!["https://a.example/announce?p=1"]);
+        assert_eq!(single.files[0].size, 1000);
+        assert_eq!(single.files.len(), 1);
+        assert_eq!(single.files[0].name, "file.mkv");
+
+        let mut bytes = b"d4:infod5:filesld6:lengthi700e4:pathl3:sub5:a.mkveed6:lengthi300e4:pathl5:b.nfoeee4:name6:Movie112:piece lengthi16384e6:pieces20:".to_vec();
+        bytes.extend([0xffu8; 20]);
+        bytes.extend(b"ee");
+        let multi = read_contents(&bytes).unwrap();
+        assert_eq!(multi.files.iter().map(|f| f.size).sum::<u64>(), 1000);
+        let names: Vec<_> = multi.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["Movie1/sub/a.mkv", "Movie1/b.nfo"]);
+        assert_eq!(multi.info_hash, info_hash(&bytes).unwrap());
+    }
+
+    /// A v2-only torrent with files `Movie/sub/a.mkv` and `Movie/b.nfo`
+    fn v2_torrent(name: &str, piece_length: u32, a_root: u8) -> Vec<u8> {
+        fn file(length: u64, root: u8) -> Vec<u8> {
+            let mut bytes = format!("d0:d6:lengthi{}e11:pieces root32:", length).into_bytes();
+            bytes.extend([root; 32]);
+            bytes.extend(b"ee");
+            bytes
+        }
+
+        let mut bytes = b"d4:infod9:file treed5:b.nfo".to_vec();
+        bytes.extend(file(300, 0x22));
+        bytes.extend(b"3:subd5:a.mkv");
+        bytes.extend(file(700, a_root));
+        bytes.extend(b"ee12:meta versioni2e");
+        bytes.extend(format!("4:name{}:{}12:piece lengthi{}eee", name.len(), name, piece_length).into_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_read_v2_contents() {
+        let contents = read_contents(&v2_torrent("Movie", 16384, 0x11)).unwrap();
+        assert_eq!(contents.name, "Movie");
+        assert_eq!(contents.files.iter().map(|f| f.size).sum::<u64>(), 1000);
+        assert_eq!(contents.info_hash.len(), 40);
+        let names: Vec<_> = contents.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["Movie/b.nfo", "Movie/sub/a.mkv"]);
+        let root = contents.v2_root.unwrap();
+
+        // Renamed and re-pieced, same files: different info hash, same root
+        let renamed = read_contents(&v2_torrent("Movie.2020", 65536, 0x11)).unwrap();
+        assert_ne!(renamed.info_hash, contents.info_hash);
+        assert_eq!(renamed.v2_root.as_deref(), Some(root.as_str()));
+
+        // Different file data changes the root
+        let changed = read_contents(&v2_torrent("Movie", 16384, 0x33)).unwrap();
+        assert_ne!(changed.v2_root.as_deref(), Some(root.as_str()));
+
+        // v1 torrents have no v2 root
+        assert!(read_contents(&source_torrent()).unwrap().v2_root.is_none());
+
+        assert_eq!(info_hash(&v2_torrent("Movie", 16384, 0x11)).unwrap(), contents.info_hash);
+    }
+
+    #[test]
+    fn test_rewrite_announce_hosts() {
+        let source = source_torrent();
+        let rewrites = vec![("A.example".to_string(), "tracker.b.example".to_string())];
+        let rewritten = rewrite_announce_hosts(&source, &rewrites).unwrap();
+
+        let before = Torrent::read_from_bytes(&source).unwrap();
+        let after = Torrent::read_from_bytes(&rewritten).unwrap();
+        assert_eq!(before.info_hash(), after.info_hash());
+        assert_eq!(after.announce.as_deref(), Some("https://tracker.b.example/announce?p=1"));
+        assert_eq!(
+            after.announce_list,
+            Some(vec![vec!["https://tracker.b.example/announce?p=1".to_string()]])
+        );
+
+        // Unrelated hosts are left alone
+        let unchanged = rewrite_announce_hosts(&source, &[("c.example".to_string(), "d.example".to_string())]).unwrap();
+        assert_eq!(unchanged, source);
+
+        let bad_host = vec![("a.example".to_string(), "not a host".to_string())];
+        assert!(rewrite_announce_hosts(&source, &bad_host).is_err());
+    }
+
+    #[test]
+    fn test_cross_seed_rejects_garbage() {
+        assert!(build_cross_seed_torrent(b"d8:announce", "https://b.example").is_err());
+        assert!(build_cross_seed_torrent(b"d7:comment4:teste", "https://b.example").is_err());
+    }
+}
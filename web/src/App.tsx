@@ -1,6 +1,7 @@
 import { Component, lazy } from 'solid-js';
 import { Router, Route } from '@solidjs/router';
 import Layout from './components/Layout';
+import { BASE_PATH } from './base';
 
 // Lazy load pages
 const Dashboard = lazy(() => import('./pages/Dashboard'));
@@ -12,7 +13,7 @@ const History = lazy(() => import('./pages/History'));
 
 const App: Component = () => {
   return (
-    <Router root={Layout}>
+    <Router root={Layout} base={BASE_PATH}>
       <Route path="/" component={Dashboard} />
       <Route path="/clients" component={Clients} />
       <Route path="/sites" component={Sites} />
@@ -9,6 +9,10 @@ export interface Client {
   username?: string;
   use_https: boolean;
   enabled: boolean;
+  category_label_prefix?: string;
+  login_timeout_secs?: number;
+  login_retries?: number;
+  download_root?: string;
 }
 
 export interface CreateClientRequest {
@@ -19,6 +23,10 @@ export interface CreateClientRequest {
   username?: string;
   password?: string;
   use_https: boolean;
+  category_label_prefix?: string;
+  login_timeout_secs?: number;
+  login_retries?: number;
+  download_root?: string;
 }
 
 export const fetchClients = () => api.get<Client[]>('/clients');
@@ -29,5 +37,17 @@ export const createClient = (data: CreateClientRequest) =>
 export const testClient = (id: string) =>
   api.post<{ success: boolean; message: string }>(`/clients/${id}/test`);
 
+export interface DetectResult {
+  configured: Client['client_type'];
+  detected: Client['client_type'];
+  updated: boolean;
+}
+
+export const detectClientType = (id: string, update = false) =>
+  api.post<DetectResult>(`/clients/${id}/detect${update ? '?update=true' : ''}`);
+
 export const deleteClient = (id: string) =>
   api.delete<{ deleted: boolean }>(`/clients/${id}`);
+
+export const setClientEnabled = (id: string, enabled: boolean) =>
+  api.patch<{ id: string; enabled: boolean }>(`/clients/${id}/enabled`, { enabled });
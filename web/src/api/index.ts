@@ -3,22 +3,164 @@ import { api } from './client';
 export interface IndexStats {
   total_entries: number;
   sites: Array<{ site_id: string; count: number }>;
+  matcher_version: number;
 }
 
 export interface ImportResult {
   total: number;
   imported: number;
+  updated: number;
   skipped: number;
   unrecognized: number;
+  trackerless: number;
+  by_site: Record<string, number>;
+  too_old: number;
+  empty: number;
+  size_only: number;
+  invalid: number;
 }
 
 export const fetchIndexStats = () => api.get<IndexStats>('/index/stats');
 
-export const importFromClient = (clientId: string) =>
-  api.post<ImportResult>(`/index/import/${clientId}`);
+export interface DuplicateEntry {
+  info_hash: string;
+  site_id: string;
+  torrent_id: string | null;
+  name: string | null;
+  save_path: string | null;
+}
+
+export interface DuplicateSet {
+  total_size: number;
+  wasted_size: number;
+  confidence: number;
+  copies: number;
+  entries: DuplicateEntry[];
+}
+
+export const fetchIndexDuplicates = (siteId?: string) =>
+  api.get<DuplicateSet[]>(`/index/duplicates${siteId ? `?site_id=${encodeURIComponent(siteId)}` : ''}`);
+
+export interface PackEntry {
+  info_hash: string;
+  site_id: string;
+  torrent_id: string | null;
+  name: string | null;
+  total_size: number;
+  file_count: number;
+  matched_files: number;
+}
+
+export interface PackSuggestion {
+  info_hash: string;
+  site_id: string;
+  name: string | null;
+  total_size: number;
+  packs: PackEntry[];
+}
+
+export const fetchPackSuggestions = (siteId?: string) =>
+  api.get<PackSuggestion[]>(`/index/suggestions${siteId ? `?site_id=${encodeURIComponent(siteId)}` : ''}`);
+
+export interface ImportOptions {
+  added_after?: string;
+  exclude_undated?: boolean;
+  category?: string;
+  tag?: string;
+}
+
+export const importFromClient = (clientId: string, options: ImportOptions = {}) => {
+  const params = new URLSearchParams();
+  if (options.added_after) params.set('added_after', options.added_after);
+  if (options.exclude_undated) params.set('exclude_undated', 'true');
+  if (options.category !== undefined) params.set('category', options.category);
+  if (options.tag) params.set('tag', options.tag);
+  const query = params.toString();
+  return api.post<ImportResult>(`/index/import/${clientId}${query ? `?${query}` : ''}`);
+};
+
+export const importFromDirectory = (path: string) =>
+  api.post<ImportResult>('/index/import-dir', { path });
+
+export const importQbExport = (data: unknown, clientId?: string) =>
+  api.post<ImportResult>(
+    clientId ? `/index/import-qb-export?client_id=${encodeURIComponent(clientId)}` : '/index/import-qb-export',
+    data,
+  );
+
+export interface ClientImportOutcome {
+  result: ImportResult | null;
+  error: string | null;
+}
+
+export const importFromAllClients = () =>
+  api.post<Record<string, ClientImportOutcome>>('/index/import-all');
 
 export const clearIndex = () =>
   api.delete<{ cleared: boolean }>('/index');
 
 export const clearSiteIndex = (siteId: string) =>
-  api.delete<{ cleared: boolean; site_id: string }>(`/index/${siteId}`);
+  api.delete<{ cleared: boolean; site_id: string; entries: number }>(`/index/${siteId}`);
+
+export const restoreSiteIndex = (siteId: string) =>
+  api.post<{ site_id: string; restored: number }>(`/index/${siteId}/restore`);
+
+export interface PruneOptions {
+  /** Days */
+  older_than?: number;
+  check_clients?: boolean;
+}
+
+export interface PruneReport {
+  older: number;
+  missing: number;
+  dry_run: boolean;
+  failed_clients: Record<string, string>;
+}
+
+const pruneQuery = (options: PruneOptions) => {
+  const params = new URLSearchParams();
+  if (options.older_than !== undefined) params.set('older_than', options.older_than.toString());
+  if (options.check_clients) params.set('check_clients', 'true');
+  return params.toString();
+};
+
+export const previewPruneIndex = (options: PruneOptions) =>
+  api.get<PruneReport>(`/index/prune?${pruneQuery(options)}`);
+
+export const pruneIndex = (options: PruneOptions) =>
+  api.post<PruneReport>(`/index/prune?${pruneQuery(options)}`);
+
+export interface ContentFingerprint {
+  total_size: number;
+  file_count: number;
+  largest_file_size: number;
+  files_hash: string | null;
+  profile_hash: string | null;
+  media_type: string | null;
+  v2_root: string | null;
+  size_only: boolean;
+  padding_excluded: boolean;
+}
+
+export interface AnalyzedMatch {
+  site_id: string;
+  torrent_id: string | null;
+  info_hash: string;
+  name: string | null;
+  confidence: number;
+  reason: string;
+}
+
+export interface FingerprintAnalysis {
+  info_hash: string;
+  name: string;
+  fingerprint: ContentFingerprint;
+  matches: AnalyzedMatch[];
+}
+
+export const analyzeTorrentFile = (file: File) => {
+  const form = new FormData();
+  form.append('file', file);
+  return api.upload<FingerprintAnalysis>('/fingerprint/analyze', form);
+};
@@ -4,30 +4,78 @@ export interface ReseedMatch {
   source_hash: string;
   source_name: string;
   source_site?: string;
+  source_client_id: string;
   target_site: string;
   target_torrent_id?: string;
   target_hash: string;
   save_path: string;
   size: number;
   confidence: number;
+  reason: string;
+  outcome?: PredictedOutcome;
+}
+
+export type PredictedOutcome =
+  | 'would_add'
+  | 'would_skip_low_confidence'
+  | 'would_skip_duplicate'
+  | 'would_skip_daily_limit'
+  | 'would_fail_no_site'
+  | 'would_fail_no_passkey'
+  | 'would_fail_no_torrent_id';
+
+export interface UnmatchedTorrent {
+  hash: string;
+  name: string;
+  size: number;
+  source_client_id: string;
 }
 
 export interface PreviewResult {
   matches: ReseedMatch[];
   total_size: number;
+  unmatched: UnmatchedTorrent[];
+  /** Target sites left out for lack of a passkey or cookie */
+  unconfigured_sites: string[];
+  truncated: number;
+  completed: number;
 }
 
 export interface PreviewRequest {
-  source_client_id: string;
+  source_client_id?: string;
+  source_client_ids?: string[];
   target_site_ids: string[];
+  source_hashes?: string[];
+  best_target_only?: boolean;
+  include_unconfigured?: boolean;
+  max_matches_per_source?: number;
+  hide_completed?: boolean;
+  annotate?: boolean;
+  target_client_id?: string;
 }
 
 export interface ExecuteRequest {
-  source_client_id: string;
+  source_client_id?: string;
+  source_client_ids?: string[];
   target_client_id: string;
   target_site_ids: string[];
+  source_hashes?: string[];
   add_paused?: boolean;
   skip_checking?: boolean;
+  add_top_of_queue?: boolean;
+  category?: string;
+  use_category_save_path?: boolean;
+  min_confidence?: number;
+  save_path_override?: string;
+  translate_save_path?: boolean;
+  upload_limit?: number;
+  download_limit?: number;
+  best_target_only?: boolean;
+  merge_source_trackers?: boolean;
+  verify_after_add?: boolean;
+  /** [from, to] tracker host pairs rewritten in downloaded torrents */
+  announce_rewrite?: [string, string][];
+  include_unconfigured?: boolean;
 }
 
 export interface ExecuteResult {
@@ -35,6 +83,18 @@ export interface ExecuteResult {
   success: number;
   failed: number;
   skipped: number;
+  queued: number;
+  unconfigured_sites: string[];
+}
+
+export interface Approval extends ReseedMatch {
+  id: number;
+  task_id?: string;
+  target_client: string;
+  add_paused: boolean;
+  status: 'pending' | 'approved' | 'rejected';
+  created_at: string;
+  resolved_at?: string;
 }
 
 export interface HistoryEntry {
@@ -42,8 +102,41 @@ export interface HistoryEntry {
   info_hash: string;
   source_site?: string;
   target_site: string;
-  status: 'success' | 'failed' | 'skipped';
+  status: 'success' | 'failed' | 'skipped' | 'site_disabled' | 'data_not_found' | 'daily_limit_reached' | 'verified_100' | 'verify_failed' | 'downloaded';
   message?: string;
+  failure_reason?: FailureReason;
+  created_at: string;
+}
+
+export type FailureReason =
+  | 'auth_failed'
+  | 'download_failed'
+  | 'rate_limited'
+  | 'mismatch'
+  | 'no_passkey'
+  | 'no_torrent_id'
+  | 'add_failed'
+  | 'verify_timeout'
+  | 'verify_error'
+  | 'site_config_missing';
+
+export interface FailureStat {
+  target_site: string;
+  failure_reason: FailureReason;
+  count: number;
+}
+
+export interface RunEntry {
+  id: number;
+  task_id?: string;
+  source_clients: string[];
+  target_client: string;
+  target_sites: string[];
+  total: number;
+  success: number;
+  failed: number;
+  skipped: number;
+  duration_ms: number;
   created_at: string;
 }
 
@@ -56,8 +149,12 @@ export interface HistoryQuery {
 export const previewReseed = (data: PreviewRequest) =>
   api.post<PreviewResult>('/reseed/preview', data);
 
-export const executeReseed = (data: ExecuteRequest) =>
-  api.post<ExecuteResult>('/reseed/execute', data);
+export const executeReseed = (data: ExecuteRequest, idempotencyKey?: string) =>
+  api.post<ExecuteResult>(
+    '/reseed/execute',
+    data,
+    idempotencyKey ? { 'Idempotency-Key': idempotencyKey } : undefined,
+  );
 
 export const fetchHistory = (query: HistoryQuery = {}) => {
   const params = new URLSearchParams();
@@ -68,3 +165,24 @@ export const fetchHistory = (query: HistoryQuery = {}) => {
   const queryString = params.toString();
   return api.get<HistoryEntry[]>(`/reseed/history${queryString ? `?${queryString}` : ''}`);
 };
+
+export const fetchFailureStats = (days?: number) =>
+  api.get<FailureStat[]>(`/reseed/failure-stats${days ? `?days=${days}` : ''}`);
+
+export const fetchRuns = (query: { limit?: number; offset?: number } = {}) => {
+  const params = new URLSearchParams();
+  if (query.limit) params.set('limit', query.limit.toString());
+  if (query.offset) params.set('offset', query.offset.toString());
+
+  const queryString = params.toString();
+  return api.get<RunEntry[]>(`/reseed/runs${queryString ? `?${queryString}` : ''}`);
+};
+
+export const fetchPendingApprovals = () =>
+  api.get<Approval[]>('/reseed/pending');
+
+export const approveMatch = (id: number) =>
+  api.post<ExecuteResult>(`/reseed/pending/${id}/approve`);
+
+export const rejectMatch = (id: number) =>
+  api.post<{ rejected: boolean }>(`/reseed/pending/${id}/reject`);
@@ -6,8 +6,17 @@ export interface Site {
   base_url: string;
   template_type: string;
   has_passkey: boolean;
+  has_rsskey: boolean;
   has_cookie: boolean;
+  has_login: boolean;
   enabled: boolean;
+  extra_headers: Record<string, string>;
+  daily_download_limit: number | null;
+  announce_url: string | null;
+  default_category: string | null;
+  default_save_path: string | null;
+  /** Password masked */
+  proxy: string | null;
 }
 
 export interface AvailableSite {
@@ -23,7 +32,16 @@ export interface CreateSiteRequest {
   name: string;
   base_url: string;
   passkey?: string;
+  rsskey?: string;
   cookie?: string;
+  extra_headers?: Record<string, string>;
+  daily_download_limit?: number;
+  announce_url?: string;
+  default_category?: string;
+  default_save_path?: string;
+  proxy?: string;
+  site_username?: string;
+  site_password?: string;
 }
 
 export const fetchSites = () => api.get<Site[]>('/sites');
@@ -33,5 +51,19 @@ export const fetchAvailableSites = () => api.get<AvailableSite[]>('/sites/availa
 export const createSite = (data: CreateSiteRequest) =>
   api.post<Site>('/sites', data);
 
+export const testAllSites = () =>
+  api.post<Record<string, { success: boolean; verified: boolean; message: string }>>('/sites/test-all');
+
+export const bulkEnableSites = (siteIds: string[], enabled: boolean) =>
+  api.post<{ changed: number; enabled: boolean }>('/sites/bulk-enable', { site_ids: siteIds, enabled });
+
+export interface SyncBuiltinsResult {
+  synced: string[];
+  domains_added: number;
+}
+
+export const syncBuiltinSites = () =>
+  api.post<SyncBuiltinsResult>('/sites/sync-builtins');
+
 export const deleteSite = (id: string) =>
   api.delete<{ deleted: boolean }>(`/sites/${id}`);
@@ -1,4 +1,6 @@
+import { BASE_PATH } from '../base';
 import { api } from './client';
+import type { ExecuteResult } from './reseed';
 
 export interface Stats {
   index: {
@@ -14,3 +16,43 @@ export interface Stats {
 }
 
 export const fetchStats = () => api.get<Stats>('/stats');
+
+export interface ComponentStatus {
+  id: string;
+  name: string;
+  enabled: boolean;
+  ok: boolean;
+  last_ok_at: string | null;
+  last_error: string | null;
+  last_error_at: string | null;
+}
+
+export interface Status {
+  ok: boolean;
+  clients: ComponentStatus[];
+  sites: ComponentStatus[];
+}
+
+export const fetchStatus = () => api.get<Status>('/status');
+
+export type LiveEvent =
+  | { type: 'snapshot' | 'stats'; stats: Stats }
+  | { type: 'index_changed'; matcher_version: number }
+  | { type: 'reseed_started'; task_id: string | null; total: number }
+  | {
+      type: 'reseed_progress';
+      task_id: string | null;
+      site: string;
+      name: string;
+      outcome: string;
+      processed: number;
+      total: number;
+    }
+  | { type: 'reseed_finished'; task_id: string | null; result: ExecuteResult };
+
+export const connectLiveEvents = (onEvent: (event: LiveEvent) => void) => {
+  const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+  const socket = new WebSocket(`${protocol}//${window.location.host}${BASE_PATH}/api/ws`);
+  socket.onmessage = (message) => onEvent(JSON.parse(message.data));
+  return socket;
+};
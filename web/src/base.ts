@@ -0,0 +1,8 @@
+declare global {
+  interface Window {
+    __GRAFT_BASE__?: string;
+  }
+}
+
+// Path prefix when served behind a reverse proxy, injected into index.html
+export const BASE_PATH = window.__GRAFT_BASE__ ?? '';
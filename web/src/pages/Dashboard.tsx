@@ -1,8 +1,20 @@
 import { Component, createResource, For } from 'solid-js';
-import { fetchStats } from '../api/stats';
+import { fetchStats, fetchStatus, type ComponentStatus } from '../api/stats';
+
+const StatusRow: Component<{ item: ComponentStatus }> = (props) => (
+  <tr>
+    <td>
+      <span class={`badge badge-xs mr-2 ${!props.item.enabled ? 'badge-ghost' : props.item.ok ? 'badge-success' : 'badge-error'}`}></span>
+      {props.item.name}
+    </td>
+    <td>{props.item.last_ok_at || 'Never'}</td>
+    <td class="text-error">{props.item.ok ? '' : props.item.last_error}</td>
+  </tr>
+);
 
 const Dashboard: Component = () => {
   const [stats] = createResource(fetchStats);
+  const [status] = createResource(fetchStatus);
 
   return (
     <div>
@@ -55,6 +67,34 @@ const Dashboard: Component = () => {
         </div>
       </div>
 
+      {/* Client and Site Health */}
+      <div class="card bg-base-100 shadow-xl mb-8">
+        <div class="card-body">
+          <h2 class="card-title">
+            Health
+            <span class={`badge ${(status()?.ok ?? true) ? 'badge-success' : 'badge-error'}`}>
+              {(status()?.ok ?? true) ? 'All OK' : 'Issues'}
+            </span>
+          </h2>
+          <div class="overflow-x-auto">
+            <table class="table">
+              <thead>
+                <tr>
+                  <th>Client / Site</th>
+                  <th>Last Success</th>
+                  <th>Last Error</th>
+                </tr>
+              </thead>
+              <tbody>
+                <For each={[...(status()?.clients || []), ...(status()?.sites || [])]}>
+                  {(item) => <StatusRow item={item} />}
+                </For>
+              </tbody>
+            </table>
+          </div>
+        </div>
+      </div>
+
       {/* Index by Site */}
       <div class="card bg-base-100 shadow-xl">
         <div class="card-body">
@@ -1,5 +1,5 @@
 import { Component, createSignal, createResource, For, Show } from 'solid-js';
-import { fetchIndexStats, importFromClient, clearIndex, clearSiteIndex } from '../api/index';
+import { fetchIndexStats, importFromClient, clearIndex, clearSiteIndex, restoreSiteIndex } from '../api/index';
 import { fetchClients } from '../api/clients';
 
 const Index: Component = () => {
@@ -7,6 +7,7 @@ const Index: Component = () => {
   const [clients] = createResource(fetchClients);
   const [importing, setImporting] = createSignal<string | null>(null);
   const [importResult, setImportResult] = createSignal<any>(null);
+  const [clearedSite, setClearedSite] = createSignal<string | null>(null);
 
   const handleImport = async (clientId: string) => {
     setImporting(clientId);
@@ -31,10 +32,17 @@ const Index: Component = () => {
   const handleClearSite = async (siteId: string) => {
     if (confirm(`Clear all index entries for ${siteId}?`)) {
       await clearSiteIndex(siteId);
+      setClearedSite(siteId);
       refetchStats();
     }
   };
 
+  const handleRestoreSite = async (siteId: string) => {
+    await restoreSiteIndex(siteId);
+    setClearedSite(null);
+    refetchStats();
+  };
+
   return (
     <div>
       <h1 class="page-title">Index Management</h1>
@@ -76,7 +84,7 @@ const Index: Component = () => {
               >
                 <span>
                   Imported {importResult().imported} torrents
-                  (skipped {importResult().skipped}, unrecognized {importResult().unrecognized})
+                  (updated {importResult().updated}, skipped {importResult().skipped}, unrecognized {importResult().unrecognized})
                 </span>
               </Show>
             </div>
@@ -94,6 +102,17 @@ const Index: Component = () => {
             </button>
           </div>
 
+          <Show when={clearedSite()}>
+            {(siteId) => (
+              <div class="alert mb-4">
+                <span>Cleared index entries for {siteId()}</span>
+                <button class="btn btn-sm" onClick={() => handleRestoreSite(siteId())}>
+                  Undo
+                </button>
+              </div>
+            )}
+          </Show>
+
           <div class="stat bg-base-200 rounded-box mb-4">
             <div class="stat-title">Total Indexed</div>
             <div class="stat-value">{stats()?.total_entries || 0}</div>
@@ -1,7 +1,14 @@
 import { Component, createSignal, createResource, For, Show } from 'solid-js';
 import { fetchClients } from '../api/clients';
 import { fetchSites } from '../api/sites';
-import { previewReseed, executeReseed, type PreviewResult } from '../api/reseed';
+import {
+  previewReseed,
+  executeReseed,
+  fetchPendingApprovals,
+  approveMatch,
+  rejectMatch,
+  type PreviewResult,
+} from '../api/reseed';
 
 const Reseed: Component = () => {
   const [clients] = createResource(fetchClients);
@@ -17,6 +24,11 @@ const Reseed: Component = () => {
   const [loading, setLoading] = createSignal(false);
   const [executing, setExecuting] = createSignal(false);
   const [result, setResult] = createSignal<any>(null);
+  // One key per preview, so a double-submitted execute runs only once
+  let executeKey = crypto.randomUUID();
+
+  const [pending, { refetch: refetchPending }] = createResource(fetchPendingApprovals);
+  const [resolving, setResolving] = createSignal<number | null>(null);
 
   const toggleSite = (siteId: string) => {
     const current = selectedSites();
@@ -33,6 +45,7 @@ const Reseed: Component = () => {
     setLoading(true);
     setPreview(null);
     setResult(null);
+    executeKey = crypto.randomUUID();
 
     try {
       const result = await previewReseed({
@@ -60,7 +73,7 @@ const Reseed: Component = () => {
         target_site_ids: selectedSites(),
         add_paused: addPaused(),
         skip_checking: skipChecking(),
-      });
+      }, executeKey);
       setResult(execResult);
       setPreview(null);
     } catch (e) {
@@ -70,6 +83,23 @@ const Reseed: Component = () => {
     setExecuting(false);
   };
 
+  const handleResolve = async (id: number, approve: boolean) => {
+    setResolving(id);
+
+    try {
+      if (approve) {
+        await approveMatch(id);
+      } else {
+        await rejectMatch(id);
+      }
+    } catch (e) {
+      console.error(e);
+    }
+
+    setResolving(null);
+    refetchPending();
+  };
+
   const formatSize = (bytes: number) => {
     const units = ['B', 'KB', 'MB', 'GB', 'TB'];
     let size = bytes;
@@ -251,6 +281,65 @@ const Reseed: Component = () => {
           </div>
         </div>
       </div>
+
+      <Show when={pending()?.length}>
+        <div class="card bg-base-100 shadow-xl mt-6">
+          <div class="card-body">
+            <h2 class="card-title">Pending Approval</h2>
+            <p class="text-sm text-base-content/70">
+              Low-confidence matches from scheduled runs, waiting for a decision
+            </p>
+
+            <div class="overflow-x-auto">
+              <table class="table table-xs">
+                <thead>
+                  <tr>
+                    <th>Name</th>
+                    <th>Target</th>
+                    <th>Confidence</th>
+                    <th>Reason</th>
+                    <th></th>
+                  </tr>
+                </thead>
+                <tbody>
+                  <For each={pending()}>
+                    {(approval) => (
+                      <tr>
+                        <td class="max-w-xs truncate" title={approval.source_name}>
+                          {approval.source_name}
+                        </td>
+                        <td>{approval.target_site}</td>
+                        <td>
+                          <span class="badge badge-sm badge-warning">
+                            {(approval.confidence * 100).toFixed(0)}%
+                          </span>
+                        </td>
+                        <td class="text-base-content/70">{approval.reason}</td>
+                        <td class="flex gap-1 justify-end">
+                          <button
+                            class="btn btn-xs btn-success"
+                            disabled={resolving() !== null}
+                            onClick={() => handleResolve(approval.id, true)}
+                          >
+                            Approve
+                          </button>
+                          <button
+                            class="btn btn-xs btn-ghost"
+                            disabled={resolving() !== null}
+                            onClick={() => handleResolve(approval.id, false)}
+                          >
+                            Reject
+                          </button>
+                        </td>
+                      </tr>
+                    )}
+                  </For>
+                </tbody>
+              </table>
+            </div>
+          </div>
+        </div>
+      </Show>
     </div>
   );
 };
@@ -10,7 +10,10 @@ const Sites: Component = () => {
     id: '',
     name: '',
     passkey: '',
+    rsskey: '',
     cookie: '',
+    site_username: '',
+    site_password: '',
   });
 
   const [selectedTemplate, setSelectedTemplate] = createSignal<AvailableSite | null>(null);
@@ -21,7 +24,10 @@ const Sites: Component = () => {
       id: template.id,
       name: template.name,
       passkey: '',
+      rsskey: '',
       cookie: '',
+      site_username: '',
+      site_password: '',
     });
   };
 
@@ -35,12 +41,15 @@ const Sites: Component = () => {
       name: form().name,
       base_url: template.base_url,
       passkey: form().passkey || undefined,
+      rsskey: form().rsskey || undefined,
       cookie: form().cookie || undefined,
+      site_username: form().site_username || undefined,
+      site_password: form().site_password || undefined,
     });
 
     setShowModal(false);
     setSelectedTemplate(null);
-    setForm({ id: '', name: '', passkey: '', cookie: '' });
+    setForm({ id: '', name: '', passkey: '', rsskey: '', cookie: '', site_username: '', site_password: '' });
     refetch();
   };
 
@@ -162,6 +171,21 @@ const Sites: Component = () => {
                   </label>
                 </div>
 
+                <Show when={selectedTemplate()?.template_type === 'unit3d'}>
+                  <div class="form-control mb-4">
+                    <label class="label">
+                      <span class="label-text">RSS Key (optional)</span>
+                    </label>
+                    <input
+                      type="text"
+                      class="input input-bordered"
+                      value={form().rsskey}
+                      onInput={(e) => setForm({ ...form(), rsskey: e.currentTarget.value })}
+                      placeholder="Used in download links; defaults to the passkey"
+                    />
+                  </div>
+                </Show>
+
                 <div class="form-control mb-4">
                   <label class="label">
                     <span class="label-text">Cookie (optional)</span>
@@ -175,6 +199,35 @@ const Sites: Component = () => {
                   />
                 </div>
 
+                <Show when={selectedTemplate()?.template_type === 'nexusphp'}>
+                  <div class="form-control mb-4">
+                    <label class="label">
+                      <span class="label-text">Login (optional)</span>
+                    </label>
+                    <div class="flex gap-2">
+                      <input
+                        type="text"
+                        class="input input-bordered flex-1"
+                        value={form().site_username}
+                        onInput={(e) => setForm({ ...form(), site_username: e.currentTarget.value })}
+                        placeholder="Username"
+                        autocomplete="off"
+                      />
+                      <input
+                        type="password"
+                        class="input input-bordered flex-1"
+                        value={form().site_password}
+                        onInput={(e) => setForm({ ...form(), site_password: e.currentTarget.value })}
+                        placeholder="Password"
+                        autocomplete="new-password"
+                      />
+                    </div>
+                    <label class="label">
+                      <span class="label-text-alt">Used to get a fresh cookie when the current one expires</span>
+                    </label>
+                  </div>
+                </Show>
+
                 <div class="modal-action">
                   <button type="button" class="btn" onClick={() => {
                     setSelectedTemplate(null);